@@ -1,31 +1,57 @@
 use crate::states::menu_state::MenuState;
+use crate::states::play_state::bot::BotDifficulty;
+use crate::states::play_state::game_mode::GameMode;
+use crate::states::play_state::replay::Replay;
 use crate::states::play_state::PlayState;
 use crate::states::shared::option::{Option, OptionInputCommand};
 use crate::states::{Assets, State, Transition};
-use crate::util::play_click_sfx;
+use crate::util::{format_time, play_click_sfx};
 use ggez::event::{Button, Keycode, Mod};
 use ggez::graphics::Point2;
 use ggez::{graphics, Context, GameResult};
 use std::time::Duration;
 
-/// Describes whether to render `GameEndState` under either "Player Wins" or
-/// "Player Loses" conditions.
+/// Describes whether to render `GameEndState` under either "Player Wins",
+/// "Player Loses" or "Time's Up" conditions.
 pub enum GameEndMode {
     Win,
     Lose,
+    TimeUp,
+}
+
+/// The results of a finished game, shown by `GameEndState`.
+pub struct GameSummary {
+    pub mode: GameMode,
+    pub score: u32,
+    pub lines: u32,
+    pub level: u32,
+    /// How long the game lasted, in seconds.
+    pub duration: f64,
+    /// The number of pieces locked into the well.
+    pub pieces: u32,
+    pub finesse_faults: u32,
+    /// The seed the game's pieces were dealt with.
+    pub seed: u64,
+    /// The bot that played the game, or `None` if a person played it.
+    pub bot: std::option::Option<BotDifficulty>,
 }
 
 pub struct GameEndState {
+    replay: Replay,
+    // the bot that played the game, which plays again if asked to
+    bot: std::option::Option<BotDifficulty>,
+    is_replay_saved: bool,
     request_replay: bool,
+    request_save_replay: bool,
     request_menu: bool,
     request_quit: bool,
     options: Vec<Option>,
     current_selection: usize,
 
     game_end_text: graphics::Text,
-    final_score_text: graphics::Text,
-    final_line_text: graphics::Text,
-    final_level_text: graphics::Text,
+    // the results shown for the game's mode, one per line
+    stat_texts: Vec<graphics::Text>,
+    seed_text: graphics::Text,
 }
 
 impl GameEndState {
@@ -35,22 +61,24 @@ impl GameEndState {
         ctx: &mut Context,
         assets: &Assets,
         mode: &GameEndMode,
-        final_score_value: u32,
-        final_cleared: u32,
-        final_level: u32,
+        summary: &GameSummary,
+        replay: Replay,
     ) -> GameResult<Self> {
         let game_over: graphics::Text;
         game_over = match mode {
             GameEndMode::Lose => graphics::Text::new(ctx, "GAME OVER", assets.get_font("title")?)?,
             GameEndMode::Win => graphics::Text::new(ctx, "YOU WIN!", assets.get_font("title")?)?,
+            GameEndMode::TimeUp => {
+                graphics::Text::new(ctx, "TIME'S UP!", assets.get_font("title")?)?
+            }
         };
 
-        let score_str = format!("Final Score: {}", final_score_value);
-        let lines_str = format!("Final Lines: {}", final_cleared);
-        let level_str = format!("Final Level: {}", final_level);
-        let final_score = graphics::Text::new(ctx, &score_str, assets.get_font("normal")?)?;
-        let final_lines = graphics::Text::new(ctx, &lines_str, assets.get_font("normal")?)?;
-        let final_level = graphics::Text::new(ctx, &level_str, assets.get_font("normal")?)?;
+        let mut stat_texts = Vec::new();
+        for stat in GameEndState::stat_strings(summary).iter() {
+            stat_texts.push(graphics::Text::new(ctx, stat, assets.get_font("normal")?)?);
+        }
+        let seed_str = format!("Seed: {}", summary.seed);
+        let seed = graphics::Text::new(ctx, &seed_str, assets.get_font("normal")?)?;
 
         let coords = graphics::get_screen_coordinates(ctx);
         let mut options_vec: Vec<Option> = Vec::new();
@@ -63,35 +91,83 @@ impl GameEndState {
         options_vec.push(Option::new(
             ctx,
             assets,
-            "Return to Menu",
+            "Save replay",
             Point2::new(coords.w / 2.0, 525.0),
         ));
         options_vec.push(Option::new(
             ctx,
             assets,
-            "Quit",
+            "Return to Menu",
             Point2::new(coords.w / 2.0, 600.0),
         ));
+        options_vec.push(Option::new(
+            ctx,
+            assets,
+            "Quit",
+            Point2::new(coords.w / 2.0, 675.0),
+        ));
 
         match mode {
-            GameEndMode::Win => assets.get_sfx("gameover_win")?.play()?,
+            GameEndMode::Win | GameEndMode::TimeUp => assets.get_sfx("gameover_win")?.play()?,
             GameEndMode::Lose => assets.get_sfx("gameover_lose")?.play()?,
         }
 
         Ok(GameEndState {
+            replay,
+            bot: summary.bot,
+            is_replay_saved: false,
             request_replay: false,
+            request_save_replay: false,
             request_menu: false,
             request_quit: false,
             options: options_vec,
             current_selection: 0,
 
             game_end_text: game_over,
-            final_score_text: final_score,
-            final_line_text: final_lines,
-            final_level_text: final_level,
+            stat_texts,
+            seed_text: seed,
         })
     }
 
+    /// Returns the results worth showing for the mode of the game in
+    /// `summary`. Sprint and Dig are all about speed, so they show the time
+    /// taken rather than the score.
+    fn stat_strings(summary: &GameSummary) -> [String; 3] {
+        let pps = if summary.duration > 0.0 {
+            f64::from(summary.pieces) / summary.duration
+        } else {
+            0.0
+        };
+
+        match summary.mode {
+            GameMode::Sprint => [
+                format!("Time: {}", format_time(summary.duration)),
+                format!("Pieces per second: {:.2}", pps),
+                format!("Finesse faults: {}", summary.finesse_faults),
+            ],
+            GameMode::Dig => [
+                format!("Time: {}", format_time(summary.duration)),
+                format!("Pieces used: {}", summary.pieces),
+                format!("Pieces per second: {:.2}", pps),
+            ],
+            GameMode::Survival => [
+                format!("Survived: {}", format_time(summary.duration)),
+                format!("Final Lines: {}", summary.lines),
+                format!("Final Score: {}", summary.score),
+            ],
+            GameMode::Ultra | GameMode::Zen | GameMode::Puzzle | GameMode::Versus => [
+                format!("Final Score: {}", summary.score),
+                format!("Final Lines: {}", summary.lines),
+                format!("Pieces per second: {:.2}", pps),
+            ],
+            GameMode::Marathon => [
+                format!("Final Score: {}", summary.score),
+                format!("Final Lines: {}", summary.lines),
+                format!("Final Level: {}", summary.level),
+            ],
+        }
+    }
+
     // Ideally, I would not be coupling `Assets` to this method. Would a
     // messaging system be fast enough to handle audio system stuff? Maybe
     // something to try for v2.
@@ -117,8 +193,14 @@ impl GameEndState {
                 if self.current_selection == 0 {
                     self.request_replay = true;
                 } else if self.current_selection == 1 {
-                    self.request_menu = true;
+                    if !self.is_replay_saved {
+                        play_click_sfx(assets)
+                            .expect("Could not play click sfx in game end state -> save replay");
+                        self.request_save_replay = true;
+                    }
                 } else if self.current_selection == 2 {
+                    self.request_menu = true;
+                } else if self.current_selection == 3 {
                     self.request_quit = true;
                 }
             }
@@ -136,11 +218,30 @@ impl State for GameEndState {
         if self.request_menu {
             return Ok(Transition::Swap(Box::new(MenuState::new(ctx, assets)?)));
         } else if self.request_replay {
-            return Ok(Transition::Swap(Box::new(PlayState::new(ctx, assets)?)));
+            return Ok(Transition::Swap(Box::new(PlayState::new(
+                ctx,
+                assets,
+                self.replay.mode,
+                self.replay.settings,
+                self.replay.ruleset,
+                self.bot,
+            )?)));
         } else if self.request_quit {
             return Ok(Transition::Drain);
         }
 
+        if self.request_save_replay {
+            let text = match self.replay.save(ctx) {
+                Ok(_) => {
+                    self.is_replay_saved = true;
+                    "Replay saved"
+                }
+                Err(_) => "Could not save replay",
+            };
+            self.options[1].set_text(ctx, assets, text)?;
+            self.request_save_replay = false;
+        }
+
         for (i, option) in self.options.iter_mut().enumerate() {
             if i == self.current_selection {
                 option.update(true)?;
@@ -159,26 +260,20 @@ impl State for GameEndState {
             coords.w / 2.0 - (self.game_end_text.width() / 2) as f32,
             100.0,
         );
-        let game_over_score_dest = Point2::new(
-            coords.w / 2.0 - (self.final_level_text.width() / 2) as f32,
-            200.0,
-        );
-        let game_over_lines_dest = Point2::new(
-            coords.w / 2.0 - (self.final_line_text.width() / 2) as f32,
-            250.0,
-        );
-        let game_over_level_dest = Point2::new(
-            coords.w / 2.0 - (self.final_level_text.width() / 2) as f32,
-            300.0,
-        );
+        let seed_dest = Point2::new(coords.w / 2.0 - (self.seed_text.width() / 2) as f32, 350.0);
 
         graphics::set_color(ctx, graphics::Color::new(0.0, 0.0, 0.0, 0.75))?;
         graphics::rectangle(ctx, graphics::DrawMode::Fill, coords)?;
         graphics::set_color(ctx, graphics::Color::new(1.0, 1.0, 1.0, 1.0))?;
         graphics::draw(ctx, &self.game_end_text, game_over_dest, 0.0)?;
-        graphics::draw(ctx, &self.final_score_text, game_over_score_dest, 0.0)?;
-        graphics::draw(ctx, &self.final_line_text, game_over_lines_dest, 0.0)?;
-        graphics::draw(ctx, &self.final_level_text, game_over_level_dest, 0.0)?;
+        for (i, text) in self.stat_texts.iter().enumerate() {
+            let dest = Point2::new(
+                coords.w / 2.0 - (text.width() / 2) as f32,
+                200.0 + 50.0 * i as f32,
+            );
+            graphics::draw(ctx, text, dest, 0.0)?;
+        }
+        graphics::draw(ctx, &self.seed_text, seed_dest, 0.0)?;
 
         for option in &self.options {
             option.draw(ctx)?;
@@ -0,0 +1,228 @@
+use crate::states::play_state::handling::Handling;
+use crate::states::shared::option::{Option, OptionInputCommand};
+use crate::states::{Assets, State, Transition};
+use crate::util::{cycle, play_click_sfx};
+use ggez::event::{Button, Keycode, Mod};
+use ggez::graphics::Point2;
+use ggez::{graphics, Context, GameResult};
+use std::time::Duration;
+
+// the position of each option on the screen
+const DAS_OPTION: usize = 0;
+const ARR_OPTION: usize = 1;
+const SOFT_DROP_OPTION: usize = 2;
+const DAS_CUT_OPTION: usize = 3;
+const BACK_OPTION: usize = 4;
+
+const FIRST_OPTION_Y: f32 = 250.0;
+const OPTION_SPACING: f32 = 65.0;
+
+// the values each setting can be cycled through, roughly in whole frames
+const DAS_VALUES: [f64; 12] = [
+    0.05, 0.067, 0.083, 0.1, 0.117, 0.133, 0.15, 0.167, 0.183, 0.2, 0.25, 0.3,
+];
+const ARR_VALUES: [f64; 7] = [0.0, 0.017, 0.033, 0.05, 0.067, 0.083, 0.1];
+const SOFT_DROP_VALUES: [f64; 6] = [5.0, 10.0, 20.0, 40.0, 80.0, f64::INFINITY];
+const DAS_CUT_VALUES: [f64; 8] = [0.0, 0.017, 0.033, 0.05, 0.067, 0.083, 0.1, 0.15];
+
+/// Lets the player change their `Handling`. Every change is saved straight
+/// away and used from the next game on. Drawn on top of the state that pushed
+/// it, and pops itself when closed.
+pub struct HandlingState {
+    handling: Handling,
+    title_text: graphics::Text,
+    options: Vec<Option>,
+    current_selection: usize,
+
+    request_back: bool,
+    request_refresh: bool,
+}
+
+impl HandlingState {
+    pub fn new(ctx: &mut Context, assets: &Assets) -> GameResult<Self> {
+        let handling = Handling::load(ctx);
+        let title_text = graphics::Text::new(ctx, "Handling", assets.get_font("title")?)?;
+
+        let coords = graphics::get_screen_coordinates(ctx);
+        let mut options: Vec<Option> = Vec::new();
+        for (i, name) in HandlingState::option_texts(&handling).iter().enumerate() {
+            options.push(Option::new(
+                ctx,
+                assets,
+                name,
+                Point2::new(coords.w / 2.0, FIRST_OPTION_Y + OPTION_SPACING * i as f32),
+            ));
+        }
+
+        Ok(HandlingState {
+            handling,
+            title_text,
+            options,
+            current_selection: 0,
+
+            request_back: false,
+            request_refresh: false,
+        })
+    }
+
+    /// Returns the text of every option, in order.
+    fn option_texts(handling: &Handling) -> [String; 5] {
+        let arr = if handling.arr == 0.0 {
+            "Instant".to_string()
+        } else {
+            milliseconds(handling.arr)
+        };
+        let soft_drop = if handling.is_instant_soft_drop() {
+            "Instant".to_string()
+        } else {
+            format!("{}x", handling.soft_drop_factor)
+        };
+
+        [
+            format!("DAS: {}", milliseconds(handling.das)),
+            format!("ARR: {}", arr),
+            format!("Soft drop: {}", soft_drop),
+            format!("DAS cut: {}", milliseconds(handling.das_cut)),
+            "Back".to_string(),
+        ]
+    }
+
+    /// Change the selected setting to its next value, or its previous value
+    /// if `direction` is negative.
+    fn change_setting(&mut self, direction: i32, assets: &Assets) {
+        let handling = &mut self.handling;
+        match self.current_selection {
+            DAS_OPTION => handling.das = cycle(&DAS_VALUES, handling.das, direction),
+            ARR_OPTION => handling.arr = cycle(&ARR_VALUES, handling.arr, direction),
+            SOFT_DROP_OPTION => {
+                handling.soft_drop_factor =
+                    cycle(&SOFT_DROP_VALUES, handling.soft_drop_factor, direction)
+            }
+            DAS_CUT_OPTION => {
+                handling.das_cut = cycle(&DAS_CUT_VALUES, handling.das_cut, direction)
+            }
+            _ => return,
+        }
+
+        play_click_sfx(assets).expect("Could not play click sfx in handling state -> change");
+        self.request_refresh = true;
+    }
+
+    fn handle_input(&mut self, command: &OptionInputCommand, assets: &Assets) {
+        match command {
+            OptionInputCommand::Up => {
+                play_click_sfx(assets).expect("Could not play click sfx in handling state -> up");
+                if self.current_selection == 0 {
+                    self.current_selection = self.options.len() - 1;
+                } else {
+                    self.current_selection -= 1;
+                }
+            }
+            OptionInputCommand::Down => {
+                play_click_sfx(assets).expect("Could not play click sfx in handling state -> down");
+                if self.current_selection >= self.options.len() - 1 {
+                    self.current_selection = 0;
+                } else {
+                    self.current_selection += 1;
+                }
+            }
+            OptionInputCommand::Select => {
+                if self.current_selection == BACK_OPTION {
+                    self.request_back = true;
+                } else {
+                    self.change_setting(1, assets);
+                }
+            }
+        }
+    }
+}
+
+/// Formats a time in seconds as whole milliseconds.
+fn milliseconds(seconds: f64) -> String {
+    format!("{}ms", (seconds * 1000.0).round())
+}
+
+impl State for HandlingState {
+    fn update(
+        &mut self,
+        ctx: &mut Context,
+        assets: &Assets,
+        _: Duration,
+    ) -> GameResult<Transition> {
+        if self.request_back {
+            return Ok(Transition::Pop);
+        }
+
+        if self.request_refresh {
+            self.handling.save(ctx)?;
+            let texts = HandlingState::option_texts(&self.handling);
+            for (option, text) in self.options.iter_mut().zip(texts.iter()) {
+                option.set_text(ctx, assets, text)?;
+            }
+            self.request_refresh = false;
+        }
+
+        for (i, option) in self.options.iter_mut().enumerate() {
+            option.update(i == self.current_selection)?;
+        }
+
+        Ok(Transition::None)
+    }
+
+    fn draw(&mut self, ctx: &mut Context, _: &Assets) -> GameResult<()> {
+        let coords = graphics::get_screen_coordinates(ctx);
+        let title_dest = Point2::new(coords.w / 2.0 - (self.title_text.width() / 2) as f32, 100.0);
+
+        graphics::set_color(ctx, graphics::Color::new(0.0, 0.0, 0.0, 0.75))?;
+        graphics::rectangle(ctx, graphics::DrawMode::Fill, coords)?;
+        graphics::set_color(ctx, graphics::Color::new(1.0, 1.0, 1.0, 1.0))?;
+        graphics::draw(ctx, &self.title_text, title_dest, 0.0)?;
+
+        for option in &self.options {
+            option.draw(ctx)?;
+        }
+
+        Ok(())
+    }
+
+    fn key_down_event(
+        &mut self,
+        _ctx: &mut Context,
+        keycode: Keycode,
+        _keymod: Mod,
+        repeat: bool,
+        assets: &Assets,
+    ) {
+        if repeat {
+            return;
+        }
+
+        match keycode {
+            Keycode::Up => self.handle_input(&OptionInputCommand::Up, assets),
+            Keycode::Down => self.handle_input(&OptionInputCommand::Down, assets),
+            Keycode::Return => self.handle_input(&OptionInputCommand::Select, assets),
+            Keycode::Left => self.change_setting(-1, assets),
+            Keycode::Right => self.change_setting(1, assets),
+            Keycode::Escape => self.request_back = true,
+            _ => (),
+        }
+    }
+
+    fn controller_button_down_event(
+        &mut self,
+        _ctx: &mut Context,
+        btn: Button,
+        _instance_id: i32,
+        assets: &Assets,
+    ) {
+        match btn {
+            Button::DPadUp => self.handle_input(&OptionInputCommand::Up, assets),
+            Button::DPadDown => self.handle_input(&OptionInputCommand::Down, assets),
+            Button::A => self.handle_input(&OptionInputCommand::Select, assets),
+            Button::DPadLeft => self.change_setting(-1, assets),
+            Button::DPadRight => self.change_setting(1, assets),
+            Button::B => self.request_back = true,
+            _ => (),
+        }
+    }
+}
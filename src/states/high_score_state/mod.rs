@@ -0,0 +1,221 @@
+mod name_entry;
+pub mod table;
+
+pub use self::name_entry::NameEntryState;
+
+use self::table::{HighScoreEntry, HighScoreTable, MAX_ENTRIES};
+use crate::states::menu_state::MenuState;
+use crate::states::play_state::game_mode::GameMode;
+use crate::states::{Assets, State, Transition};
+use crate::util::{format_time, play_click_sfx};
+use ggez::event::{Button, Keycode, Mod};
+use ggez::graphics::Point2;
+use ggez::{graphics, Context, GameResult};
+use std::time::Duration;
+
+const FIRST_ROW_Y: f32 = 240.0;
+const ROW_SPACING: f32 = 35.0;
+
+/// Shows the high-score table of each mode. Left and right switch between
+/// modes. When shown after a game, the entry that was just added is
+/// highlighted, and closing the table moves on to `next`.
+pub struct HighScoreState {
+    mode: GameMode,
+    highlight: Option<usize>,
+    next: Option<Box<dyn State>>,
+
+    title_text: graphics::Text,
+    mode_text: graphics::Text,
+    header_text: graphics::Text,
+    row_texts: Vec<graphics::Text>,
+
+    request_close: bool,
+    request_refresh: bool,
+}
+
+impl HighScoreState {
+    /// Creates a view of the table for `mode`. `highlight` is the index of the
+    /// entry to highlight, and `next` the state that replaces this one when it
+    /// is closed. Without a `next` state, closing returns to the menu.
+    pub fn new(
+        ctx: &mut Context,
+        assets: &Assets,
+        mode: GameMode,
+        highlight: Option<usize>,
+        next: Option<Box<dyn State>>,
+    ) -> GameResult<Self> {
+        let title_text = graphics::Text::new(ctx, "High Scores", assets.get_font("title")?)?;
+        let mode_text = graphics::Text::new(ctx, mode.name(), assets.get_font("normal")?)?;
+        let header_text = graphics::Text::new(
+            ctx,
+            &format_row("#", "NAME", "SCORE", "LINES", "LV", "TIME", "DATE"),
+            assets.get_font("normal")?,
+        )?;
+
+        let mut state = HighScoreState {
+            mode,
+            highlight,
+            next,
+
+            title_text,
+            mode_text,
+            header_text,
+            row_texts: Vec::new(),
+
+            request_close: false,
+            request_refresh: false,
+        };
+        state.refresh(ctx, assets)?;
+
+        Ok(state)
+    }
+
+    /// Loads the table of the current mode and rebuilds every row.
+    fn refresh(&mut self, ctx: &mut Context, assets: &Assets) -> GameResult<()> {
+        let table = HighScoreTable::load(ctx, self.mode);
+        let mode_str = format!("< {} >", self.mode.name());
+        self.mode_text = graphics::Text::new(ctx, &mode_str, assets.get_font("normal")?)?;
+
+        self.row_texts.clear();
+        for i in 0..MAX_ENTRIES {
+            let row = match table.entries().get(i) {
+                Some(entry) => entry_row(i, entry),
+                None => format_row(&(i + 1).to_string(), "---", "", "", "", "", ""),
+            };
+            self.row_texts
+                .push(graphics::Text::new(ctx, &row, assets.get_font("normal")?)?);
+        }
+
+        Ok(())
+    }
+
+    fn change_mode(&mut self, assets: &Assets) {
+        play_click_sfx(assets).expect("Could not play click sfx in high score state -> mode");
+        self.mode = self.mode.next();
+        // the new entry only belongs to the table it was added to
+        self.highlight = None;
+        self.request_refresh = true;
+    }
+}
+
+fn entry_row(index: usize, entry: &HighScoreEntry) -> String {
+    format_row(
+        &(index + 1).to_string(),
+        &entry.name,
+        &entry.score.to_string(),
+        &entry.lines.to_string(),
+        &entry.level.to_string(),
+        &format_time(entry.duration),
+        &entry.date_text(),
+    )
+}
+
+/// Lines up the columns of a row. The table is drawn in a monospace font, so
+/// padding each column is enough to align them.
+fn format_row(
+    rank: &str,
+    name: &str,
+    score: &str,
+    lines: &str,
+    level: &str,
+    time: &str,
+    date: &str,
+) -> String {
+    format!(
+        "{:>2}  {:<8}  {:>8}  {:>5}  {:>2}  {:>9}  {:<10}",
+        rank, name, score, lines, level, time, date
+    )
+}
+
+impl State for HighScoreState {
+    fn update(
+        &mut self,
+        ctx: &mut Context,
+        assets: &Assets,
+        _: Duration,
+    ) -> GameResult<Transition> {
+        if self.request_close {
+            self.request_close = false;
+            return match self.next.take() {
+                Some(next) => Ok(Transition::Replace(next)),
+                None => Ok(Transition::Swap(Box::new(MenuState::new(ctx, assets)?))),
+            };
+        }
+
+        if self.request_refresh {
+            self.refresh(ctx, assets)?;
+            self.request_refresh = false;
+        }
+
+        Ok(Transition::None)
+    }
+
+    fn draw(&mut self, ctx: &mut Context, _: &Assets) -> GameResult<()> {
+        let coords = graphics::get_screen_coordinates(ctx);
+        let centered = |text: &graphics::Text, y: f32| {
+            Point2::new(coords.w / 2.0 - (text.width() / 2) as f32, y)
+        };
+
+        graphics::set_color(ctx, graphics::Color::new(0.0, 0.0, 0.0, 0.75))?;
+        graphics::rectangle(ctx, graphics::DrawMode::Fill, coords)?;
+        graphics::set_color(ctx, graphics::Color::new(1.0, 1.0, 1.0, 1.0))?;
+        graphics::draw(
+            ctx,
+            &self.title_text,
+            centered(&self.title_text, 100.0),
+            0.0,
+        )?;
+        graphics::draw(ctx, &self.mode_text, centered(&self.mode_text, 160.0), 0.0)?;
+        graphics::draw(
+            ctx,
+            &self.header_text,
+            centered(&self.header_text, 200.0),
+            0.0,
+        )?;
+
+        for (i, text) in self.row_texts.iter().enumerate() {
+            if self.highlight == Some(i) {
+                graphics::set_color(ctx, graphics::Color::new(1.0, 1.0, 0.0, 1.0))?;
+            } else {
+                graphics::set_color(ctx, graphics::Color::new(1.0, 1.0, 1.0, 1.0))?;
+            }
+            let dest = centered(text, FIRST_ROW_Y + ROW_SPACING * i as f32);
+            graphics::draw(ctx, text, dest, 0.0)?;
+        }
+
+        Ok(())
+    }
+
+    fn key_down_event(
+        &mut self,
+        _ctx: &mut Context,
+        keycode: Keycode,
+        _keymod: Mod,
+        repeat: bool,
+        assets: &Assets,
+    ) {
+        if repeat {
+            return;
+        }
+
+        match keycode {
+            Keycode::Left | Keycode::Right => self.change_mode(assets),
+            Keycode::Return | Keycode::Escape => self.request_close = true,
+            _ => (),
+        }
+    }
+
+    fn controller_button_down_event(
+        &mut self,
+        _ctx: &mut Context,
+        btn: Button,
+        _instance_id: i32,
+        assets: &Assets,
+    ) {
+        match btn {
+            Button::DPadLeft | Button::DPadRight => self.change_mode(assets),
+            Button::A | Button::B | Button::Start => self.request_close = true,
+            _ => (),
+        }
+    }
+}
@@ -0,0 +1,223 @@
+use super::table::{HighScoreEntry, HighScoreTable, MAX_NAME_LEN};
+use super::HighScoreState;
+use crate::states::play_state::game_mode::GameMode;
+use crate::states::{Assets, State, Transition};
+use crate::util::play_click_sfx;
+use ggez::event::{Button, Keycode, Mod};
+use ggez::graphics::Point2;
+use ggez::{graphics, Context, GameResult};
+use std::time::Duration;
+
+// the characters a name can be spelled with using up and down
+const CHARACTERS: &str = "ABCDEFGHIJKLMNOPQRSTUVWXYZ0123456789 ";
+const EMPTY_NAME: &str = "???";
+
+/// Asks the player for their name after a game that earned a place in the
+/// high-score table. Names can be typed on a keyboard, or spelled one
+/// character at a time with up, down, left and right. Once confirmed, the
+/// entry is saved and the table is shown.
+pub struct NameEntryState {
+    mode: GameMode,
+    entry: HighScoreEntry,
+    next: Option<Box<dyn State>>,
+
+    title_text: graphics::Text,
+    prompt_text: graphics::Text,
+    help_text: graphics::Text,
+    name_text: graphics::Text,
+
+    request_confirm: bool,
+    request_skip: bool,
+    request_refresh: bool,
+}
+
+impl NameEntryState {
+    /// Creates a name entry for `entry`, which must qualify for the table of
+    /// `mode`. `next` is shown after the table is closed.
+    pub fn new(
+        ctx: &mut Context,
+        assets: &Assets,
+        mode: GameMode,
+        entry: HighScoreEntry,
+        next: Box<dyn State>,
+    ) -> GameResult<Self> {
+        let title_text = graphics::Text::new(ctx, "NEW HIGH SCORE!", assets.get_font("title")?)?;
+        let prompt_str = format!("Score: {}  Enter your name:", entry.score);
+        let prompt_text = graphics::Text::new(ctx, &prompt_str, assets.get_font("normal")?)?;
+        let help_text = graphics::Text::new(
+            ctx,
+            "Up/Down: change letter  Right: add letter  Left: delete",
+            assets.get_font("ui")?,
+        )?;
+        let name_text = graphics::Text::new(ctx, "_", assets.get_font("title")?)?;
+
+        Ok(NameEntryState {
+            mode,
+            entry,
+            next: Some(next),
+
+            title_text,
+            prompt_text,
+            help_text,
+            name_text,
+
+            request_confirm: false,
+            request_skip: false,
+            request_refresh: false,
+        })
+    }
+
+    fn push_char(&mut self, c: char) {
+        if self.entry.name.len() < MAX_NAME_LEN {
+            self.entry.name.push(c);
+            self.request_refresh = true;
+        }
+    }
+
+    fn pop_char(&mut self) {
+        if self.entry.name.pop().is_some() {
+            self.request_refresh = true;
+        }
+    }
+
+    /// Changes the last character of the name to the next character in
+    /// `CHARACTERS`, or the previous one if `direction` is negative. Starts a
+    /// new character if the name is empty.
+    fn cycle_char(&mut self, direction: i32, assets: &Assets) {
+        play_click_sfx(assets).expect("Could not play click sfx in name entry state -> cycle");
+        let current = match self.entry.name.pop() {
+            Some(c) => c,
+            None => {
+                self.push_char('A');
+                return;
+            }
+        };
+
+        let count = CHARACTERS.len() as i32;
+        let index = CHARACTERS.find(current).unwrap_or(0) as i32;
+        let next = (index + direction).rem_euclid(count) as usize;
+        self.entry.name.push(CHARACTERS.as_bytes()[next] as char);
+        self.request_refresh = true;
+    }
+}
+
+impl State for NameEntryState {
+    fn update(
+        &mut self,
+        ctx: &mut Context,
+        assets: &Assets,
+        _: Duration,
+    ) -> GameResult<Transition> {
+        if self.request_skip {
+            if let Some(next) = self.next.take() {
+                return Ok(Transition::Replace(next));
+            }
+        }
+
+        if self.request_confirm {
+            self.request_confirm = false;
+            let name = self.entry.name.trim();
+            self.entry.name = if name.is_empty() {
+                EMPTY_NAME.to_string()
+            } else {
+                name.to_string()
+            };
+
+            let mut table = HighScoreTable::load(ctx, self.mode);
+            let highlight = table.insert(self.entry.clone());
+            table.save(ctx)?;
+            return Ok(Transition::Replace(Box::new(HighScoreState::new(
+                ctx,
+                assets,
+                self.mode,
+                highlight,
+                self.next.take(),
+            )?)));
+        }
+
+        if self.request_refresh {
+            let name_str = if self.entry.name.len() < MAX_NAME_LEN {
+                format!("{}_", self.entry.name)
+            } else {
+                self.entry.name.clone()
+            };
+            self.name_text = graphics::Text::new(ctx, &name_str, assets.get_font("title")?)?;
+            self.request_refresh = false;
+        }
+
+        Ok(Transition::None)
+    }
+
+    fn draw(&mut self, ctx: &mut Context, _: &Assets) -> GameResult<()> {
+        let coords = graphics::get_screen_coordinates(ctx);
+        let centered = |text: &graphics::Text, y: f32| {
+            Point2::new(coords.w / 2.0 - (text.width() / 2) as f32, y)
+        };
+
+        graphics::set_color(ctx, graphics::Color::new(0.0, 0.0, 0.0, 0.75))?;
+        graphics::rectangle(ctx, graphics::DrawMode::Fill, coords)?;
+        graphics::set_color(ctx, graphics::Color::new(1.0, 1.0, 1.0, 1.0))?;
+        graphics::draw(
+            ctx,
+            &self.title_text,
+            centered(&self.title_text, 150.0),
+            0.0,
+        )?;
+        graphics::draw(
+            ctx,
+            &self.prompt_text,
+            centered(&self.prompt_text, 250.0),
+            0.0,
+        )?;
+        graphics::draw(ctx, &self.name_text, centered(&self.name_text, 320.0), 0.0)?;
+        graphics::draw(ctx, &self.help_text, centered(&self.help_text, 450.0), 0.0)?;
+
+        Ok(())
+    }
+
+    fn text_input_event(&mut self, _ctx: &mut Context, text: String, assets: &Assets) {
+        for c in text.chars() {
+            if c.is_ascii_alphanumeric() || c == ' ' {
+                play_click_sfx(assets)
+                    .expect("Could not play click sfx in name entry state -> type");
+                self.push_char(c.to_ascii_uppercase());
+            }
+        }
+    }
+
+    fn key_down_event(
+        &mut self,
+        _ctx: &mut Context,
+        keycode: Keycode,
+        _keymod: Mod,
+        _repeat: bool,
+        assets: &Assets,
+    ) {
+        match keycode {
+            Keycode::Up => self.cycle_char(1, assets),
+            Keycode::Down => self.cycle_char(-1, assets),
+            Keycode::Right => self.push_char('A'),
+            Keycode::Left | Keycode::Backspace => self.pop_char(),
+            Keycode::Return => self.request_confirm = true,
+            Keycode::Escape => self.request_skip = true,
+            _ => (),
+        }
+    }
+
+    fn controller_button_down_event(
+        &mut self,
+        _ctx: &mut Context,
+        btn: Button,
+        _instance_id: i32,
+        assets: &Assets,
+    ) {
+        match btn {
+            Button::DPadUp => self.cycle_char(1, assets),
+            Button::DPadDown => self.cycle_char(-1, assets),
+            Button::DPadRight => self.push_char('A'),
+            Button::DPadLeft | Button::B => self.pop_char(),
+            Button::A | Button::Start => self.request_confirm = true,
+            _ => (),
+        }
+    }
+}
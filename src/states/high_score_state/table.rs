@@ -0,0 +1,264 @@
+use crate::states::game_over_state::GameSummary;
+use crate::states::play_state::game_mode::{GameMode, Ranking};
+use ggez::{Context, GameResult};
+use std::io::{Read, Write};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+pub const MAX_ENTRIES: usize = 10;
+pub const MAX_NAME_LEN: usize = 8;
+const HIGH_SCORE_DIR: &str = "/highscores";
+const BEST_SPLITS_PATH: &str = "/highscores/sprint_splits.txt";
+
+/// A single entry in a `HighScoreTable`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct HighScoreEntry {
+    pub name: String,
+    pub score: u32,
+    pub lines: u32,
+    pub level: u32,
+    /// How long the game lasted, in seconds.
+    pub duration: f64,
+    /// When the game was played, in seconds since the Unix epoch.
+    pub date: u64,
+}
+
+impl HighScoreEntry {
+    /// Creates an unnamed entry for a game that just finished.
+    pub fn new(summary: &GameSummary) -> Self {
+        HighScoreEntry {
+            name: String::new(),
+            score: summary.score,
+            lines: summary.lines,
+            level: summary.level,
+            duration: summary.duration,
+            date: now(),
+        }
+    }
+
+    /// Returns the date the game was played on, formatted as `YYYY-MM-DD`.
+    pub fn date_text(&self) -> String {
+        let (year, month, day) = civil_from_days((self.date / 86_400) as i64);
+        format!("{:04}-{:02}-{:02}", year, month, day)
+    }
+}
+
+/// The best games played in a single mode, best first.
+pub struct HighScoreTable {
+    mode: GameMode,
+    entries: Vec<HighScoreEntry>,
+}
+
+impl HighScoreTable {
+    pub fn new(mode: GameMode) -> Self {
+        HighScoreTable {
+            mode,
+            entries: Vec::new(),
+        }
+    }
+
+    /// Loads the table for `mode`. Returns an empty table if none was saved.
+    /// Lines that cannot be read are skipped.
+    pub fn load(ctx: &mut Context, mode: GameMode) -> Self {
+        let mut table = HighScoreTable::new(mode);
+        let mut contents = String::new();
+        let read = ctx
+            .filesystem
+            .open(HighScoreTable::path(mode))
+            .and_then(|mut file| Ok(file.read_to_string(&mut contents)?));
+        if read.is_ok() {
+            table.entries = contents.lines().filter_map(parse_entry).collect();
+            table.entries.truncate(MAX_ENTRIES);
+        }
+        table
+    }
+
+    /// Saves the table, replacing the one saved for its mode.
+    pub fn save(&self, ctx: &mut Context) -> GameResult<()> {
+        ctx.filesystem.create_dir(HIGH_SCORE_DIR)?;
+        let mut file = ctx.filesystem.create(HighScoreTable::path(self.mode))?;
+        for entry in &self.entries {
+            writeln!(
+                file,
+                "{} {} {} {} {} {}",
+                entry.score, entry.lines, entry.level, entry.duration, entry.date, entry.name
+            )?;
+        }
+        Ok(())
+    }
+
+    pub fn entries(&self) -> &[HighScoreEntry] {
+        &self.entries
+    }
+
+    /// Returns true if `entry` would earn a place in the table.
+    pub fn qualifies(&self, entry: &HighScoreEntry) -> bool {
+        if self.mode.ranking() == Ranking::Score && entry.score == 0 {
+            return false;
+        }
+
+        self.entries.len() < MAX_ENTRIES
+            || self
+                .entries
+                .iter()
+                .any(|existing| self.ranks_above(entry, existing))
+    }
+
+    /// Adds `entry` to the table, pushing the lowest entry out if the table
+    /// is full. An entry ranks below any existing entry it ties with. Returns
+    /// the index of the new entry, or `None` if it did not qualify.
+    pub fn insert(&mut self, entry: HighScoreEntry) -> Option<usize> {
+        if !self.qualifies(&entry) {
+            return None;
+        }
+
+        let index = self
+            .entries
+            .iter()
+            .position(|existing| self.ranks_above(&entry, existing))
+            .unwrap_or(self.entries.len());
+        self.entries.insert(index, entry);
+        self.entries.truncate(MAX_ENTRIES);
+
+        Some(index)
+    }
+
+    /// Returns true if `entry` belongs above `other` in this table, as ranked
+    /// by the mode of the table.
+    fn ranks_above(&self, entry: &HighScoreEntry, other: &HighScoreEntry) -> bool {
+        match self.mode.ranking() {
+            Ranking::Score => entry.score > other.score,
+            Ranking::FastestTime => entry.duration < other.duration,
+            Ranking::LongestTime => entry.duration > other.duration,
+        }
+    }
+
+    fn path(mode: GameMode) -> String {
+        format!("{}/{}.txt", HIGH_SCORE_DIR, mode.name().to_lowercase())
+    }
+}
+
+/// Loads the splits of the fastest sprint, as saved by `save_best_splits`.
+/// Returns no splits if none were saved.
+pub fn load_best_splits(ctx: &mut Context) -> Vec<f64> {
+    let mut contents = String::new();
+    let read = ctx
+        .filesystem
+        .open(BEST_SPLITS_PATH)
+        .and_then(|mut file| Ok(file.read_to_string(&mut contents)?));
+    if read.is_err() {
+        return Vec::new();
+    }
+
+    contents
+        .lines()
+        .map(|line| line.parse().ok())
+        .collect::<Option<Vec<f64>>>()
+        .unwrap_or_default()
+}
+
+/// Saves `splits` as the splits of the fastest sprint, one per line.
+pub fn save_best_splits(ctx: &mut Context, splits: &[f64]) -> GameResult<()> {
+    ctx.filesystem.create_dir(HIGH_SCORE_DIR)?;
+    let mut file = ctx.filesystem.create(BEST_SPLITS_PATH)?;
+    for split in splits {
+        writeln!(file, "{}", split)?;
+    }
+    Ok(())
+}
+
+/// Returns the current time in seconds since the Unix epoch.
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_secs())
+        .unwrap_or(0)
+}
+
+/// Reads an entry written by `HighScoreTable::save`. The name comes last so
+/// that it may contain spaces.
+fn parse_entry(line: &str) -> Option<HighScoreEntry> {
+    let mut parts = line.splitn(6, ' ');
+    Some(HighScoreEntry {
+        score: parts.next()?.parse().ok()?,
+        lines: parts.next()?.parse().ok()?,
+        level: parts.next()?.parse().ok()?,
+        duration: parts.next()?.parse().ok()?,
+        date: parts.next()?.parse().ok()?,
+        name: parts.next()?.to_string(),
+    })
+}
+
+/// Converts a number of days since the Unix epoch into a (year, month, day)
+/// date in the proleptic Gregorian calendar. This is Howard Hinnant's
+/// `civil_from_days` algorithm.
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719_468;
+    let era = if z >= 0 { z } else { z - 146_096 } / 146_097;
+    let day_of_era = z - era * 146_097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = (day_of_year - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    (year, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_entry, HighScoreEntry, HighScoreTable, MAX_ENTRIES};
+    use crate::states::play_state::game_mode::GameMode;
+
+    fn entry(score: u32) -> HighScoreEntry {
+        HighScoreEntry {
+            name: "AAA".to_string(),
+            score,
+            lines: 0,
+            level: 1,
+            duration: 0.0,
+            date: 0,
+        }
+    }
+
+    #[test]
+    fn insert_keeps_the_best_entries_in_order() {
+        let mut table = HighScoreTable::new(GameMode::Marathon);
+        for score in 1..=MAX_ENTRIES as u32 {
+            assert!(table.insert(entry(score * 100)).is_some());
+        }
+
+        assert!(!table.qualifies(&entry(100)));
+        assert_eq!(None, table.insert(entry(50)));
+        assert_eq!(Some(1), table.insert(entry(950)));
+        assert_eq!(MAX_ENTRIES, table.entries().len());
+        assert_eq!(1000, table.entries()[0].score);
+        assert_eq!(200, table.entries()[MAX_ENTRIES - 1].score);
+    }
+
+    #[test]
+    fn sprint_entries_are_ranked_by_time() {
+        let mut table = HighScoreTable::new(GameMode::Sprint);
+        let slow = HighScoreEntry {
+            duration: 90.0,
+            ..entry(0)
+        };
+        let fast = HighScoreEntry {
+            duration: 60.0,
+            ..entry(0)
+        };
+
+        assert_eq!(Some(0), table.insert(slow));
+        assert_eq!(Some(0), table.insert(fast));
+        assert_eq!(60.0, table.entries()[0].duration);
+    }
+
+    #[test]
+    fn entries_and_dates_are_read_back() {
+        let entry = parse_entry("1200 14 2 95.5 1700000000 BIG BOB").unwrap();
+        assert_eq!("BIG BOB", entry.name);
+        assert_eq!(95.5, entry.duration);
+        assert_eq!("2023-11-14", entry.date_text());
+    }
+}
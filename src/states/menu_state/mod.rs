@@ -1,8 +1,15 @@
 mod spawner;
 
 use self::spawner::Spawner;
-use crate::states::play_state::PlayState;
+use crate::states::handling_state::HandlingState;
+use crate::states::high_score_state::HighScoreState;
+use crate::states::play_state::game_mode::GameMode;
+use crate::states::play_state::ruleset::Ruleset;
+use crate::states::puzzle_state::PuzzleListState;
+use crate::states::replay_state::ReplayListState;
+use crate::states::setup_state::SetupState;
 use crate::states::shared::option::{Option, OptionInputCommand};
+use crate::states::versus_state::{LobbyState, VersusSetupState};
 use crate::states::{Assets, State, Transition};
 use crate::util::{play_click_sfx, DurationExt};
 use ggez::event::{Button, Keycode, Mod};
@@ -10,6 +17,23 @@ use ggez::graphics::{Color, Point2};
 use ggez::{graphics, Context, GameResult};
 use std::time::Duration;
 
+// the position of each option in the menu
+const PLAY_OPTION: usize = 0;
+const VERSUS_OPTION: usize = 1;
+const LOBBY_OPTION: usize = 2;
+const HIGH_SCORES_OPTION: usize = 3;
+const PUZZLES_OPTION: usize = 4;
+const REPLAYS_OPTION: usize = 5;
+const HANDLING_OPTION: usize = 6;
+const ROTATION_OPTION: usize = 7;
+const LOCK_RESET_OPTION: usize = 8;
+const RANDOMIZER_OPTION: usize = 9;
+const GRAVITY_OPTION: usize = 10;
+const EXIT_OPTION: usize = 11;
+
+const FIRST_OPTION_Y: f32 = 200.0;
+const OPTION_SPACING: f32 = 42.0;
+
 pub struct MenuState {
     title_text: graphics::Text,
     title_shadow: graphics::Text,
@@ -17,9 +41,17 @@ pub struct MenuState {
     piece_spawner: Spawner,
     options: Vec<Option>,
     current_selection: usize,
+    ruleset: Ruleset,
 
     request_play: bool,
+    request_versus: bool,
+    request_lobby: bool,
+    request_high_scores: bool,
+    request_puzzles: bool,
+    request_replays: bool,
+    request_handling: bool,
     request_exit: bool,
+    request_ruleset_refresh: bool,
 }
 
 impl MenuState {
@@ -27,20 +59,32 @@ impl MenuState {
         let title = graphics::Text::new(ctx, "Rustris", assets.get_font("title")?)?;
         let title_shadow = graphics::Text::new(ctx, "Rustris", assets.get_font("title_shadow")?)?;
 
+        let ruleset = Ruleset::default();
+        let names = [
+            "Play!".to_string(),
+            "Versus".to_string(),
+            "LAN Lobby".to_string(),
+            "High Scores".to_string(),
+            "Puzzles".to_string(),
+            "Replays".to_string(),
+            "Handling".to_string(),
+            MenuState::rotation_option_text(&ruleset),
+            MenuState::lock_reset_option_text(&ruleset),
+            MenuState::randomizer_option_text(&ruleset),
+            MenuState::gravity_option_text(&ruleset),
+            "Exit".to_string(),
+        ];
+
         let coords = graphics::get_screen_coordinates(ctx);
         let mut options_vec: Vec<Option> = Vec::new();
-        options_vec.push(Option::new(
-            ctx,
-            assets,
-            "Play!",
-            Point2::new(coords.w / 2.0, 250.0),
-        ));
-        options_vec.push(Option::new(
-            ctx,
-            assets,
-            "Exit",
-            Point2::new(coords.w / 2.0, 325.0),
-        ));
+        for (i, name) in names.iter().enumerate() {
+            options_vec.push(Option::new(
+                ctx,
+                assets,
+                name,
+                Point2::new(coords.w / 2.0, FIRST_OPTION_Y + OPTION_SPACING * i as f32),
+            ));
+        }
 
         Ok(MenuState {
             title_text: title,
@@ -49,12 +93,36 @@ impl MenuState {
             piece_spawner: Spawner::new(),
             options: options_vec,
             current_selection: 0,
+            ruleset,
 
             request_play: false,
+            request_versus: false,
+            request_lobby: false,
+            request_high_scores: false,
+            request_puzzles: false,
+            request_replays: false,
+            request_handling: false,
             request_exit: false,
+            request_ruleset_refresh: false,
         })
     }
 
+    fn rotation_option_text(ruleset: &Ruleset) -> String {
+        format!("Rotation: {}", ruleset.rotation_system.name())
+    }
+
+    fn lock_reset_option_text(ruleset: &Ruleset) -> String {
+        format!("Lock reset: {}", ruleset.lock_reset.name())
+    }
+
+    fn randomizer_option_text(ruleset: &Ruleset) -> String {
+        format!("Randomizer: {}", ruleset.randomizer.name())
+    }
+
+    fn gravity_option_text(ruleset: &Ruleset) -> String {
+        format!("Gravity: {}", ruleset.gravity.name())
+    }
+
     // Ideally, I would not be coupling `Assets` to this method. Would a
     // messaging system be fast enough to handle audio system stuff? Maybe
     // something to try for v2.
@@ -76,13 +144,41 @@ impl MenuState {
                     self.current_selection += 1;
                 }
             }
-            OptionInputCommand::Select => {
-                if self.current_selection == 0 {
-                    self.request_play = true;
-                } else if self.current_selection == 1 {
-                    self.request_exit = true;
+            OptionInputCommand::Select => match self.current_selection {
+                PLAY_OPTION => self.request_play = true,
+                VERSUS_OPTION => self.request_versus = true,
+                LOBBY_OPTION => self.request_lobby = true,
+                HIGH_SCORES_OPTION => self.request_high_scores = true,
+                PUZZLES_OPTION => self.request_puzzles = true,
+                REPLAYS_OPTION => self.request_replays = true,
+                HANDLING_OPTION => self.request_handling = true,
+                ROTATION_OPTION => {
+                    play_click_sfx(assets)
+                        .expect("Could not play click sfx in menu state -> rotation");
+                    self.ruleset.rotation_system = self.ruleset.rotation_system.next();
+                    self.request_ruleset_refresh = true;
                 }
-            }
+                LOCK_RESET_OPTION => {
+                    play_click_sfx(assets)
+                        .expect("Could not play click sfx in menu state -> lock reset");
+                    self.ruleset.lock_reset = self.ruleset.lock_reset.next();
+                    self.request_ruleset_refresh = true;
+                }
+                RANDOMIZER_OPTION => {
+                    play_click_sfx(assets)
+                        .expect("Could not play click sfx in menu state -> randomizer");
+                    self.ruleset.randomizer = self.ruleset.randomizer.next();
+                    self.request_ruleset_refresh = true;
+                }
+                GRAVITY_OPTION => {
+                    play_click_sfx(assets)
+                        .expect("Could not play click sfx in menu state -> gravity");
+                    self.ruleset.gravity = self.ruleset.gravity.next();
+                    self.request_ruleset_refresh = true;
+                }
+                EXIT_OPTION => self.request_exit = true,
+                _ => (),
+            },
         }
     }
 }
@@ -107,15 +203,65 @@ impl State for MenuState {
         self.piece_spawner.update(ctx, assets, dt);
 
         if self.request_play {
-            assets.get_music("menu")?.pause();
-            return Ok(Transition::Swap(Box::new(
-                PlayState::new(ctx, assets).unwrap(),
-            )));
+            self.request_play = false;
+            return Ok(Transition::Push(Box::new(SetupState::new(
+                ctx,
+                assets,
+                self.ruleset,
+            )?)));
+        } else if self.request_versus {
+            self.request_versus = false;
+            return Ok(Transition::Push(Box::new(VersusSetupState::new(
+                ctx,
+                assets,
+                self.ruleset,
+            )?)));
+        } else if self.request_lobby {
+            self.request_lobby = false;
+            return Ok(Transition::Push(Box::new(LobbyState::new(
+                ctx,
+                assets,
+                self.ruleset,
+            )?)));
+        } else if self.request_high_scores {
+            return Ok(Transition::Swap(Box::new(HighScoreState::new(
+                ctx,
+                assets,
+                GameMode::default(),
+                None,
+                None,
+            )?)));
+        } else if self.request_puzzles {
+            return Ok(Transition::Swap(Box::new(PuzzleListState::new(
+                ctx,
+                assets,
+                self.ruleset,
+                0,
+            )?)));
+        } else if self.request_replays {
+            return Ok(Transition::Swap(Box::new(ReplayListState::new(
+                ctx, assets,
+            )?)));
+        } else if self.request_handling {
+            self.request_handling = false;
+            return Ok(Transition::Push(Box::new(HandlingState::new(ctx, assets)?)));
         } else if self.request_exit {
             assets.get_music("menu")?.pause();
             return Ok(Transition::Pop);
         }
 
+        if self.request_ruleset_refresh {
+            let text = MenuState::rotation_option_text(&self.ruleset);
+            self.options[ROTATION_OPTION].set_text(ctx, assets, &text)?;
+            let text = MenuState::lock_reset_option_text(&self.ruleset);
+            self.options[LOCK_RESET_OPTION].set_text(ctx, assets, &text)?;
+            let text = MenuState::randomizer_option_text(&self.ruleset);
+            self.options[RANDOMIZER_OPTION].set_text(ctx, assets, &text)?;
+            let text = MenuState::gravity_option_text(&self.ruleset);
+            self.options[GRAVITY_OPTION].set_text(ctx, assets, &text)?;
+            self.request_ruleset_refresh = false;
+        }
+
         // for mut option in &mut self.options {
         for (i, option) in self.options.iter_mut().enumerate() {
             if i == self.current_selection {
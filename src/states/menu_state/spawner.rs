@@ -1,3 +1,4 @@
+use crate::states::play_state::rotation::Srs;
 use crate::states::play_state::tetromino::{u8_to_piece_type, Piece, PieceType};
 use crate::states::Assets;
 use crate::util::DurationExt;
@@ -20,7 +21,7 @@ impl SpawnedPiece {
         Self {
             position: pos,
             extra_rotation: rot,
-            piece: Piece::new(ptype),
+            piece: Piece::new(ptype, &Srs),
             is_dead: false,
         }
     }
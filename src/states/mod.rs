@@ -1,8 +1,15 @@
 pub mod game_over_state;
+pub mod handling_state;
+pub mod high_score_state;
 pub mod intro_state;
 pub mod menu_state;
+pub mod pause_state;
 pub mod play_state;
+pub mod puzzle_state;
+pub mod replay_state;
+pub mod setup_state;
 pub mod shared;
+pub mod versus_state;
 
 use crate::states::intro_state::IntroState;
 use ggez::event::{Axis, Button, EventHandler, Keycode, Mod, MouseButton, MouseState};
@@ -98,6 +105,9 @@ pub enum Transition {
     Swap(Box<dyn State>),
     /// Remove the state currently at the top of the stack.
     Pop,
+    /// Remove the state currently at the top of the stack and push a new one
+    /// in its place.
+    Replace(Box<dyn State>),
     /// Clear the stack which in turn quits the game.
     Drain,
 }
@@ -121,6 +131,7 @@ pub trait State {
     }
     fn key_up_event(&mut self, _ctx: &mut Context, _keycode: Keycode, _keymod: Mod, _repeat: bool) {
     }
+    fn text_input_event(&mut self, _ctx: &mut Context, _text: String, _assets: &Assets) {}
     fn mouse_button_down_event(
         &mut self,
         _ctx: &mut Context,
@@ -252,6 +263,7 @@ impl StateManager {
         match transition {
             Transition::None => (),
             Transition::Pop => self.pop(),
+            Transition::Replace(state) => self.replace(state),
             Transition::Swap(state) => self.swap(state),
             Transition::Push(state) => self.push(state),
             Transition::Drain => self.drain(),
@@ -268,6 +280,13 @@ impl StateManager {
         }
     }
 
+    /// Pops the state at the top of the stack and pushes `boxed_state` in its
+    /// place.
+    fn replace(&mut self, boxed_state: Box<dyn State>) {
+        self.states.pop();
+        self.push(boxed_state);
+    }
+
     /// Pushes a state onto the state stack.
     fn push(&mut self, boxed_state: Box<dyn State>) {
         self.states.push(boxed_state)
@@ -368,6 +387,12 @@ impl EventHandler for StateManager {
         }
     }
 
+    fn text_input_event(&mut self, ctx: &mut Context, text: String) {
+        if let Some(state) = self.states.last_mut() {
+            state.text_input_event(ctx, text, &self.assets);
+        }
+    }
+
     fn controller_button_down_event(&mut self, ctx: &mut Context, btn: Button, instance_id: i32) {
         if let Some(state) = self.states.last_mut() {
             state.controller_button_down_event(ctx, btn, instance_id, &self.assets);
@@ -0,0 +1,202 @@
+use crate::states::handling_state::HandlingState;
+use crate::states::menu_state::MenuState;
+use crate::states::play_state::bot::BotDifficulty;
+use crate::states::play_state::game_mode::{GameMode, ModeSettings};
+use crate::states::play_state::ruleset::Ruleset;
+use crate::states::play_state::well::Well;
+use crate::states::play_state::PlayState;
+use crate::states::shared::option::{Option, OptionInputCommand};
+use crate::states::{Assets, State, Transition};
+use crate::util::play_click_sfx;
+use ggez::event::{Button, Keycode, Mod};
+use ggez::graphics::Point2;
+use ggez::{graphics, Context, GameResult};
+use std::time::Duration;
+
+// the position of each option on the screen
+const RESUME_OPTION: usize = 0;
+const RESTART_OPTION: usize = 1;
+const OPTIONS_OPTION: usize = 2;
+const MENU_OPTION: usize = 3;
+
+const FIRST_OPTION_Y: f32 = 300.0;
+const OPTION_SPACING: f32 = 75.0;
+
+/// Pushed on top of a `PlayState` when the player pauses. The game does not
+/// advance while this state is on top of it, and the well is covered so it
+/// cannot be studied while paused.
+pub struct PauseState {
+    mode: GameMode,
+    settings: ModeSettings,
+    ruleset: Ruleset,
+    bot: std::option::Option<BotDifficulty>,
+    title_text: graphics::Text,
+    options: Vec<Option>,
+    current_selection: usize,
+
+    request_resume: bool,
+    request_restart: bool,
+    request_options: bool,
+    request_menu: bool,
+}
+
+impl PauseState {
+    /// Creates a pause menu for a game of `mode` with `settings`, played with
+    /// `ruleset` by `bot`, or by a person if it is `None`. All four are used
+    /// again if the game is restarted.
+    pub fn new(
+        ctx: &mut Context,
+        assets: &Assets,
+        mode: GameMode,
+        settings: ModeSettings,
+        ruleset: Ruleset,
+        bot: std::option::Option<BotDifficulty>,
+    ) -> GameResult<Self> {
+        let title_text = graphics::Text::new(ctx, "PAUSED", assets.get_font("title")?)?;
+
+        let coords = graphics::get_screen_coordinates(ctx);
+        let mut options: Vec<Option> = Vec::new();
+        for (i, name) in ["Resume", "Restart", "Options", "Quit to Menu"]
+            .iter()
+            .enumerate()
+        {
+            options.push(Option::new(
+                ctx,
+                assets,
+                name,
+                Point2::new(coords.w / 2.0, FIRST_OPTION_Y + OPTION_SPACING * i as f32),
+            ));
+        }
+
+        Ok(PauseState {
+            mode,
+            settings,
+            ruleset,
+            bot,
+            title_text,
+            options,
+            current_selection: 0,
+
+            request_resume: false,
+            request_restart: false,
+            request_options: false,
+            request_menu: false,
+        })
+    }
+
+    fn handle_input(&mut self, command: &OptionInputCommand, assets: &Assets) {
+        match command {
+            OptionInputCommand::Up => {
+                play_click_sfx(assets).expect("Could not play click sfx in pause state -> up");
+                if self.current_selection == 0 {
+                    self.current_selection = self.options.len() - 1;
+                } else {
+                    self.current_selection -= 1;
+                }
+            }
+            OptionInputCommand::Down => {
+                play_click_sfx(assets).expect("Could not play click sfx in pause state -> down");
+                if self.current_selection >= self.options.len() - 1 {
+                    self.current_selection = 0;
+                } else {
+                    self.current_selection += 1;
+                }
+            }
+            OptionInputCommand::Select => match self.current_selection {
+                RESUME_OPTION => self.request_resume = true,
+                RESTART_OPTION => self.request_restart = true,
+                OPTIONS_OPTION => self.request_options = true,
+                MENU_OPTION => self.request_menu = true,
+                _ => (),
+            },
+        }
+    }
+}
+
+impl State for PauseState {
+    fn update(
+        &mut self,
+        ctx: &mut Context,
+        assets: &Assets,
+        _: Duration,
+    ) -> GameResult<Transition> {
+        if self.request_resume {
+            return Ok(Transition::Pop);
+        } else if self.request_restart {
+            return Ok(Transition::Swap(Box::new(PlayState::new(
+                ctx,
+                assets,
+                self.mode,
+                self.settings,
+                self.ruleset,
+                self.bot,
+            )?)));
+        } else if self.request_options {
+            self.request_options = false;
+            return Ok(Transition::Push(Box::new(HandlingState::new(ctx, assets)?)));
+        } else if self.request_menu {
+            return Ok(Transition::Swap(Box::new(MenuState::new(ctx, assets)?)));
+        }
+
+        for (i, option) in self.options.iter_mut().enumerate() {
+            option.update(i == self.current_selection)?;
+        }
+
+        Ok(Transition::None)
+    }
+
+    fn draw(&mut self, ctx: &mut Context, _: &Assets) -> GameResult<()> {
+        let coords = graphics::get_screen_coordinates(ctx);
+        let title_dest = Point2::new(coords.w / 2.0 - (self.title_text.width() / 2) as f32, 150.0);
+
+        graphics::set_color(ctx, graphics::Color::new(0.0, 0.0, 0.0, 1.0))?;
+        graphics::rectangle(ctx, graphics::DrawMode::Fill, Well::backside())?;
+        graphics::set_color(ctx, graphics::Color::new(0.0, 0.0, 0.0, 0.75))?;
+        graphics::rectangle(ctx, graphics::DrawMode::Fill, coords)?;
+        graphics::set_color(ctx, graphics::Color::new(1.0, 1.0, 1.0, 1.0))?;
+        graphics::draw(ctx, &self.title_text, title_dest, 0.0)?;
+
+        for option in &self.options {
+            option.draw(ctx)?;
+        }
+
+        Ok(())
+    }
+
+    fn key_down_event(
+        &mut self,
+        _ctx: &mut Context,
+        keycode: Keycode,
+        _keymod: Mod,
+        repeat: bool,
+        assets: &Assets,
+    ) {
+        if repeat {
+            return;
+        }
+
+        match keycode {
+            Keycode::Up => self.handle_input(&OptionInputCommand::Up, assets),
+            Keycode::Down => self.handle_input(&OptionInputCommand::Down, assets),
+            Keycode::Return => self.handle_input(&OptionInputCommand::Select, assets),
+            Keycode::Escape => self.request_resume = true,
+            _ => (),
+        }
+    }
+
+    fn controller_button_down_event(
+        &mut self,
+        _ctx: &mut Context,
+        btn: Button,
+        _instance_id: i32,
+        assets: &Assets,
+    ) {
+        match btn {
+            Button::DPadUp => self.handle_input(&OptionInputCommand::Up, assets),
+            Button::DPadDown => self.handle_input(&OptionInputCommand::Down, assets),
+            Button::A => self.handle_input(&OptionInputCommand::Select, assets),
+            Button::B | Button::Start => self.request_resume = true,
+            _ => (),
+        }
+    }
+}
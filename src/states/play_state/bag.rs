@@ -1,63 +1,64 @@
-use rand;
-use rand::Rng;
+use rand::rngs::StdRng;
+use rand::SeedableRng;
 
-use super::tetromino::{u8_to_piece_type, Piece, PieceType};
+use super::randomizer::{Randomizer, RandomizerKind, Sequence};
+use super::tetromino::PieceType;
 
-/// A bag of `Pieces`. Takes care of dispensing, refilling and giving a peek at
-/// the next piece.
+/// Deals the pieces of a game. Takes care of dispensing pieces and giving a
+/// peek at the next piece. The order of the pieces is decided by a
+/// `Randomizer`, and depends only on the randomizer and the seed.
+#[derive(Clone)]
 pub struct PieceBag {
-    queue: Vec<PieceType>,
+    seed: u64,
+    rng: StdRng,
+    randomizer: Box<dyn Randomizer>,
+    next_piece: PieceType,
 }
 
 impl PieceBag {
-    pub fn new() -> Self {
+    pub fn new(randomizer_kind: RandomizerKind, seed: u64) -> Self {
+        let mut rng = StdRng::seed_from_u64(seed);
+        let mut randomizer = randomizer_kind.build();
+        let next_piece = randomizer.next_piece(&mut rng);
+
         PieceBag {
-            queue: PieceBag::generate_full_bag(),
+            seed,
+            rng,
+            randomizer,
+            next_piece,
         }
     }
 
-    /// Takes the next piece from the bag. If the bag is empty after removing a
-    /// piece, refill it.
-    pub fn take_piece(&mut self) -> Piece {
-        let next_piece_shape = self.queue.remove(0);
+    /// Creates a bag that deals `pieces` in order, over and over. `pieces`
+    /// must not be empty.
+    pub fn with_sequence(pieces: &[PieceType]) -> Self {
+        let mut rng = StdRng::seed_from_u64(0);
+        let mut randomizer: Box<dyn Randomizer> = Box::new(Sequence::new(pieces.to_vec()));
+        let next_piece = randomizer.next_piece(&mut rng);
 
-        // if bag is now empty, generate new bag
-        if self.queue.is_empty() {
-            self.queue = PieceBag::generate_full_bag();
+        PieceBag {
+            seed: 0,
+            rng,
+            randomizer,
+            next_piece,
         }
+    }
 
-        Piece::new(next_piece_shape)
+    /// Takes the next piece from the bag, and decides on the piece after it.
+    pub fn take_piece(&mut self) -> PieceType {
+        let piece = self.next_piece;
+        self.next_piece = self.randomizer.next_piece(&mut self.rng);
+        piece
     }
 
     /// Returns the next piece in the bag without actually removing it from the
     /// bag.
-    pub fn peek_at_next_piece(&self) -> Piece {
-        let next_piece_shape = self.queue.first().expect("Could not peek into PieceBag");
-
-        Piece::new_from_ref(*next_piece_shape)
+    pub fn peek_at_next_piece(&self) -> PieceType {
+        self.next_piece
     }
 
-    /// Generates a a full bag of 7 pieces. This is a static function rather
-    /// than a method so we can fill the bag in the `new()` function.
-    fn generate_full_bag() -> Vec<PieceType> {
-        let mut pieces: Vec<PieceType> = Vec::new();
-        for _ in 0..7 {
-            let num = rand::thread_rng().gen_range(0, 7);
-            let mut shape = u8_to_piece_type(num).unwrap();
-
-            // TODO: better random pieces
-            // in the end, we want 1 of each piece
-            while pieces.contains(&shape) {
-                let num = rand::thread_rng().gen_range(0, 7);
-                shape = u8_to_piece_type(num).unwrap();
-            }
-
-            // TODO: debug line, remove / put behind 'debug' flag
-            //println!("Adding piece: {:?}", shape);
-
-            pieces.push(shape);
-        }
-
-        pieces
+    /// Returns the seed the bag was created with.
+    pub fn seed(&self) -> u64 {
+        self.seed
     }
 }
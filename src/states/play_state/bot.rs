@@ -0,0 +1,544 @@
+use super::game_core::GameCore;
+use super::input_state::InputAction;
+use super::rotation::RotationSystem;
+use super::tetromino::{next_rotation_index, Piece, PieceType};
+use super::well::{Well, HEIGHT, WIDTH};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::collections::VecDeque;
+
+// how much each feature of a well counts towards its score. Higher stacks,
+// holes and uneven columns are bad, clearing lines is good
+const HEIGHT_WEIGHT: f64 = -0.51;
+const LINES_WEIGHT: f64 = 0.76;
+const HOLES_WEIGHT: f64 = -0.36;
+const BUMPINESS_WEIGHT: f64 = -0.18;
+// the score of a placement that locks inside of the vanish zone
+const TOP_OUT_SCORE: f64 = -1000.0;
+// a mistake is made by picking one of this many of the best placements
+const MISTAKE_CHOICES: usize = 4;
+
+/// How well a built-in bot plays.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum BotDifficulty {
+    Easy,
+    Medium,
+    Hard,
+}
+
+impl BotDifficulty {
+    /// Returns the short name shown to the player.
+    pub fn name(self) -> &'static str {
+        match self {
+            BotDifficulty::Easy => "Easy",
+            BotDifficulty::Medium => "Medium",
+            BotDifficulty::Hard => "Hard",
+        }
+    }
+
+    /// Returns the difficulty after this one, wrapping around at the end.
+    pub fn next(self) -> Self {
+        match self {
+            BotDifficulty::Easy => BotDifficulty::Medium,
+            BotDifficulty::Medium => BotDifficulty::Hard,
+            BotDifficulty::Hard => BotDifficulty::Easy,
+        }
+    }
+
+    /// Returns the number of frames the bot waits after a new piece appears
+    /// before it decides where to put it.
+    pub fn think_frames(self) -> u32 {
+        match self {
+            BotDifficulty::Easy => 40,
+            BotDifficulty::Medium => 20,
+            BotDifficulty::Hard => 6,
+        }
+    }
+
+    /// Returns the number of frames the bot waits after each input before
+    /// the next one. Each input is only held down for a single frame.
+    pub fn input_frames(self) -> u32 {
+        match self {
+            BotDifficulty::Easy => 8,
+            BotDifficulty::Medium => 4,
+            BotDifficulty::Hard => 1,
+        }
+    }
+
+    /// Returns the chance, from 0 to 1, that the bot picks a placement other
+    /// than the best one it found.
+    pub fn mistake_chance(self) -> f64 {
+        match self {
+            BotDifficulty::Easy => 0.25,
+            BotDifficulty::Medium => 0.08,
+            BotDifficulty::Hard => 0.0,
+        }
+    }
+
+    /// Returns true if the bot takes the next piece into account when
+    /// placing the current one.
+    pub fn looks_ahead(self) -> bool {
+        self == BotDifficulty::Hard
+    }
+}
+
+/// Returns the player that comes after `player` when choosing who plays a
+/// game, where `None` is a person and every bot difficulty follows in turn.
+pub fn next_player(player: Option<BotDifficulty>) -> Option<BotDifficulty> {
+    match player {
+        None => Some(BotDifficulty::Easy),
+        Some(BotDifficulty::Hard) => None,
+        Some(difficulty) => Some(difficulty.next()),
+    }
+}
+
+/// Returns the name shown for `player`, where `None` is a person.
+pub fn player_name(player: Option<BotDifficulty>) -> String {
+    match player {
+        Some(difficulty) => format!("Bot ({})", difficulty.name()),
+        None => "Human".to_string(),
+    }
+}
+
+/// A read-only snapshot of everything a `Bot` may look at when deciding what
+/// to do with the current piece.
+pub struct BotView<'a> {
+    pub well: &'a Well,
+    pub current_piece: &'a Piece,
+    pub hold: Option<PieceType>,
+    /// False if the current piece was taken from the hold, so it cannot be
+    /// held again.
+    pub can_hold: bool,
+    /// The pieces that will be dealt next, soonest first. Empty once a puzzle
+    /// has dealt all of its pieces.
+    pub next: Vec<PieceType>,
+    pub rotation_system: &'a dyn RotationSystem,
+}
+
+impl<'a> BotView<'a> {
+    /// Takes a snapshot of `core` as it is now.
+    pub fn new(core: &'a GameCore) -> Self {
+        BotView {
+            well: core.well(),
+            current_piece: core.current_piece(),
+            hold: core.hold_piece_type(),
+            can_hold: core.can_hold(),
+            next: core
+                .next_piece()
+                .map(|piece| piece.get_type())
+                .into_iter()
+                .collect(),
+            rotation_system: core.rotation_system(),
+        }
+    }
+}
+
+/// Where a `Bot` wants the current piece to be hard dropped from.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Target {
+    /// Swap the current piece with the hold before moving it.
+    pub hold: bool,
+    /// The rotation state to turn the piece to, as returned by
+    /// `Piece::get_rotation_index`.
+    pub rotation: u32,
+    /// The column of the left edge of the piece's 4x4 box.
+    pub x: i32,
+}
+
+/// What a `Bot` decided to do with the current piece.
+#[derive(Debug, Clone, PartialEq)]
+pub enum BotMove {
+    /// Move the piece to a target, then hard drop it.
+    Place(Target),
+    /// Press each of these inputs in turn.
+    Inputs(Vec<InputAction>),
+}
+
+/// Something that can play a game without a person. A bot is asked for a
+/// move once for each piece, and a `BotPlayer` carries the move out.
+pub trait Bot {
+    /// Decides what to do with the current piece in `view`.
+    fn choose_move(&mut self, view: &BotView) -> BotMove;
+}
+
+/// The built-in bot. Tries every position the current piece, or the piece
+/// it could swap with the hold, can be dropped from, and picks the one that
+/// leaves the lowest, flattest well with the fewest holes. Its difficulty
+/// decides whether it looks ahead to the next piece and how often it makes
+/// mistakes.
+pub struct HeuristicBot {
+    difficulty: BotDifficulty,
+    rng: StdRng,
+}
+
+impl HeuristicBot {
+    /// Creates a bot playing at `difficulty`. Bots created with the same
+    /// `seed` make the same mistakes.
+    pub fn new(difficulty: BotDifficulty, seed: u64) -> Self {
+        HeuristicBot {
+            difficulty,
+            rng: StdRng::seed_from_u64(seed),
+        }
+    }
+
+    /// Returns the score of the best way to follow `well` up with a piece of
+    /// `piece_type`, or of `well` itself if there is no piece to look at.
+    fn best_follow_up(
+        &self,
+        view: &BotView,
+        well: &Well,
+        lines: u32,
+        piece_type: Option<PieceType>,
+    ) -> f64 {
+        let piece_type = match piece_type {
+            Some(piece_type) if self.difficulty.looks_ahead() => piece_type,
+            _ => return evaluate(well, lines),
+        };
+
+        let piece = Piece::new(piece_type, view.rotation_system);
+        reachable(&piece, well, view.rotation_system)
+            .iter()
+            .map(|(placed, _)| match drop_onto(well, placed) {
+                Some((next_well, next_lines)) => evaluate(&next_well, lines + next_lines),
+                None => TOP_OUT_SCORE,
+            })
+            .fold(TOP_OUT_SCORE, f64::max)
+    }
+}
+
+impl Bot for HeuristicBot {
+    fn choose_move(&mut self, view: &BotView) -> BotMove {
+        let current_type = view.current_piece.get_type();
+        let mut candidates: Vec<(f64, Target)> = Vec::new();
+
+        for hold in [false, true].iter() {
+            // the piece played, and the piece that will be dealt after it
+            let (piece, following) = if !*hold {
+                (*view.current_piece, view.next.first().cloned())
+            } else if !view.can_hold || view.hold == Some(current_type) {
+                continue;
+            } else if let Some(hold_type) = view.hold {
+                let piece = Piece::new(hold_type, view.rotation_system);
+                (piece, view.next.first().cloned())
+            } else if let Some(next_type) = view.next.first() {
+                (Piece::new(*next_type, view.rotation_system), None)
+            } else {
+                continue;
+            };
+
+            for (placed, _) in reachable(&piece, view.well, view.rotation_system).iter() {
+                let score = match drop_onto(view.well, placed) {
+                    Some((well, lines)) => self.best_follow_up(view, &well, lines, following),
+                    None => TOP_OUT_SCORE,
+                };
+                let target = Target {
+                    hold: *hold,
+                    rotation: placed.get_rotation_index(),
+                    x: placed.top_left.x as i32,
+                };
+                candidates.push((score, target));
+            }
+        }
+
+        if candidates.is_empty() {
+            return BotMove::Inputs(vec![InputAction::HardDrop]);
+        }
+
+        candidates.sort_by(|a, b| b.0.partial_cmp(&a.0).unwrap_or(std::cmp::Ordering::Equal));
+        let choice = if self.rng.gen::<f64>() < self.difficulty.mistake_chance() {
+            self.rng.gen_range(0, candidates.len().min(MISTAKE_CHOICES))
+        } else {
+            0
+        };
+        BotMove::Place(candidates[choice].1)
+    }
+}
+
+/// Plays a game with a `Bot`, pressing one input at a time at the speed of
+/// its difficulty. A new move is asked for each time a piece locks, and any
+/// inputs left over from the last move are thrown away.
+pub struct BotPlayer {
+    bot: Box<dyn Bot>,
+    difficulty: BotDifficulty,
+    // the pieces locked when the current piece appeared
+    piece: Option<u32>,
+    has_planned: bool,
+    plan: VecDeque<InputAction>,
+    // the input pressed on the last frame, released on this one
+    pressed: Option<InputAction>,
+    // frames left before the next input, or before planning
+    wait: u32,
+}
+
+impl BotPlayer {
+    /// Creates a player that carries out the moves of `bot` at the speed of
+    /// `difficulty`.
+    pub fn new(bot: Box<dyn Bot>, difficulty: BotDifficulty) -> Self {
+        BotPlayer {
+            bot,
+            difficulty,
+            piece: None,
+            has_planned: false,
+            plan: VecDeque::new(),
+            pressed: None,
+            wait: 0,
+        }
+    }
+
+    /// Creates a player for the built-in `HeuristicBot`.
+    pub fn heuristic(difficulty: BotDifficulty, seed: u64) -> Self {
+        BotPlayer::new(Box::new(HeuristicBot::new(difficulty, seed)), difficulty)
+    }
+
+    /// Returns the difficulty the bot plays at.
+    pub fn difficulty(&self) -> BotDifficulty {
+        self.difficulty
+    }
+
+    /// Returns the inputs to press (true) or release (false) before the next
+    /// frame of `core` is played.
+    pub fn update(&mut self, core: &GameCore) -> Vec<(InputAction, bool)> {
+        let mut changes = Vec::new();
+        if let Some(action) = self.pressed.take() {
+            changes.push((action, false));
+        }
+        if core.is_finished() {
+            return changes;
+        }
+
+        if self.piece != Some(core.pieces_locked()) {
+            self.piece = Some(core.pieces_locked());
+            self.has_planned = false;
+            self.plan.clear();
+            self.wait = self.wait.max(self.difficulty.think_frames());
+        }
+
+        if self.wait > 0 {
+            self.wait -= 1;
+            return changes;
+        }
+
+        if !self.has_planned {
+            self.has_planned = true;
+            let view = BotView::new(core);
+            self.plan = match self.bot.choose_move(&view) {
+                BotMove::Place(target) => inputs_for(&view, target).into(),
+                BotMove::Inputs(inputs) => inputs.into(),
+            };
+        }
+
+        if let Some(action) = self.plan.pop_front() {
+            changes.push((action, true));
+            self.pressed = Some(action);
+            self.wait = self.difficulty.input_frames();
+        }
+        changes
+    }
+}
+
+/// Returns the inputs that move the current piece in `view` to `target` and
+/// hard drop it. If the target cannot be reached, the piece is dropped where
+/// it is.
+pub fn inputs_for(view: &BotView, target: Target) -> Vec<InputAction> {
+    let mut inputs = Vec::new();
+    let piece = if target.hold {
+        inputs.push(InputAction::Hold);
+        match view.hold.or_else(|| view.next.first().cloned()) {
+            Some(piece_type) => Piece::new(piece_type, view.rotation_system),
+            None => *view.current_piece,
+        }
+    } else {
+        *view.current_piece
+    };
+
+    let path = reachable(&piece, view.well, view.rotation_system)
+        .into_iter()
+        .find(|(placed, _)| {
+            placed.get_rotation_index() == target.rotation && placed.top_left.x as i32 == target.x
+        });
+    if let Some((_, path)) = path {
+        inputs.extend(path);
+    }
+    inputs.push(InputAction::HardDrop);
+    inputs
+}
+
+/// Returns every position `piece` can be hard dropped from by turning it
+/// and then tapping left or right, along with the inputs that reach it. Only
+/// the stack around the piece is taken into account, so tucks and spins are
+/// never found.
+fn reachable(
+    piece: &Piece,
+    well: &Well,
+    rotation_system: &dyn RotationSystem,
+) -> Vec<(Piece, Vec<InputAction>)> {
+    let turns: [&[InputAction]; 4] = [
+        &[],
+        &[InputAction::RotateClockwise],
+        &[InputAction::RotateClockwise, InputAction::RotateClockwise],
+        &[InputAction::RotateCounterclockwise],
+    ];
+
+    let mut found = Vec::new();
+    for turn in turns.iter() {
+        let mut turned = *piece;
+        let fits = turn.iter().all(|action| {
+            let direction = if *action == InputAction::RotateClockwise {
+                1
+            } else {
+                -1
+            };
+            rotate(&mut turned, direction, well, rotation_system)
+        });
+        if !fits {
+            continue;
+        }
+        found.push((turned, turn.to_vec()));
+
+        for (offset, action) in [(-1.0, InputAction::Left), (1.0, InputAction::Right)].iter() {
+            let mut moved = turned;
+            let mut inputs = turn.to_vec();
+            loop {
+                let mut next = moved.top_left;
+                next.x += offset;
+                if well.check_for_collisions(&moved.get_shape(), next) {
+                    break;
+                }
+                moved.top_left = next;
+                inputs.push(*action);
+                found.push((moved, inputs.clone()));
+            }
+        }
+    }
+    found
+}
+
+/// Rotates `piece` the way `GameCore` would, trying each kick in turn.
+/// Returns false if none of them fit.
+fn rotate(
+    piece: &mut Piece,
+    direction: i32,
+    well: &Well,
+    rotation_system: &dyn RotationSystem,
+) -> bool {
+    let to = next_rotation_index(piece.get_rotation_index(), direction);
+    let next_shape = piece.get_next_shape(direction);
+    for (kick_x, kick_y) in rotation_system.kicks(well, piece, to).iter() {
+        let mut position = piece.top_left;
+        position.x += *kick_x as f32;
+        position.y += *kick_y as f32;
+        if !well.check_for_collisions(&next_shape, position) {
+            piece.top_left = position;
+            piece.potential_top_left = position;
+            piece.change_shape(direction);
+            return true;
+        }
+    }
+    false
+}
+
+/// Hard drops `piece` onto a copy of `well` and clears any filled lines.
+/// Returns the new well and the lines cleared, or `None` if the piece locks
+/// inside of the vanish zone.
+fn drop_onto(well: &Well, piece: &Piece) -> Option<(Well, u32)> {
+    let mut dropped = *piece;
+    loop {
+        let mut below = dropped.top_left;
+        below.y += 1.0;
+        if well.check_for_collisions(&dropped.get_shape(), below) {
+            break;
+        }
+        dropped.top_left = below;
+    }
+    if dropped.is_in_vanish_zone() {
+        return None;
+    }
+
+    let mut well = well.clone();
+    well.land(&dropped);
+    let lines = well.clear_lines();
+    Some((well, lines))
+}
+
+/// Scores a well after a piece has locked, clearing `lines` lines. Higher is
+/// better.
+fn evaluate(well: &Well, lines: u32) -> f64 {
+    let mut heights = [0; WIDTH];
+    let mut holes = 0;
+    for (x, height) in heights.iter_mut().enumerate() {
+        let top = (0..HEIGHT).find(|y| well.is_occupied(x as i32, *y as i32));
+        if let Some(top) = top {
+            *height = HEIGHT - top;
+            holes += (top..HEIGHT)
+                .filter(|y| !well.is_occupied(x as i32, *y as i32))
+                .count();
+        }
+    }
+
+    let aggregate_height: usize = heights.iter().sum();
+    let bumpiness: usize = heights
+        .windows(2)
+        .map(|pair| (pair[0] as i32 - pair[1] as i32).unsigned_abs() as usize)
+        .sum();
+
+    HEIGHT_WEIGHT * aggregate_height as f64
+        + LINES_WEIGHT * f64::from(lines)
+        + HOLES_WEIGHT * holes as f64
+        + BUMPINESS_WEIGHT * bumpiness as f64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{BotDifficulty, BotPlayer};
+    use crate::states::play_state::game_core::GameCore;
+    use crate::states::play_state::game_mode::{GameMode, ModeSettings};
+    use crate::states::play_state::handling::Handling;
+    use crate::states::play_state::input_state::InputState;
+    use crate::states::play_state::puzzle::Puzzle;
+    use crate::states::play_state::replay::FRAME_DURATION;
+    use crate::states::play_state::ruleset::Ruleset;
+
+    /// Plays `core` with `bot` for up to `frames` frames, or until it ends.
+    fn play(core: &mut GameCore, bot: &mut BotPlayer, frames: u32) {
+        let mut input = InputState::default();
+        for _ in 0..frames {
+            if core.is_finished() {
+                return;
+            }
+            for (action, pressed) in bot.update(core) {
+                input.set_action(action, pressed);
+            }
+            core.step(&mut input, FRAME_DURATION);
+        }
+    }
+
+    #[test]
+    fn bot_drops_an_i_piece_into_a_well() {
+        let text = "name Test\ngoal lines 4\npieces O I\nwell\n\
+                    #########.\n#########.\n#########.\n#########.\n";
+        let puzzle = Puzzle::read_from(&mut text.as_bytes()).unwrap();
+        let mut core = GameCore::from_puzzle(&puzzle, Ruleset::default(), Handling::default());
+
+        // the O piece has to be kept out of the last column to leave room for
+        // the I piece
+        let mut bot = BotPlayer::heuristic(BotDifficulty::Hard, 0);
+        play(&mut core, &mut bot, 600);
+        assert!(core.has_won());
+    }
+
+    #[test]
+    fn bot_survives_a_marathon() {
+        let mut core = GameCore::new(
+            GameMode::Marathon,
+            ModeSettings::default(),
+            Ruleset::default(),
+            Handling::default(),
+            0,
+        );
+        let mut bot = BotPlayer::heuristic(BotDifficulty::Hard, 0);
+        play(&mut core, &mut bot, 2400);
+
+        assert!(!core.is_game_over());
+        assert!(core.cleared_lines() >= 10);
+    }
+}
@@ -0,0 +1,98 @@
+use super::rotation::RotationSystem;
+use super::tetromino::Piece;
+use super::well::Well;
+use std::collections::{HashSet, VecDeque};
+
+// the row pieces are moved around in while searching, clear of the top of the
+// well so every orientation fits
+const SEARCH_ROW: f32 = 6.0;
+
+/// The cells a piece covers, relative to the highest row it covers. Two
+/// pieces with the same placement fill the same columns in the same way, even
+/// if they are in different orientations.
+type Placement = Vec<(i32, i32)>;
+
+/// Returns the fewest inputs needed to move a newly spawned piece into the
+/// same columns and orientation as `piece`. Each tap of left, right or a
+/// rotation counts as one input, as does holding left or right to shift the
+/// piece to a wall. The stack is ignored, so placements that can only be
+/// reached by a kick, tuck or spin return `None`.
+pub fn min_inputs(piece: &Piece, rotation_system: &dyn RotationSystem) -> Option<u32> {
+    let well = Well::new();
+    let target = placement(piece);
+
+    let mut start = Piece::new(piece.get_type(), rotation_system);
+    start.top_left.y = SEARCH_ROW;
+
+    let mut seen = HashSet::new();
+    let mut queue = VecDeque::new();
+    seen.insert((start.get_rotation_index(), start.top_left.x as i32));
+    queue.push_back((start, 0));
+
+    while let Some((current, inputs)) = queue.pop_front() {
+        if placement(&current) == target {
+            return Some(inputs);
+        }
+
+        for next in neighbours(&current, &well).iter() {
+            if seen.insert((next.get_rotation_index(), next.top_left.x as i32)) {
+                queue.push_back((*next, inputs + 1));
+            }
+        }
+    }
+
+    None
+}
+
+/// Returns every position `piece` can reach with a single input.
+fn neighbours(piece: &Piece, well: &Well) -> Vec<Piece> {
+    let mut found = Vec::new();
+
+    for direction in [-1.0, 1.0].iter() {
+        // a tap
+        let mut moved = *piece;
+        moved.top_left.x += direction;
+        if !well.check_for_collisions(&moved.get_shape(), moved.top_left) {
+            found.push(moved);
+        }
+
+        // holding until the piece reaches the wall
+        let mut shifted = *piece;
+        loop {
+            let mut next = shifted.top_left;
+            next.x += direction;
+            if well.check_for_collisions(&shifted.get_shape(), next) {
+                break;
+            }
+            shifted.top_left = next;
+        }
+        found.push(shifted);
+    }
+
+    for direction in [-1, 1].iter() {
+        let mut rotated = *piece;
+        rotated.change_shape(*direction);
+        if !well.check_for_collisions(&rotated.get_shape(), rotated.top_left) {
+            found.push(rotated);
+        }
+    }
+
+    found
+}
+
+fn placement(piece: &Piece) -> Placement {
+    let mut cells: Placement = Vec::new();
+    for (r, row) in piece.get_shape().iter().enumerate() {
+        for (c, block) in row.iter().enumerate() {
+            if *block != 0 {
+                cells.push((piece.top_left.x as i32 + c as i32, r as i32));
+            }
+        }
+    }
+
+    let top = cells.iter().map(|(_, r)| *r).min().unwrap_or(0);
+    for cell in cells.iter_mut() {
+        cell.1 -= top;
+    }
+    cells
+}
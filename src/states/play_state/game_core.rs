@@ -0,0 +1,1891 @@
+use super::bag::PieceBag;
+use super::finesse;
+use super::game_mode::{GameMode, ModeSettings, SPRINT_LINES};
+use super::garbage::GarbageGenerator;
+use super::gravity::{FRAMES_PER_SECOND, INSTANT_GRAVITY};
+use super::handling::Handling;
+use super::input_state::{InputState, InputStateField};
+use super::puzzle::{Puzzle, PuzzleGoal};
+use super::rotation::RotationSystem;
+use super::ruleset::{LockReset, Ruleset};
+use super::t_spin::{detect_t_spin, TSpin};
+use super::tetromino::{next_rotation_index, Piece, PieceType};
+use super::well::{Well, HEIGHT};
+use crate::util::DurationExt;
+use ggez::graphics::Point2;
+use std::time::Duration;
+
+// the longest time, in seconds, a soft dropped piece takes to fall one row,
+// used when gravity is slower than this or turned off
+const SLOWEST_SOFT_DROP_ROW_TIME: f64 = 1.0;
+const COMBO_BONUS: u32 = 50;
+const MOVE_RESET_LIMIT: u32 = 15;
+const LINES_PER_SPLIT: u32 = 10;
+// time before the first row of garbage rises in Survival, after which each
+// row comes a little sooner than the last, down to a minimum
+const SURVIVAL_FIRST_ROW_TIME: f64 = 10.0;
+const SURVIVAL_MIN_ROW_TIME: f64 = 1.0;
+const SURVIVAL_ROW_SPEED_UP: f64 = 0.9;
+const SURVIVAL_MESSINESS: f64 = 0.5;
+// the number of rows, counting from the top of the well, cleared away when the
+// stack reaches the top in a mode with no top-out
+const TOP_OUT_CLEARED_ROWS: usize = 12;
+// rows of garbage sent in Versus for clearing 0 to 4 lines, and for a T-spin
+// clearing 0 to 3 lines. A back-to-back clear sends one more row, a perfect
+// clear sends `PERFECT_CLEAR_ATTACK` rows on top, and combos add the rows in
+// `COMBO_ATTACK`, which keeps giving its last value for longer combos
+const LINE_CLEAR_ATTACK: [u32; 5] = [0, 0, 1, 2, 4];
+const T_SPIN_ATTACK: [u32; 4] = [0, 2, 4, 6];
+const BACK_TO_BACK_ATTACK: u32 = 1;
+const PERFECT_CLEAR_ATTACK: u32 = 10;
+const COMBO_ATTACK: [u32; 12] = [0, 1, 1, 2, 2, 3, 3, 4, 4, 4, 5, 5];
+// the chance that the hole in garbage sent in Versus moves away from the
+// column of the hole in the garbage before it
+const VERSUS_MESSINESS: f64 = 0.7;
+
+/// Something that happened inside of a `GameCore` during a call to
+/// `GameCore::step`. A front-end can use these to play sounds, update its UI,
+/// etc. without the core needing to know anything about rendering or audio.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum GameEvent {
+    /// The current piece moved one cell.
+    Moved,
+    /// The current piece was rotated.
+    Rotated,
+    /// The current piece was hard dropped.
+    HardDropped,
+    /// The current piece was swapped into the hold area.
+    Held,
+    /// The current piece was added to the well and a new piece was taken.
+    PieceLocked,
+    /// The given number of lines were cleared.
+    LinesCleared(u32),
+    /// The current piece locked as a T-spin, clearing the given number of
+    /// lines.
+    TSpin(TSpin, u32),
+    /// The player reached the given level.
+    LevelUp(u32),
+    /// A piece landed in the vanish zone, or the next piece had no room to
+    /// spawn; the game is over.
+    GameOver,
+    /// The player passed another `LINES_PER_SPLIT` lines. Holds the index of
+    /// the split in `GameCore::splits`.
+    Split(usize),
+    /// The player reached the goal of the game mode.
+    Won,
+    /// The time limit of the game mode ran out.
+    TimeUp,
+    /// A row of garbage rose from the bottom of the well.
+    GarbageRose,
+    /// The stack reached the top of the well in a mode with no top-out, so
+    /// its upper rows were cleared away.
+    StackCleared,
+    /// A puzzle ran out of pieces before its goal was reached.
+    PuzzleFailed,
+    /// The given number of rows of garbage should be sent to the opponent.
+    /// Garbage sent to this player has already been cancelled out of it.
+    GarbageSent(u32),
+}
+
+/// Where and how a piece locked. Enough to lock the same piece in a copy of
+/// the game, dealt the same pieces, with `GameCore::place_piece`.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct LockedPiece {
+    pub rotation: u32,
+    pub x: i32,
+    pub y: i32,
+    /// Whether the piece was swapped in from hold during its turn.
+    pub held: bool,
+    /// The kick used by the rotation that moved the piece last, if it was
+    /// moved by a rotation. Needed to detect T-spins.
+    pub kick: Option<usize>,
+}
+
+/// All of the rules of a game of Rustris, with no dependency on a window,
+/// renderer or audio device. `PlayState` drives a `GameCore` with player input
+/// and draws its contents, but a `GameCore` can just as easily be driven by a
+/// test or a bot.
+///
+/// A `GameCore` can be cloned to take a snapshot of the game, which plays on
+/// exactly as the original would.
+#[derive(Clone)]
+pub struct GameCore {
+    prev_input: InputState,
+    mode: GameMode,
+    settings: ModeSettings,
+    ruleset: Ruleset,
+    handling: Handling,
+    rotation_system: Box<dyn RotationSystem>,
+
+    well: Well,
+    bag: PieceBag,
+    garbage: GarbageGenerator,
+    current_piece: Piece,
+    hold_piece_type: Option<PieceType>,
+    can_hold: bool,
+    // used to detect T-spins when the current piece locks
+    last_action_was_rotation: bool,
+    last_kick_index: usize,
+    last_lock: Option<LockedPiece>,
+
+    // time, in seconds, the game has been played for
+    elapsed_time: f64,
+    // the elapsed time at every `LINES_PER_SPLIT` lines cleared
+    splits: Vec<f64>,
+    // rows per frame the current piece falls, and the fraction of a row it has
+    // built up towards its next fall
+    gravity: f64,
+    gravity_progress: f64,
+    // time left before a charged DAS may move the current piece
+    das_cut_timer: f64,
+    // time since the last row of garbage rose, and the time between rows
+    garbage_timer: f64,
+    garbage_interval: f64,
+    // rows of garbage sent by an opponent that have not risen yet, one entry
+    // for each attack
+    received_garbage: Vec<u32>,
+    // time the current piece has spent resting on the stack
+    lock_timer: f64,
+    lock_resets: u32,
+    lowest_row: f32,
+    score: u32,
+    // the number of locks in a row that cleared lines
+    combo_chain: u32,
+    // the number of difficult line clears in a row
+    b2b_chain: u32,
+    cleared_lines: u32,
+    pieces_locked: u32,
+    // left, right and rotation presses made while moving the current piece
+    piece_inputs: u32,
+    finesse_faults: u32,
+    lines_until_next_level: i32,
+    level: u32,
+    game_over: bool,
+    // only used by puzzles. The pieces left counts the pieces still to be
+    // dealt, not counting the current piece or the hold
+    puzzle_goal: Option<PuzzleGoal>,
+    puzzle_pieces_left: Option<u32>,
+    puzzle_solved: bool,
+    puzzle_failed: bool,
+
+    events: Vec<GameEvent>,
+}
+
+impl GameCore {
+    /// Creates a new game of `mode`, using the options for it in `settings`,
+    /// played with `ruleset` and `handling`. Games created with the same
+    /// ruleset and `seed` are dealt the same sequence of pieces.
+    pub fn new(
+        mode: GameMode,
+        settings: ModeSettings,
+        ruleset: Ruleset,
+        handling: Handling,
+        seed: u64,
+    ) -> Self {
+        let rotation_system = ruleset.rotation_system.build();
+        let mut bag = PieceBag::new(ruleset.randomizer, seed);
+        let first_piece = Piece::new(bag.take_piece(), rotation_system.as_ref());
+        let messiness = match mode {
+            GameMode::Dig => settings.dig_messiness,
+            GameMode::Versus => VERSUS_MESSINESS,
+            _ => SURVIVAL_MESSINESS,
+        };
+        let mut garbage = GarbageGenerator::new(seed, messiness);
+        let mut well = Well::new();
+        if mode == GameMode::Dig {
+            for _ in 0..settings.dig_rows {
+                well.push_garbage_row(garbage.next_hole());
+            }
+        }
+        let level = if mode.has_levels() {
+            settings.marathon_start_level
+        } else {
+            0
+        };
+
+        let mut core = GameCore {
+            prev_input: InputState::default(),
+            mode,
+            settings,
+            ruleset,
+            handling,
+            rotation_system,
+
+            well,
+            bag,
+            garbage,
+            current_piece: first_piece,
+            hold_piece_type: None,
+            can_hold: true,
+            last_action_was_rotation: false,
+            last_kick_index: 0,
+            last_lock: None,
+
+            elapsed_time: 0.0,
+            splits: Vec::new(),
+            gravity: match mode {
+                GameMode::Zen => settings
+                    .zen_gravity
+                    .map_or(0.0, |level| ruleset.gravity.gravity(level)),
+                _ => ruleset.gravity.gravity(level),
+            },
+            gravity_progress: 0.0,
+            das_cut_timer: 0.0,
+            garbage_timer: 0.0,
+            garbage_interval: SURVIVAL_FIRST_ROW_TIME,
+            received_garbage: Vec::new(),
+            lock_timer: 0.0,
+            lock_resets: 0,
+            lowest_row: first_piece.top_left.y,
+            score: 0,
+            combo_chain: 0,
+            b2b_chain: 0,
+            cleared_lines: 0,
+            pieces_locked: 0,
+            piece_inputs: 0,
+            finesse_faults: 0,
+            lines_until_next_level: settings.marathon_level_rule.lines_for_level(level) as i32,
+            level,
+            game_over: false,
+            puzzle_goal: None,
+            puzzle_pieces_left: None,
+            puzzle_solved: false,
+            puzzle_failed: false,
+
+            events: Vec::new(),
+        };
+        core.apply_instant_gravity();
+        core
+    }
+
+    /// Creates a game of `puzzle`, played with `ruleset` and `handling`. The
+    /// game starts from the puzzle's well and hold, and deals only the pieces
+    /// the puzzle lists.
+    pub fn from_puzzle(puzzle: &Puzzle, ruleset: Ruleset, handling: Handling) -> Self {
+        let mut core = GameCore::new(
+            GameMode::Puzzle,
+            ModeSettings::default(),
+            ruleset,
+            handling,
+            0,
+        );
+        core.well = puzzle.well();
+        core.bag = PieceBag::with_sequence(&puzzle.pieces);
+        let first_type = core.bag.take_piece();
+        core.spawn_piece(first_type);
+        core.hold_piece_type = puzzle.hold;
+        core.puzzle_goal = Some(puzzle.goal);
+        core.puzzle_pieces_left = Some(puzzle.pieces.len() as u32 - 1);
+        core
+    }
+
+    /// Advance the game by `dt` using the current state of `input`. The delay
+    /// timers inside of `input` are advanced as a side effect. Returns
+    /// everything that happened during this step, in order.
+    pub fn step(&mut self, input: &mut InputState, dt: Duration) -> Vec<GameEvent> {
+        if !self.is_finished() {
+            self.elapsed_time += dt.as_subsec_millis();
+            if self.is_time_up() {
+                self.events.push(GameEvent::TimeUp);
+                return std::mem::take(&mut self.events);
+            }
+
+            self.count_piece_inputs(input);
+            self.handle_user_input(input, dt);
+            self.prev_input = *input;
+
+            // TODO: put behind option
+            self.handle_shadow_piece();
+
+            if !self.is_finished() {
+                self.handle_gravity(dt);
+            }
+            if !self.is_finished() && self.mode == GameMode::Survival {
+                self.handle_rising_garbage(dt);
+            }
+        }
+
+        std::mem::take(&mut self.events)
+    }
+
+    /// Locks the current piece as `lock` describes, as it was locked in a copy
+    /// of this game played elsewhere, such as by the opponent in a network
+    /// match. Returns everything that happened.
+    pub fn place_piece(&mut self, lock: &LockedPiece) -> Vec<GameEvent> {
+        if !self.is_finished() {
+            if lock.held {
+                self.handle_hold();
+            }
+
+            let piece = &mut self.current_piece;
+            for _ in 0..4 {
+                if piece.get_rotation_index() == lock.rotation {
+                    break;
+                }
+                piece.change_shape(1);
+            }
+            piece.top_left = Point2::new(lock.x as f32, lock.y as f32);
+            self.last_action_was_rotation = lock.kick.is_some();
+            self.last_kick_index = lock.kick.unwrap_or(0);
+
+            self.lock_piece();
+            self.handle_shadow_piece();
+        }
+
+        std::mem::take(&mut self.events)
+    }
+
+    /// Ends the game as if the player had topped out. Used when the opponent
+    /// in a network match tops out on their own machine.
+    pub fn concede(&mut self) {
+        self.game_over = true;
+    }
+
+    /// Returns where and how the last piece to lock was locked.
+    pub fn last_lock(&self) -> Option<LockedPiece> {
+        self.last_lock
+    }
+
+    /// Returns the playfield.
+    pub fn well(&self) -> &Well {
+        &self.well
+    }
+
+    /// Returns the piece currently controlled by the player.
+    pub fn current_piece(&self) -> &Piece {
+        &self.current_piece
+    }
+
+    /// Returns the type of the piece in the hold area, if there is one.
+    pub fn hold_piece_type(&self) -> Option<PieceType> {
+        self.hold_piece_type
+    }
+
+    /// Returns the type of the piece that will be taken next.
+    pub fn next_piece_type(&self) -> PieceType {
+        self.bag.peek_at_next_piece()
+    }
+
+    /// Returns the piece that will be taken next, in its spawn orientation.
+    /// Returns `None` once a puzzle has dealt all of its pieces.
+    pub fn next_piece(&self) -> Option<Piece> {
+        if self.puzzle_pieces_left == Some(0) {
+            return None;
+        }
+        Some(Piece::new(
+            self.next_piece_type(),
+            self.rotation_system.as_ref(),
+        ))
+    }
+
+    /// Returns the piece in the hold area in its spawn orientation, if there is
+    /// one.
+    pub fn hold_piece(&self) -> Option<Piece> {
+        self.hold_piece_type()
+            .map(|piece_type| Piece::new(piece_type, self.rotation_system.as_ref()))
+    }
+
+    /// Returns true if the current piece can be swapped into the hold area.
+    /// A piece taken from the hold cannot be held again until it locks.
+    pub fn can_hold(&self) -> bool {
+        self.can_hold
+    }
+
+    /// Returns the rotation system the game is played with.
+    pub fn rotation_system(&self) -> &dyn RotationSystem {
+        self.rotation_system.as_ref()
+    }
+
+    /// Returns the mode this game is played in.
+    pub fn mode(&self) -> GameMode {
+        self.mode
+    }
+
+    /// Returns the seed used to deal this game's pieces.
+    pub fn seed(&self) -> u64 {
+        self.bag.seed()
+    }
+
+    pub fn score(&self) -> u32 {
+        self.score
+    }
+
+    /// Returns the current combo: the number of line clears in a row, not
+    /// counting the first.
+    pub fn combo(&self) -> u32 {
+        self.combo_chain.saturating_sub(1)
+    }
+
+    /// Returns the current back-to-back count: the number of difficult line
+    /// clears (tetrises and T-spins) in a row, not counting the first.
+    pub fn back_to_back(&self) -> u32 {
+        self.b2b_chain.saturating_sub(1)
+    }
+
+    /// Returns the time, in seconds, the game has been played for.
+    pub fn elapsed_time(&self) -> f64 {
+        self.elapsed_time
+    }
+
+    /// Returns the time, in seconds, left before the game ends, if the mode
+    /// has a time limit.
+    pub fn time_remaining(&self) -> Option<f64> {
+        match self.mode {
+            GameMode::Ultra => Some((self.settings.ultra_time_limit - self.elapsed_time).max(0.0)),
+            GameMode::Marathon
+            | GameMode::Sprint
+            | GameMode::Dig
+            | GameMode::Survival
+            | GameMode::Zen
+            | GameMode::Puzzle
+            | GameMode::Versus => None,
+        }
+    }
+
+    /// Returns how close the well is to being flooded with garbage, from 0 to
+    /// 1, in modes where garbage rises without the player's say. In Survival
+    /// this is how close the next row is to rising. In Versus it is the
+    /// garbage waiting to rise, as a share of the visible well.
+    pub fn garbage_warning(&self) -> Option<f64> {
+        match self.mode {
+            GameMode::Survival => Some(self.garbage_timer / self.garbage_interval),
+            GameMode::Versus => {
+                let visible_rows = (HEIGHT - 2) as f64;
+                Some((f64::from(self.incoming_garbage()) / visible_rows).min(1.0))
+            }
+            _ => None,
+        }
+    }
+
+    /// Queues `rows` rows of garbage sent by an opponent. They rise the next
+    /// time a piece locks without clearing any lines, unless they are
+    /// cancelled out by garbage sent back first.
+    pub fn receive_garbage(&mut self, rows: u32) {
+        if rows > 0 {
+            self.received_garbage.push(rows);
+        }
+    }
+
+    /// Returns the number of rows of garbage waiting to rise.
+    pub fn incoming_garbage(&self) -> u32 {
+        self.received_garbage.iter().sum()
+    }
+
+    /// Returns the time, in seconds, at which every `LINES_PER_SPLIT` lines
+    /// were cleared.
+    pub fn splits(&self) -> &[f64] {
+        &self.splits
+    }
+
+    pub fn cleared_lines(&self) -> u32 {
+        self.cleared_lines
+    }
+
+    /// Returns the number of pieces that have been locked into the well.
+    pub fn pieces_locked(&self) -> u32 {
+        self.pieces_locked
+    }
+
+    /// Returns the number of inputs made beyond the fewest needed to place
+    /// each piece, as measured by `finesse::min_inputs`.
+    pub fn finesse_faults(&self) -> u32 {
+        self.finesse_faults
+    }
+
+    /// Returns the current level, starting at 0.
+    pub fn level(&self) -> u32 {
+        self.level
+    }
+
+    pub fn is_game_over(&self) -> bool {
+        self.game_over
+    }
+
+    /// Returns true if the goal of the game mode has been reached.
+    pub fn has_won(&self) -> bool {
+        match self.mode {
+            GameMode::Marathon => match self.settings.marathon_goal.lines() {
+                Some(goal) => self.cleared_lines >= goal,
+                None => false,
+            },
+            GameMode::Sprint => self.cleared_lines >= SPRINT_LINES,
+            GameMode::Ultra | GameMode::Survival | GameMode::Zen | GameMode::Versus => false,
+            GameMode::Puzzle => self.puzzle_solved,
+            GameMode::Dig => self.well.garbage_rows() == 0,
+        }
+    }
+
+    /// Returns true if the time limit of the game mode has run out.
+    pub fn is_time_up(&self) -> bool {
+        self.time_remaining() == Some(0.0)
+    }
+
+    /// Returns true if the game has ended, whether it was won, lost or ran
+    /// out of time.
+    pub fn is_finished(&self) -> bool {
+        self.is_game_over() || self.has_won() || self.is_time_up() || self.has_failed()
+    }
+
+    /// Returns true if a puzzle ran out of pieces before its goal was reached.
+    pub fn has_failed(&self) -> bool {
+        self.puzzle_failed
+    }
+
+    /// Count every new press of left, right or a rotation towards the inputs
+    /// used to place the current piece.
+    fn count_piece_inputs(&mut self, input: &InputState) {
+        let prev = &self.prev_input;
+        let presses = [
+            (input.left, prev.left),
+            (input.right, prev.right),
+            (input.rotate_clockwise, prev.rotate_clockwise),
+            (input.rotate_counterclockwise, prev.rotate_counterclockwise),
+        ];
+        self.piece_inputs += presses
+            .iter()
+            .filter(|(now, before)| now.is_active && !before.is_active)
+            .count() as u32;
+    }
+
+    fn handle_user_input(&mut self, input: &mut InputState, dt: Duration) {
+        let dt = dt.as_subsec_millis();
+        self.das_cut_timer = (self.das_cut_timer - dt).max(0.0);
+
+        if input.left.is_active {
+            let moves = self.tick_auto_shift(&mut input.left, dt);
+            self.repeat_move(Point2::new(-1.0, 0.0), moves);
+        } else if input.right.is_active {
+            let moves = self.tick_auto_shift(&mut input.right, dt);
+            self.repeat_move(Point2::new(1.0, 0.0), moves);
+        } else if input.soft_drop.is_active {
+            let drop_delay = if self.handling.is_instant_soft_drop() {
+                0.0
+            } else {
+                // soft drop still works when there is no gravity
+                let row_time = 1.0 / (self.gravity * FRAMES_PER_SECOND);
+                row_time.min(SLOWEST_SOFT_DROP_ROW_TIME) / self.handling.soft_drop_factor
+            };
+            let moves = input.soft_drop.tick(dt, 0.0, drop_delay);
+            self.repeat_move(Point2::new(0.0, 1.0), moves);
+        } else if input.rotate_clockwise.is_active {
+            if input.rotate_clockwise.is_active != self.prev_input.rotate_clockwise.is_active {
+                self.rotate_piece(1);
+            }
+        } else if input.rotate_counterclockwise.is_active {
+            if input.rotate_counterclockwise.is_active
+                != self.prev_input.rotate_counterclockwise.is_active
+            {
+                self.rotate_piece(-1);
+            }
+        } else if input.hard_drop.is_active {
+            if input.hard_drop.is_active != self.prev_input.hard_drop.is_active {
+                self.handle_shadow_piece();
+                if self.current_piece.get_shadow_position() != self.current_piece.top_left {
+                    self.last_action_was_rotation = false;
+                }
+                self.current_piece.top_left = self.current_piece.get_shadow_position();
+                self.current_piece.potential_top_left = self.current_piece.top_left;
+                self.events.push(GameEvent::HardDropped);
+                self.lock_piece();
+            }
+        } else if input.hold.is_active && input.hold.is_active != self.prev_input.hold.is_active {
+            self.handle_hold();
+        }
+    }
+
+    /// Advance the timers of a held left or right input using the DAS and ARR
+    /// of the player's handling. Returns the number of moves to make this step.
+    /// A charged DAS does not move a newly spawned piece until the DAS cut
+    /// delay has passed.
+    fn tick_auto_shift(&self, field: &mut InputStateField, dt: f64) -> u32 {
+        if self.das_cut_timer > 0.0 && field.is_charged(self.handling.das) {
+            return 0;
+        }
+
+        field.tick(dt, self.handling.das, self.handling.arr)
+    }
+
+    /// Move the current piece by `offset` up to `moves` times, stopping early
+    /// if it is blocked.
+    fn repeat_move(&mut self, offset: Point2, moves: u32) {
+        for _ in 0..moves {
+            if !self.move_piece(offset) {
+                break;
+            }
+        }
+    }
+
+    /// Attempt to move the current piece. Returns true if the piece moved.
+    fn move_piece(&mut self, potential_new_position: Point2) -> bool {
+        self.current_piece.potential_top_left.x += potential_new_position.x;
+        self.current_piece.potential_top_left.y += potential_new_position.y;
+
+        let current_shape = self.current_piece.get_shape();
+        let collision_found = self
+            .well
+            .check_for_collisions(&current_shape, self.current_piece.potential_top_left);
+
+        if collision_found {
+            self.current_piece.potential_top_left = self.current_piece.top_left;
+        } else {
+            self.last_action_was_rotation = false;
+            self.events.push(GameEvent::Moved);
+        }
+
+        self.current_piece.top_left = self.current_piece.potential_top_left; // advance tetromino
+        if !collision_found {
+            self.reset_lock_delay();
+        }
+
+        !collision_found
+    }
+
+    /// Attempt to rotate the current piece. Each of the kicks given by the
+    /// rotation system is tried in order until one fits.
+    fn rotate_piece(&mut self, direction: i32) {
+        let to = next_rotation_index(self.current_piece.get_rotation_index(), direction);
+        let next_shape = self.current_piece.get_next_shape(direction);
+        let kicks = self
+            .rotation_system
+            .kicks(&self.well, &self.current_piece, to);
+
+        for (kick_index, (kick_x, kick_y)) in kicks.iter().enumerate() {
+            let mut potential_position = self.current_piece.top_left;
+            potential_position.x += *kick_x as f32;
+            potential_position.y += *kick_y as f32;
+
+            if !self
+                .well
+                .check_for_collisions(&next_shape, potential_position)
+            {
+                self.current_piece.top_left = potential_position;
+                self.current_piece.potential_top_left = potential_position;
+                self.current_piece.change_shape(direction);
+                self.last_action_was_rotation = true;
+                self.last_kick_index = kick_index;
+                self.events.push(GameEvent::Rotated);
+                self.reset_lock_delay();
+                return;
+            }
+        }
+    }
+
+    /// Move the current piece down by however many whole rows gravity has
+    /// built up over `dt`, which may be none, or several at once. While the
+    /// piece rests on the stack, advance its lock delay and lock it once the
+    /// delay has passed.
+    fn handle_gravity(&mut self, dt: Duration) {
+        if self.gravity >= INSTANT_GRAVITY {
+            self.fall(HEIGHT as u32);
+        } else {
+            self.gravity_progress += self.gravity * dt.as_subsec_millis() * FRAMES_PER_SECOND;
+            let rows = self.gravity_progress.floor();
+            self.gravity_progress -= rows;
+            self.fall(rows as u32);
+        }
+
+        if self.is_grounded() {
+            // gravity does not build up while the piece has nowhere to fall
+            self.gravity_progress = 0.0;
+            self.lock_timer += dt.as_subsec_millis();
+            if self.lock_timer >= self.ruleset.lock_delay {
+                self.lock_piece();
+            }
+        }
+    }
+
+    /// Move the current piece down by up to `rows` rows, stopping once it
+    /// lands.
+    fn fall(&mut self, rows: u32) {
+        for _ in 0..rows {
+            if self.is_grounded() {
+                return;
+            }
+
+            self.current_piece.top_left.y += 1.0;
+            self.current_piece.potential_top_left = self.current_piece.top_left;
+            self.last_action_was_rotation = false;
+            self.reset_lock_delay();
+        }
+    }
+
+    /// Under 20G, drop the current piece straight onto the stack.
+    fn apply_instant_gravity(&mut self) {
+        if self.gravity >= INSTANT_GRAVITY {
+            self.fall(HEIGHT as u32);
+        }
+    }
+
+    /// Returns true if the current piece is resting on the stack or the floor.
+    fn is_grounded(&self) -> bool {
+        let mut below = self.current_piece.top_left;
+        below.y += 1.0;
+
+        self.well
+            .check_for_landing(&self.current_piece.get_shape(), below)
+    }
+
+    /// Apply the lock delay reset policy after the current piece has
+    /// successfully moved or rotated.
+    fn reset_lock_delay(&mut self) {
+        if self.current_piece.top_left.y > self.lowest_row {
+            self.lowest_row = self.current_piece.top_left.y;
+            if self.ruleset.lock_reset != LockReset::Classic {
+                self.lock_timer = 0.0;
+                self.lock_resets = 0;
+            }
+            return;
+        }
+
+        // only moves made while the lock delay is running count as resets
+        if self.lock_timer == 0.0 {
+            return;
+        }
+
+        match self.ruleset.lock_reset {
+            LockReset::Move => {
+                if self.lock_resets < MOVE_RESET_LIMIT {
+                    self.lock_timer = 0.0;
+                    self.lock_resets += 1;
+                }
+            }
+            LockReset::Infinity => self.lock_timer = 0.0,
+            LockReset::Step | LockReset::Classic => (),
+        }
+    }
+
+    /// Add the current piece to the well, clear any filled lines and take the
+    /// next piece from the bag. If any part of the piece is locked inside of
+    /// the vanish zone, the game is over instead, and so it is if the next
+    /// piece has no room to spawn. In modes with no top-out, the upper rows of
+    /// the stack are cleared away instead.
+    fn lock_piece(&mut self) {
+        let topped_out = self.current_piece.is_in_vanish_zone();
+        if topped_out && self.mode.can_top_out() {
+            self.game_over = true;
+            self.events.push(GameEvent::GameOver);
+            return;
+        }
+
+        let t_spin = if self.last_action_was_rotation {
+            detect_t_spin(&self.well, &self.current_piece, self.last_kick_index)
+        } else {
+            None
+        };
+
+        if let Some(fewest) =
+            finesse::min_inputs(&self.current_piece, self.rotation_system.as_ref())
+        {
+            self.finesse_faults += self.piece_inputs.saturating_sub(fewest);
+        }
+
+        let piece = &self.current_piece;
+        self.last_lock = Some(LockedPiece {
+            rotation: piece.get_rotation_index(),
+            x: piece.top_left.x as i32,
+            y: piece.top_left.y as i32,
+            held: !self.can_hold,
+            kick: if self.last_action_was_rotation {
+                Some(self.last_kick_index)
+            } else {
+                None
+            },
+        });
+
+        self.well.land(&self.current_piece);
+        self.pieces_locked += 1;
+        self.events.push(GameEvent::PieceLocked);
+        self.handle_line_clears(t_spin);
+        if self.game_over {
+            return;
+        }
+
+        // once a puzzle has dealt all of its pieces, the piece in hold is the
+        // last one left
+        let next_type = match self.take_next_piece() {
+            Some(piece_type) => piece_type,
+            None => match self.hold_piece_type.take() {
+                Some(piece_type) => piece_type,
+                None => {
+                    if !self.has_won() {
+                        self.puzzle_failed = true;
+                        self.events.push(GameEvent::PuzzleFailed);
+                    }
+                    return;
+                }
+            },
+        };
+        self.spawn_piece(next_type);
+        self.can_hold = true;
+        self.gravity_progress = 0.0;
+
+        let piece = &self.current_piece;
+        let spawn_blocked = self
+            .well
+            .check_for_collisions(&piece.get_shape(), piece.top_left);
+        if !self.mode.can_top_out() && (topped_out || spawn_blocked) {
+            self.well.clear_top_rows(TOP_OUT_CLEARED_ROWS);
+            self.events.push(GameEvent::StackCleared);
+        } else if spawn_blocked {
+            // block out: the new piece overlaps the stack
+            self.game_over = true;
+            self.events.push(GameEvent::GameOver);
+        }
+    }
+
+    /// Raise a row of garbage once enough time has passed, and make the next
+    /// row come sooner. The current piece is pushed up with the stack if the
+    /// new row would overlap it. The game is over if the stack is pushed out
+    /// of the top of the well, or the current piece has no room left.
+    fn handle_rising_garbage(&mut self, dt: Duration) {
+        self.garbage_timer += dt.as_subsec_millis();
+        if self.garbage_timer < self.garbage_interval {
+            return;
+        }
+
+        self.garbage_timer -= self.garbage_interval;
+        self.garbage_interval =
+            (self.garbage_interval * SURVIVAL_ROW_SPEED_UP).max(SURVIVAL_MIN_ROW_TIME);
+        let overflowed = self.well.push_garbage_row(self.garbage.next_hole());
+        self.events.push(GameEvent::GarbageRose);
+
+        let piece = &mut self.current_piece;
+        if self
+            .well
+            .check_for_collisions(&piece.get_shape(), piece.top_left)
+        {
+            piece.top_left.y -= 1.0;
+            piece.potential_top_left = piece.top_left;
+            self.lowest_row -= 1.0;
+        }
+        if overflowed
+            || self
+                .well
+                .check_for_collisions(&piece.get_shape(), piece.top_left)
+        {
+            self.game_over = true;
+            self.events.push(GameEvent::GameOver);
+        }
+    }
+
+    /// Returns the rows of garbage sent for a lock that cleared
+    /// `lines_cleared` lines. Called once the combo and back-to-back chains
+    /// include the lock.
+    fn attack(&self, t_spin: Option<TSpin>, lines_cleared: u32) -> u32 {
+        let lines = lines_cleared as usize;
+        let mut rows = match t_spin {
+            Some(TSpin::Full) => T_SPIN_ATTACK[lines.min(T_SPIN_ATTACK.len() - 1)],
+            _ => LINE_CLEAR_ATTACK[lines.min(LINE_CLEAR_ATTACK.len() - 1)],
+        };
+        if self.b2b_chain >= 2 {
+            rows += BACK_TO_BACK_ATTACK;
+        }
+        if self.well.is_empty() {
+            rows += PERFECT_CLEAR_ATTACK;
+        }
+        rows + COMBO_ATTACK[(self.combo() as usize).min(COMBO_ATTACK.len() - 1)]
+    }
+
+    /// Uses `rows` rows of garbage to cancel out received garbage, oldest
+    /// first, and sends whatever is left to the opponent.
+    fn send_garbage(&mut self, mut rows: u32) {
+        while rows > 0 && !self.received_garbage.is_empty() {
+            let cancelled = rows.min(self.received_garbage[0]);
+            rows -= cancelled;
+            self.received_garbage[0] -= cancelled;
+            if self.received_garbage[0] == 0 {
+                self.received_garbage.remove(0);
+            }
+        }
+
+        if rows > 0 {
+            self.events.push(GameEvent::GarbageSent(rows));
+        }
+    }
+
+    /// Raise all of the garbage sent by an opponent. The rows of each attack
+    /// share a hole. The game is over if the stack is pushed out of the top of
+    /// the well.
+    fn raise_received_garbage(&mut self) {
+        let mut overflowed = false;
+        for rows in std::mem::take(&mut self.received_garbage) {
+            let hole = self.garbage.next_hole();
+            for _ in 0..rows {
+                overflowed |= self.well.push_garbage_row(hole);
+                self.events.push(GameEvent::GarbageRose);
+            }
+        }
+
+        if overflowed {
+            self.game_over = true;
+            self.events.push(GameEvent::GameOver);
+        }
+    }
+
+    /// Makes a new piece of `piece_type` the current piece.
+    fn spawn_piece(&mut self, piece_type: PieceType) {
+        self.current_piece = Piece::new(piece_type, self.rotation_system.as_ref());
+        self.last_action_was_rotation = false;
+        self.last_kick_index = 0;
+        self.lock_timer = 0.0;
+        self.lock_resets = 0;
+        self.lowest_row = self.current_piece.top_left.y;
+        self.das_cut_timer = self.handling.das_cut;
+        self.piece_inputs = 0;
+        self.apply_instant_gravity();
+    }
+
+    /// Calculate the position of the shadow piece.
+    fn handle_shadow_piece(&mut self) {
+        let mut shadow_position = self.current_piece.top_left;
+        let mut potential_shadow_position = shadow_position;
+        loop {
+            potential_shadow_position.y += 1.0;
+            let collision_found = self
+                .well
+                .check_for_landing(&self.current_piece.get_shape(), potential_shadow_position);
+
+            if collision_found {
+                break;
+            }
+
+            shadow_position = potential_shadow_position;
+        }
+
+        self.current_piece.set_shadow_position(shadow_position);
+    }
+
+    /// Will attempt to add the current piece to the 'Hold' area. Cannot perform
+    /// a hold if piece in the Hold area is the same as the current piece.
+    fn handle_hold(&mut self) {
+        // can only perform a hold once per piece turn
+        // a piece turn ends when the current piece lands
+        if self.can_hold {
+            let current_type = self.current_piece.get_type();
+            if let Some(piece_type) = self.hold_piece_type {
+                if piece_type != self.current_piece.get_type() {
+                    self.spawn_piece(piece_type);
+                    self.hold_piece_type = Some(current_type);
+                    self.can_hold = false;
+                    self.events.push(GameEvent::Held);
+                }
+            } else if let Some(next_type) = self.take_next_piece() {
+                self.spawn_piece(next_type);
+                self.hold_piece_type = Some(current_type);
+                self.can_hold = false;
+                self.events.push(GameEvent::Held);
+            }
+        }
+    }
+
+    /// Takes the next piece from the bag. Returns `None` once a puzzle has
+    /// dealt all of its pieces.
+    fn take_next_piece(&mut self) -> Option<PieceType> {
+        match self.puzzle_pieces_left {
+            None => Some(self.bag.take_piece()),
+            Some(0) => None,
+            Some(left) => {
+                self.puzzle_pieces_left = Some(left - 1);
+                Some(self.bag.take_piece())
+            }
+        }
+    }
+
+    /// Check for filled rows and asks the well to clear them. Adds the number
+    /// of lines cleared to `cleared_lines`. Also will increase the level if the
+    /// next level threshold has been met. T-spins are scored even when they do
+    /// not clear any lines.
+    fn handle_line_clears(&mut self, t_spin: Option<TSpin>) {
+        let lines_cleared: u32 = self.well.clear_lines();
+        if let Some(goal) = self.puzzle_goal {
+            let total_lines = self.cleared_lines + lines_cleared;
+            if goal.is_met(&self.well, t_spin, lines_cleared, total_lines) {
+                self.puzzle_solved = true;
+            }
+        }
+
+        if let Some(kind) = t_spin {
+            self.events.push(GameEvent::TSpin(kind, lines_cleared));
+        }
+
+        // a lock that doesn't clear anything breaks the combo, but not the
+        // back-to-back chain, and lets any received garbage rise
+        if lines_cleared == 0 {
+            self.combo_chain = 0;
+            self.raise_received_garbage();
+        } else {
+            self.combo_chain += 1;
+            self.events.push(GameEvent::LinesCleared(lines_cleared));
+        }
+        if lines_cleared == 0 && t_spin.is_none() {
+            return;
+        }
+
+        // add to score
+        let level = self.level;
+        let base_score = match (t_spin, lines_cleared) {
+            (Some(TSpin::Mini), 0) => 100,
+            (Some(TSpin::Mini), 1) => 200,
+            (Some(TSpin::Mini), 2) => 400,
+            (Some(TSpin::Full), 0) => 400,
+            (Some(TSpin::Full), 1) => 800,
+            (Some(TSpin::Full), 2) => 1200,
+            (Some(TSpin::Full), 3) => 1600,
+            (_, 1) => 40,
+            (_, 2) => 100,
+            (_, 3) => 300,
+            (_, 4) => 1200,
+            _ => 0,
+        };
+
+        // tetrises and T-spins that clear lines are "difficult" clears. Two or
+        // more in a row are worth 1.5x, any other line clear ends the chain
+        if lines_cleared > 0 {
+            if lines_cleared == 4 || t_spin.is_some() {
+                self.b2b_chain += 1;
+            } else {
+                self.b2b_chain = 0;
+            }
+        }
+        if lines_cleared > 0 && self.b2b_chain >= 2 {
+            self.increase_score(base_score * 3 / 2, level);
+        } else {
+            self.increase_score(base_score, level);
+        }
+
+        if self.combo() > 0 {
+            self.increase_score(COMBO_BONUS * self.combo(), level);
+        }
+
+        if self.mode == GameMode::Versus && lines_cleared > 0 {
+            let rows = self.attack(t_spin, lines_cleared);
+            self.send_garbage(rows);
+        }
+
+        if self.mode.has_levels() {
+            self.lines_until_next_level -= lines_cleared as i32;
+            if self.lines_until_next_level <= 0 {
+                self.increase_level();
+                self.lines_until_next_level +=
+                    self.settings
+                        .marathon_level_rule
+                        .lines_for_level(self.level) as i32;
+            }
+        }
+        self.cleared_lines += lines_cleared;
+
+        while (self.splits.len() as u32 + 1) * LINES_PER_SPLIT <= self.cleared_lines {
+            self.splits.push(self.elapsed_time);
+            self.events.push(GameEvent::Split(self.splits.len() - 1));
+        }
+        if self.has_won() {
+            self.events.push(GameEvent::Won);
+        }
+    }
+
+    /// Calculate the score increase based on current level and base score for
+    /// the number of lines cleared.
+    fn increase_score(&mut self, base_score: u32, level: u32) {
+        self.score += base_score * (level + 1);
+    }
+
+    /// Increase the level and adjust the current rate at which pieces drop.
+    fn increase_level(&mut self) {
+        self.level += 1;
+        self.events.push(GameEvent::LevelUp(self.level));
+        self.gravity = self.ruleset.gravity.gravity(self.level);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{GameCore, GameEvent, COMBO_BONUS, MOVE_RESET_LIMIT};
+    use crate::states::play_state::game_mode::{GameMode, LevelRule, MarathonGoal, ModeSettings};
+    use crate::states::play_state::gravity::GravityCurve;
+    use crate::states::play_state::handling::Handling;
+    use crate::states::play_state::input_state::{InputAction, InputState};
+    use crate::states::play_state::puzzle::Puzzle;
+    use crate::states::play_state::ruleset::{LockReset, Ruleset};
+    use crate::states::play_state::t_spin::TSpin;
+    use crate::states::play_state::well::Well;
+    use std::time::Duration;
+
+    const FRAME: Duration = Duration::from_millis(16);
+
+    /// Press and release hard drop over two steps.
+    fn hard_drop(core: &mut GameCore, input: &mut InputState) -> Vec<GameEvent> {
+        input.hard_drop.is_active = true;
+        let mut events = core.step(input, FRAME);
+        input.hard_drop.reset();
+        events.extend(core.step(input, FRAME));
+        events
+    }
+
+    /// Press and release `action` over two steps.
+    fn tap(core: &mut GameCore, input: &mut InputState, action: InputAction) -> Vec<GameEvent> {
+        input.set_action(action, true);
+        let mut events = core.step(input, FRAME);
+        input.set_action(action, false);
+        events.extend(core.step(input, FRAME));
+        events
+    }
+
+    /// Hold soft drop until the current piece reaches the stack.
+    fn soft_drop_to_the_stack(core: &mut GameCore, input: &mut InputState) {
+        input.soft_drop.is_active = true;
+        loop {
+            core.step(input, FRAME);
+            let piece = core.current_piece();
+            if piece.top_left == piece.get_shadow_position() {
+                break;
+            }
+        }
+        input.soft_drop.reset();
+    }
+
+    /// Step without any input until the current piece locks. Returns the
+    /// number of frames that took.
+    fn frames_until_locked(core: &mut GameCore, input: &mut InputState) -> u32 {
+        let mut frames = 1;
+        while !core.step(input, FRAME).contains(&GameEvent::PieceLocked) {
+            frames += 1;
+        }
+        frames
+    }
+
+    /// Move the current piece left and right until it locks or `max_frames`
+    /// have passed. Returns the number of frames it took to lock, if it did.
+    fn wiggle_until_locked(
+        core: &mut GameCore,
+        input: &mut InputState,
+        max_frames: u32,
+    ) -> Option<u32> {
+        let mut frames = 0;
+        while frames < max_frames {
+            let action = if frames % 4 == 0 {
+                InputAction::Left
+            } else {
+                InputAction::Right
+            };
+            frames += 2;
+            if tap(core, input, action).contains(&GameEvent::PieceLocked) {
+                return Some(frames);
+            }
+        }
+        None
+    }
+
+    fn core_with_lock_reset(lock_reset: LockReset) -> GameCore {
+        let ruleset = Ruleset {
+            lock_reset,
+            ..Ruleset::default()
+        };
+        GameCore::new(
+            GameMode::Marathon,
+            ModeSettings::default(),
+            ruleset,
+            Handling::default(),
+            0,
+        )
+    }
+
+    /// Returns a game where an O piece lands on a ledge, and can slide off
+    /// it to the right.
+    fn core_with_ledge(lock_reset: LockReset) -> GameCore {
+        let ruleset = Ruleset {
+            lock_reset,
+            ..Ruleset::default()
+        };
+        let text = "name Ledge\ngoal lines 100\npieces O\nwell\n....#.....\n";
+        let puzzle = Puzzle::read_from(&mut text.as_bytes()).unwrap();
+        GameCore::from_puzzle(&puzzle, ruleset, Handling::default())
+    }
+
+    /// Land the piece of a `core_with_ledge` game on the ledge, use up most of
+    /// the lock delay, then slide it off the ledge and onto the floor.
+    fn fall_off_the_ledge(core: &mut GameCore, input: &mut InputState) {
+        soft_drop_to_the_stack(core, input);
+        for _ in 0..20 {
+            core.step(input, FRAME);
+        }
+        tap(core, input, InputAction::Right);
+        soft_drop_to_the_stack(core, input);
+    }
+
+    /// Stand the current I piece upright and move it over to the last column.
+    fn move_i_to_last_column(core: &mut GameCore, input: &mut InputState) {
+        tap(core, input, InputAction::RotateClockwise);
+        for _ in 0..5 {
+            tap(core, input, InputAction::Right);
+        }
+    }
+
+    #[test]
+    fn gravity_moves_piece_down() {
+        let mut core = GameCore::new(
+            GameMode::Marathon,
+            ModeSettings::default(),
+            Ruleset::default(),
+            Handling::default(),
+            0,
+        );
+        let mut input = InputState::default();
+        let start_y = core.current_piece().top_left.y;
+
+        for _ in 0..70 {
+            core.step(&mut input, FRAME);
+        }
+
+        assert_eq!(start_y + 1.0, core.current_piece().top_left.y);
+    }
+
+    #[test]
+    fn hard_drop_locks_piece_and_takes_next() {
+        let mut core = GameCore::new(
+            GameMode::Marathon,
+            ModeSettings::default(),
+            Ruleset::default(),
+            Handling::default(),
+            0,
+        );
+        let mut input = InputState::default();
+        let next = core.next_piece_type();
+
+        let events = hard_drop(&mut core, &mut input);
+
+        assert!(events.contains(&GameEvent::HardDropped));
+        assert!(events.contains(&GameEvent::PieceLocked));
+        assert_eq!(next, core.current_piece().get_type());
+    }
+
+    #[test]
+    fn hold_only_once_per_piece() {
+        let mut core = GameCore::new(
+            GameMode::Marathon,
+            ModeSettings::default(),
+            Ruleset::default(),
+            Handling::default(),
+            0,
+        );
+        let mut input = InputState::default();
+        let first = core.current_piece().get_type();
+
+        input.hold.is_active = true;
+        assert_eq!(vec![GameEvent::Held], core.step(&mut input, FRAME));
+        input.hold.reset();
+        core.step(&mut input, FRAME);
+        input.hold.is_active = true;
+        assert!(core.step(&mut input, FRAME).is_empty());
+
+        assert_eq!(Some(first), core.hold_piece_type());
+    }
+
+    #[test]
+    fn grounded_piece_locks_after_lock_delay() {
+        let mut core = GameCore::new(
+            GameMode::Marathon,
+            ModeSettings::default(),
+            Ruleset::default(),
+            Handling::default(),
+            0,
+        );
+        let mut input = InputState::default();
+
+        soft_drop_to_the_stack(&mut core, &mut input);
+        let frames = frames_until_locked(&mut core, &mut input) - 1;
+
+        // 0.5 seconds at 16ms per frame
+        assert!(
+            (30..=32).contains(&frames),
+            "locked after {} frames",
+            frames
+        );
+    }
+
+    #[test]
+    fn move_reset_stops_after_fifteen_resets() {
+        let mut core = core_with_lock_reset(LockReset::Move);
+        let mut input = InputState::default();
+
+        soft_drop_to_the_stack(&mut core, &mut input);
+        let frames = wiggle_until_locked(&mut core, &mut input, 1000).unwrap();
+
+        // every move resets the 31 frame delay until the resets run out
+        let resets_frames = MOVE_RESET_LIMIT * 2;
+        assert!(
+            (resets_frames + 28..=resets_frames + 34).contains(&frames),
+            "locked after {} frames",
+            frames
+        );
+    }
+
+    #[test]
+    fn step_reset_only_resets_on_reaching_a_lower_row() {
+        let mut core = core_with_lock_reset(LockReset::Step);
+        let mut input = InputState::default();
+
+        // moving along the floor does not reset the delay
+        soft_drop_to_the_stack(&mut core, &mut input);
+        let frames = wiggle_until_locked(&mut core, &mut input, 1000).unwrap();
+        assert!(frames <= 34, "locked after {} frames", frames);
+
+        // falling a row gives the whole delay back
+        let mut core = core_with_ledge(LockReset::Step);
+        fall_off_the_ledge(&mut core, &mut input);
+        let frames = frames_until_locked(&mut core, &mut input);
+        assert!(frames >= 28, "locked after {} frames", frames);
+    }
+
+    #[test]
+    fn classic_reset_never_resets() {
+        let mut core = core_with_ledge(LockReset::Classic);
+        let mut input = InputState::default();
+
+        // even falling a row keeps the time already used up
+        fall_off_the_ledge(&mut core, &mut input);
+        let frames = frames_until_locked(&mut core, &mut input);
+        assert!(frames <= 12, "locked after {} frames", frames);
+    }
+
+    #[test]
+    fn infinity_reset_never_locks_while_the_piece_keeps_moving() {
+        let mut core = core_with_lock_reset(LockReset::Infinity);
+        let mut input = InputState::default();
+
+        soft_drop_to_the_stack(&mut core, &mut input);
+        assert_eq!(None, wiggle_until_locked(&mut core, &mut input, 600));
+        let frames = frames_until_locked(&mut core, &mut input);
+        assert!(frames <= 32, "locked after {} frames", frames);
+    }
+
+    #[test]
+    fn zero_arr_shifts_piece_to_the_wall_once_das_is_charged() {
+        let handling = Handling {
+            das: 0.1,
+            arr: 0.0,
+            ..Handling::default()
+        };
+        let mut core = GameCore::new(
+            GameMode::Marathon,
+            ModeSettings::default(),
+            Ruleset::default(),
+            handling,
+            0,
+        );
+        let mut input = InputState::default();
+        let spawn_x = core.current_piece().top_left.x;
+
+        input.left.is_active = true;
+        core.step(&mut input, FRAME);
+        assert_eq!(spawn_x - 1.0, core.current_piece().top_left.x);
+
+        // 6 frames of 16ms charges a 100ms DAS
+        for _ in 0..6 {
+            core.step(&mut input, FRAME);
+        }
+        let piece = core.current_piece();
+        let mut beside = piece.top_left;
+        beside.x -= 1.0;
+        assert!(core.well().check_for_collisions(&piece.get_shape(), beside));
+    }
+
+    #[test]
+    fn stacking_in_the_middle_tops_out() {
+        let mut core = GameCore::new(
+            GameMode::Marathon,
+            ModeSettings::default(),
+            Ruleset::default(),
+            Handling::default(),
+            0,
+        );
+        let mut input = InputState::default();
+
+        for _ in 0..30 {
+            hard_drop(&mut core, &mut input);
+        }
+
+        assert!(core.is_game_over());
+    }
+
+    #[test]
+    fn spawning_into_the_stack_tops_out() {
+        // the I piece locks in the first visible row, right where the O spawns
+        let mut text = "name Block Out\ngoal lines 100\npieces I O\nwell\n".to_string();
+        for _ in 0..19 {
+            text.push_str("...####...\n");
+        }
+        let puzzle = Puzzle::read_from(&mut text.as_bytes()).unwrap();
+        let mut core = GameCore::from_puzzle(&puzzle, Ruleset::default(), Handling::default());
+        let mut input = InputState::default();
+
+        let events = hard_drop(&mut core, &mut input);
+
+        assert!(events.contains(&GameEvent::PieceLocked));
+        assert!(events.contains(&GameEvent::GameOver));
+        assert!(core.is_game_over());
+    }
+
+    #[test]
+    fn sprint_is_won_at_forty_lines_with_a_split_every_ten() {
+        let mut core = GameCore::new(
+            GameMode::Sprint,
+            ModeSettings::default(),
+            Ruleset::default(),
+            Handling::default(),
+            0,
+        );
+        let mut input = InputState::default();
+        core.well = Well::from_rows(&["##########"]);
+        core.cleared_lines = 39;
+        core.splits = vec![10.0, 20.0, 30.0];
+
+        let events = hard_drop(&mut core, &mut input);
+
+        assert!(events.contains(&GameEvent::Split(3)));
+        assert!(events.contains(&GameEvent::Won));
+        assert!(core.has_won());
+        assert_eq!(0, core.level());
+        assert_eq!(4, core.splits().len());
+    }
+
+    #[test]
+    fn wasted_inputs_count_as_finesse_faults() {
+        let mut core = GameCore::new(
+            GameMode::Marathon,
+            ModeSettings::default(),
+            Ruleset::default(),
+            Handling::default(),
+            0,
+        );
+        let mut input = InputState::default();
+
+        hard_drop(&mut core, &mut input);
+        assert_eq!(0, core.finesse_faults());
+
+        // tapping left then right puts the piece back where it spawned
+        for field in [InputAction::Left, InputAction::Right].iter() {
+            input.set_action(*field, true);
+            core.step(&mut input, FRAME);
+            input.set_action(*field, false);
+            core.step(&mut input, FRAME);
+        }
+        hard_drop(&mut core, &mut input);
+        assert_eq!(2, core.finesse_faults());
+        assert_eq!(2, core.pieces_locked());
+    }
+
+    #[test]
+    fn ultra_ends_when_time_runs_out() {
+        let settings = ModeSettings {
+            ultra_time_limit: 1.0,
+            ..ModeSettings::default()
+        };
+        let mut core = GameCore::new(
+            GameMode::Ultra,
+            settings,
+            Ruleset::default(),
+            Handling::default(),
+            0,
+        );
+        let mut input = InputState::default();
+
+        let mut frames = 0;
+        while !core.step(&mut input, FRAME).contains(&GameEvent::TimeUp) {
+            frames += 1;
+        }
+
+        // 1 second at 16ms per frame
+        assert_eq!(62, frames);
+        assert!(core.is_finished());
+        assert!(!core.has_won());
+        assert!(!core.is_game_over());
+    }
+
+    #[test]
+    fn survival_garbage_rises_sooner_each_time() {
+        let mut core = GameCore::new(
+            GameMode::Survival,
+            ModeSettings::default(),
+            Ruleset::default(),
+            Handling::default(),
+            0,
+        );
+        let mut input = InputState::default();
+
+        let mut rows_at = Vec::new();
+        for frame in 0..60 * 20 {
+            if core
+                .step(&mut input, FRAME)
+                .contains(&GameEvent::GarbageRose)
+            {
+                rows_at.push(frame);
+            }
+            if rows_at.len() == 2 {
+                break;
+            }
+        }
+
+        assert_eq!(2, core.well().garbage_rows());
+        assert!(rows_at[1] - rows_at[0] < rows_at[0]);
+    }
+
+    #[test]
+    fn piece_bumped_by_rising_garbage_moves_from_where_it_was_pushed() {
+        let mut core = GameCore::new(
+            GameMode::Survival,
+            ModeSettings::default(),
+            Ruleset::default(),
+            Handling::default(),
+            0,
+        );
+        let mut input = InputState::default();
+        soft_drop_to_the_stack(&mut core, &mut input);
+        let landed = core.current_piece().top_left;
+
+        // the next row rises under the piece resting on the floor
+        core.garbage_timer = core.garbage_interval;
+        assert!(core
+            .step(&mut input, FRAME)
+            .contains(&GameEvent::GarbageRose));
+        assert_eq!(landed.y - 1.0, core.current_piece().top_left.y);
+
+        tap(&mut core, &mut input, InputAction::Right);
+
+        assert_eq!(landed.x + 1.0, core.current_piece().top_left.x);
+        assert_eq!(landed.y - 1.0, core.current_piece().top_left.y);
+    }
+
+    #[test]
+    fn gravity_can_move_several_rows_in_one_frame() {
+        let ruleset = Ruleset {
+            gravity: GravityCurve::Master,
+            ..Ruleset::default()
+        };
+        let settings = ModeSettings {
+            marathon_start_level: 8,
+            ..ModeSettings::default()
+        };
+        let mut core = GameCore::new(
+            GameMode::Marathon,
+            settings,
+            ruleset,
+            Handling::default(),
+            0,
+        );
+        let mut input = InputState::default();
+        let start_y = core.current_piece().top_left.y;
+
+        // 3G over 16ms is 2.88 rows
+        core.step(&mut input, FRAME);
+        assert_eq!(start_y + 2.0, core.current_piece().top_left.y);
+    }
+
+    #[test]
+    fn instant_gravity_puts_pieces_straight_on_the_stack() {
+        let ruleset = Ruleset {
+            gravity: GravityCurve::Instant,
+            ..Ruleset::default()
+        };
+        let mut core = GameCore::new(
+            GameMode::Marathon,
+            ModeSettings::default(),
+            ruleset,
+            Handling::default(),
+            0,
+        );
+        let mut input = InputState::default();
+        assert!(core.is_grounded());
+
+        hard_drop(&mut core, &mut input);
+        assert!(core.is_grounded());
+    }
+
+    #[test]
+    fn zen_clears_the_stack_instead_of_topping_out() {
+        let settings = ModeSettings {
+            zen_gravity: None,
+            ..ModeSettings::default()
+        };
+        let mut core = GameCore::new(
+            GameMode::Zen,
+            settings,
+            Ruleset::default(),
+            Handling::default(),
+            0,
+        );
+        let mut input = InputState::default();
+
+        let mut cleared = false;
+        for _ in 0..30 {
+            cleared |= hard_drop(&mut core, &mut input).contains(&GameEvent::StackCleared);
+        }
+
+        assert!(cleared);
+        assert!(!core.is_finished());
+    }
+
+    #[test]
+    fn dig_starts_with_garbage() {
+        let settings = ModeSettings {
+            dig_rows: 6,
+            ..ModeSettings::default()
+        };
+        let core = GameCore::new(
+            GameMode::Dig,
+            settings,
+            Ruleset::default(),
+            Handling::default(),
+            0,
+        );
+
+        assert_eq!(6, core.well().garbage_rows());
+        assert!(!core.has_won());
+    }
+
+    #[test]
+    fn marathon_starts_at_the_chosen_level() {
+        let settings = ModeSettings {
+            marathon_start_level: 4,
+            marathon_goal: MarathonGoal::Endless,
+            marathon_level_rule: LevelRule::Variable,
+            ..ModeSettings::default()
+        };
+        let core = GameCore::new(
+            GameMode::Marathon,
+            settings,
+            Ruleset::default(),
+            Handling::default(),
+            0,
+        );
+
+        assert_eq!(4, core.level());
+        assert_eq!(GravityCurve::default().gravity(4), core.gravity);
+        assert_eq!(25, core.lines_until_next_level);
+        assert!(!core.has_won());
+    }
+
+    #[test]
+    fn received_garbage_rises_when_a_piece_locks_without_clearing() {
+        let mut core = GameCore::new(
+            GameMode::Versus,
+            ModeSettings::default(),
+            Ruleset::default(),
+            Handling::default(),
+            0,
+        );
+        let mut input = InputState::default();
+
+        core.receive_garbage(2);
+        core.receive_garbage(1);
+        assert_eq!(3, core.incoming_garbage());
+
+        let events = hard_drop(&mut core, &mut input);
+        let risen = events.iter().filter(|e| **e == GameEvent::GarbageRose);
+        assert_eq!(3, risen.count());
+        assert_eq!(3, core.well.garbage_rows());
+        assert_eq!(0, core.incoming_garbage());
+    }
+
+    #[test]
+    fn received_garbage_pushing_the_stack_into_the_spawn_area_tops_out() {
+        let mut core = GameCore::new(
+            GameMode::Versus,
+            ModeSettings::default(),
+            Ruleset::default(),
+            Handling::default(),
+            0,
+        );
+        let mut input = InputState::default();
+        // a column under the spawn area, two rows short of where pieces spawn
+        core.well = Well::from_rows(&["....#....."; 18]);
+        core.receive_garbage(2);
+
+        for _ in 0..3 {
+            tap(&mut core, &mut input, InputAction::Left);
+        }
+        let events = hard_drop(&mut core, &mut input);
+
+        assert!(events.contains(&GameEvent::GameOver));
+        assert!(core.is_game_over());
+    }
+
+    #[test]
+    fn sent_garbage_cancels_received_garbage_first() {
+        let mut core = GameCore::new(
+            GameMode::Versus,
+            ModeSettings::default(),
+            Ruleset::default(),
+            Handling::default(),
+            0,
+        );
+        core.receive_garbage(1);
+        core.receive_garbage(2);
+
+        core.send_garbage(2);
+        assert_eq!(1, core.incoming_garbage());
+        assert!(core.events.is_empty());
+
+        core.send_garbage(4);
+        assert_eq!(0, core.incoming_garbage());
+        assert_eq!(vec![GameEvent::GarbageSent(3)], core.events);
+    }
+
+    #[test]
+    fn placing_each_lock_mirrors_the_game() {
+        let new_core = || {
+            GameCore::new(
+                GameMode::Versus,
+                ModeSettings::default(),
+                Ruleset::default(),
+                Handling::default(),
+                7,
+            )
+        };
+        let mut core = new_core();
+        let mut mirror = new_core();
+        let mut input = InputState::default();
+        let mut tap = |core: &mut GameCore, action: InputAction| {
+            input.set_action(action, true);
+            core.step(&mut input, FRAME);
+            input.set_action(action, false);
+            core.step(&mut input, FRAME);
+        };
+
+        for turn in 0..12 {
+            if turn % 5 == 0 {
+                tap(&mut core, InputAction::Hold);
+            }
+            if turn % 2 == 0 {
+                tap(&mut core, InputAction::RotateClockwise);
+            }
+            // spread the pieces across the well
+            let direction = if turn % 2 == 0 {
+                InputAction::Left
+            } else {
+                InputAction::Right
+            };
+            for _ in 0..turn % 5 {
+                tap(&mut core, direction);
+            }
+            tap(&mut core, InputAction::HardDrop);
+            mirror.place_piece(&core.last_lock().unwrap());
+        }
+
+        assert!(!core.is_game_over());
+        assert_eq!(core.well(), mirror.well());
+        assert_eq!(core.hold_piece_type(), mirror.hold_piece_type());
+        assert_eq!(core.pieces_locked(), mirror.pieces_locked());
+    }
+
+    #[test]
+    fn puzzles_end_when_solved_or_out_of_pieces() {
+        let text = "name Test\ngoal clear_all\npieces O O\nwell\n####....##\n####....##\n";
+        let puzzle = Puzzle::read_from(&mut text.as_bytes()).unwrap();
+        let mut input = InputState::default();
+
+        // two pieces dropped in the same place leave the well half full
+        let mut core = GameCore::from_puzzle(&puzzle, Ruleset::default(), Handling::default());
+        hard_drop(&mut core, &mut input);
+        assert!(!core.is_finished());
+        assert!(hard_drop(&mut core, &mut input).contains(&GameEvent::PuzzleFailed));
+        assert!(core.has_failed());
+
+        let mut core = GameCore::from_puzzle(&puzzle, Ruleset::default(), Handling::default());
+        hard_drop(&mut core, &mut input);
+        for _ in 0..2 {
+            input.right.is_active = true;
+            core.step(&mut input, FRAME);
+            input.right.reset();
+        }
+        assert!(hard_drop(&mut core, &mut input).contains(&GameEvent::Won));
+        assert!(core.has_won());
+        assert!(!core.has_failed());
+    }
+
+    #[test]
+    fn combo_resets_on_a_lock_that_clears_nothing() {
+        let text = "name Combo\ngoal lines 100\npieces O O O\nwell\n\
+                    ####..####\n####..####\n####..####\n####..####\n";
+        let puzzle = Puzzle::read_from(&mut text.as_bytes()).unwrap();
+        let mut core = GameCore::from_puzzle(&puzzle, Ruleset::default(), Handling::default());
+        let mut input = InputState::default();
+
+        hard_drop(&mut core, &mut input);
+        assert_eq!(0, core.combo());
+        hard_drop(&mut core, &mut input);
+        assert_eq!(1, core.combo());
+
+        // the well is empty, so the last piece clears nothing
+        hard_drop(&mut core, &mut input);
+        assert_eq!(0, core.combo());
+    }
+
+    #[test]
+    fn back_to_back_tetrises_score_half_again_until_a_single_breaks_the_chain() {
+        let text = "name B2B\ngoal lines 100\npieces I I I\nwell\n######....\n\
+                    #########.\n#########.\n#########.\n#########.\n\
+                    #########.\n#########.\n#########.\n#########.\n";
+        let puzzle = Puzzle::read_from(&mut text.as_bytes()).unwrap();
+        let mut core = GameCore::from_puzzle(&puzzle, Ruleset::default(), Handling::default());
+        let mut input = InputState::default();
+        let multiplier = core.level() + 1;
+
+        move_i_to_last_column(&mut core, &mut input);
+        hard_drop(&mut core, &mut input);
+        assert_eq!(1200 * multiplier, core.score());
+        assert_eq!(0, core.back_to_back());
+
+        // the second clear in a row also scores a combo
+        move_i_to_last_column(&mut core, &mut input);
+        hard_drop(&mut core, &mut input);
+        assert_eq!(1, core.back_to_back());
+        assert_eq!((1200 + 1800 + COMBO_BONUS) * multiplier, core.score());
+
+        // lying flat against the right wall, the last I clears a single
+        for _ in 0..3 {
+            tap(&mut core, &mut input, InputAction::Right);
+        }
+        hard_drop(&mut core, &mut input);
+        assert_eq!(0, core.back_to_back());
+        assert_eq!(
+            (1200 + 1800 + COMBO_BONUS + 40 + COMBO_BONUS * 2) * multiplier,
+            core.score()
+        );
+    }
+
+    #[test]
+    fn back_to_back_t_spins_score_half_again() {
+        // two T-spin double slots, one above the other
+        let text = "name B2B\ngoal lines 100\npieces T T\nwell\n\
+                    ..#.......\n##...#####\n###.######\n\
+                    ..#.......\n##...#####\n###.######\n";
+        let puzzle = Puzzle::read_from(&mut text.as_bytes()).unwrap();
+        let handling = Handling {
+            soft_drop_factor: f64::INFINITY,
+            ..Handling::default()
+        };
+        let mut core = GameCore::from_puzzle(&puzzle, Ruleset::default(), handling);
+        let mut input = InputState::default();
+        let multiplier = core.level() + 1;
+
+        let mut t_spin_double = |core: &mut GameCore| {
+            tap(core, &mut input, InputAction::RotateClockwise);
+            tap(core, &mut input, InputAction::Left);
+            // an instant soft drop reaches the bottom on its second frame
+            input.set_action(InputAction::SoftDrop, true);
+            core.step(&mut input, FRAME);
+            tap(core, &mut input, InputAction::SoftDrop);
+            tap(core, &mut input, InputAction::RotateClockwise);
+            hard_drop(core, &mut input)
+        };
+
+        let events = t_spin_double(&mut core);
+        assert!(events.contains(&GameEvent::TSpin(TSpin::Full, 2)));
+        assert_eq!(1200 * multiplier, core.score());
+
+        let events = t_spin_double(&mut core);
+        assert!(events.contains(&GameEvent::TSpin(TSpin::Full, 2)));
+        assert_eq!(1, core.back_to_back());
+        assert_eq!((1200 + 1800 + COMBO_BONUS) * multiplier, core.score());
+    }
+}
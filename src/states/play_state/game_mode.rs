@@ -0,0 +1,334 @@
+use ggez::GameResult;
+use std::collections::HashMap;
+use std::io::Write;
+use std::str::FromStr;
+
+/// The number of lines a sprint is won at.
+pub const SPRINT_LINES: u32 = 40;
+/// The highest level a marathon can be started at, counting from 0.
+pub const MAX_START_LEVEL: u32 = 14;
+
+/// The modes a game can be played in. A mode decides the goal of a game.
+#[derive(Debug, Copy, Clone, PartialEq, Default)]
+pub enum GameMode {
+    /// Clear lines to level up until the goal is reached.
+    #[default]
+    Marathon,
+    /// Clear `SPRINT_LINES` lines as fast as possible.
+    Sprint,
+    /// Score as much as possible before time runs out.
+    Ultra,
+    /// Clear every row of the garbage the well starts with as fast as
+    /// possible.
+    Dig,
+    /// Survive for as long as possible while garbage rises from the bottom of
+    /// the well, faster and faster.
+    Survival,
+    /// Play for as long as you like. The game cannot be lost, and the speed
+    /// never changes.
+    Zen,
+    /// Solve a challenge loaded from a puzzle file. Puzzles are chosen from
+    /// their own list, so this mode is skipped by `GameMode::next`.
+    Puzzle,
+    /// Two players race side by side, sending garbage to each other by
+    /// clearing lines, until one of them tops out. Versus is started from its
+    /// own menu, so this mode is skipped by `GameMode::next`.
+    Versus,
+}
+
+/// How the games of a mode are ordered in its high-score table.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Ranking {
+    /// Highest score first.
+    Score,
+    /// Quickest time first. Only won games are ranked.
+    FastestTime,
+    /// Longest time first.
+    LongestTime,
+}
+
+impl GameMode {
+    /// Returns the short name shown to the player.
+    pub fn name(self) -> &'static str {
+        match self {
+            GameMode::Marathon => "Marathon",
+            GameMode::Sprint => "Sprint",
+            GameMode::Ultra => "Ultra",
+            GameMode::Dig => "Dig",
+            GameMode::Survival => "Survival",
+            GameMode::Zen => "Zen",
+            GameMode::Puzzle => "Puzzle",
+            GameMode::Versus => "Versus",
+        }
+    }
+
+    /// Returns the mode after this one, wrapping around at the end.
+    pub fn next(self) -> Self {
+        match self {
+            GameMode::Marathon => GameMode::Sprint,
+            GameMode::Sprint => GameMode::Ultra,
+            GameMode::Ultra => GameMode::Dig,
+            GameMode::Dig => GameMode::Survival,
+            GameMode::Survival => GameMode::Zen,
+            GameMode::Zen | GameMode::Puzzle | GameMode::Versus => GameMode::Marathon,
+        }
+    }
+
+    /// Returns true if the level rises, and gravity with it, as lines are
+    /// cleared.
+    pub fn has_levels(self) -> bool {
+        match self {
+            GameMode::Marathon => true,
+            GameMode::Sprint
+            | GameMode::Ultra
+            | GameMode::Dig
+            | GameMode::Survival
+            | GameMode::Zen
+            | GameMode::Puzzle
+            | GameMode::Versus => false,
+        }
+    }
+
+    /// Returns true if the game is over once the stack reaches the top of the
+    /// well. Otherwise, the upper rows of the stack are cleared away instead.
+    pub fn can_top_out(self) -> bool {
+        self != GameMode::Zen
+    }
+
+    /// Returns how games in this mode are ranked.
+    pub fn ranking(self) -> Ranking {
+        match self {
+            GameMode::Marathon
+            | GameMode::Ultra
+            | GameMode::Zen
+            | GameMode::Puzzle
+            | GameMode::Versus => Ranking::Score,
+            GameMode::Sprint | GameMode::Dig => Ranking::FastestTime,
+            GameMode::Survival => Ranking::LongestTime,
+        }
+    }
+
+    /// Returns the mode with the short name `name`, if there is one.
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "Marathon" => Some(GameMode::Marathon),
+            "Sprint" => Some(GameMode::Sprint),
+            "Ultra" => Some(GameMode::Ultra),
+            "Dig" => Some(GameMode::Dig),
+            "Survival" => Some(GameMode::Survival),
+            "Zen" => Some(GameMode::Zen),
+            "Puzzle" => Some(GameMode::Puzzle),
+            "Versus" => Some(GameMode::Versus),
+            _ => None,
+        }
+    }
+}
+
+/// The number of lines a marathon is won at.
+#[derive(Debug, Copy, Clone, PartialEq, Default)]
+pub enum MarathonGoal {
+    #[default]
+    Lines150,
+    Lines200,
+    /// The game goes on until the player tops out.
+    Endless,
+}
+
+impl MarathonGoal {
+    /// Returns the short name shown to the player.
+    pub fn name(self) -> &'static str {
+        match self {
+            MarathonGoal::Lines150 => "150 lines",
+            MarathonGoal::Lines200 => "200 lines",
+            MarathonGoal::Endless => "Endless",
+        }
+    }
+
+    /// Returns the goal with the short name `name`, if there is one.
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "150 lines" => Some(MarathonGoal::Lines150),
+            "200 lines" => Some(MarathonGoal::Lines200),
+            "Endless" => Some(MarathonGoal::Endless),
+            _ => None,
+        }
+    }
+
+    /// Returns the goal after this one, wrapping around at the end.
+    pub fn next(self) -> Self {
+        match self {
+            MarathonGoal::Lines150 => MarathonGoal::Lines200,
+            MarathonGoal::Lines200 => MarathonGoal::Endless,
+            MarathonGoal::Endless => MarathonGoal::Lines150,
+        }
+    }
+
+    /// Returns the number of lines that wins the game, if there is one.
+    pub fn lines(self) -> Option<u32> {
+        match self {
+            MarathonGoal::Lines150 => Some(150),
+            MarathonGoal::Lines200 => Some(200),
+            MarathonGoal::Endless => None,
+        }
+    }
+}
+
+/// Decides how many lines must be cleared to pass each level.
+#[derive(Debug, Copy, Clone, PartialEq, Default)]
+pub enum LevelRule {
+    /// Every level takes 10 lines.
+    #[default]
+    Fixed,
+    /// Each level takes 5 more lines than the last, as in the guideline's
+    /// variable goal system: 5 lines to pass level 1, 10 to pass level 2, and
+    /// so on.
+    Variable,
+}
+
+impl LevelRule {
+    /// Returns the short name shown to the player.
+    pub fn name(self) -> &'static str {
+        match self {
+            LevelRule::Fixed => "Fixed",
+            LevelRule::Variable => "Variable",
+        }
+    }
+
+    /// Returns the rule with the short name `name`, if there is one.
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "Fixed" => Some(LevelRule::Fixed),
+            "Variable" => Some(LevelRule::Variable),
+            _ => None,
+        }
+    }
+
+    /// Returns the rule after this one, wrapping around at the end.
+    pub fn next(self) -> Self {
+        match self {
+            LevelRule::Fixed => LevelRule::Variable,
+            LevelRule::Variable => LevelRule::Fixed,
+        }
+    }
+
+    /// Returns the number of lines needed to pass `level`, counting from 0.
+    pub fn lines_for_level(self, level: u32) -> u32 {
+        match self {
+            LevelRule::Fixed => 10,
+            LevelRule::Variable => 5 * (level + 1),
+        }
+    }
+}
+
+/// The options a player can choose for the modes that have them. Only the
+/// options of the mode being played have any effect.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct ModeSettings {
+    /// The level a marathon starts at, counting from 0.
+    pub marathon_start_level: u32,
+    pub marathon_goal: MarathonGoal,
+    pub marathon_level_rule: LevelRule,
+    /// How long, in seconds, an Ultra game lasts.
+    pub ultra_time_limit: f64,
+    /// The number of rows of garbage a Dig game starts with.
+    pub dig_rows: u32,
+    /// The chance, from 0 to 1, that the hole in each row of garbage is in a
+    /// different column to the hole in the row below it.
+    pub dig_messiness: f64,
+    /// The level whose gravity a Zen game is played at, counting from 0, or
+    /// `None` for no gravity at all.
+    pub zen_gravity: Option<u32>,
+}
+
+impl Default for ModeSettings {
+    fn default() -> Self {
+        ModeSettings {
+            marathon_start_level: 0,
+            marathon_goal: MarathonGoal::default(),
+            marathon_level_rule: LevelRule::default(),
+            ultra_time_limit: 120.0,
+            dig_rows: 10,
+            dig_messiness: 0.25,
+            zen_gravity: Some(0),
+        }
+    }
+}
+
+impl ModeSettings {
+    /// Writes each value as a `key value` line, for the header of a replay.
+    pub fn write_fields<W: Write>(&self, writer: &mut W) -> GameResult<()> {
+        writeln!(writer, "marathon_start_level {}", self.marathon_start_level)?;
+        writeln!(writer, "marathon_goal {}", self.marathon_goal.name())?;
+        writeln!(
+            writer,
+            "marathon_level_rule {}",
+            self.marathon_level_rule.name()
+        )?;
+        writeln!(writer, "ultra_time_limit {}", self.ultra_time_limit)?;
+        writeln!(writer, "dig_rows {}", self.dig_rows)?;
+        writeln!(writer, "dig_messiness {}", self.dig_messiness)?;
+        match self.zen_gravity {
+            Some(level) => writeln!(writer, "zen_gravity {}", level)?,
+            None => writeln!(writer, "zen_gravity off")?,
+        }
+        Ok(())
+    }
+
+    /// Reads the values written by `ModeSettings::write_fields` from `fields`.
+    /// Returns `None` if any value is missing or invalid.
+    pub fn from_fields(fields: &HashMap<&str, &str>) -> Option<ModeSettings> {
+        let field = |key: &str| fields.get(key).copied();
+        Some(ModeSettings {
+            marathon_start_level: parse_field(field("marathon_start_level")?)?,
+            marathon_goal: MarathonGoal::from_name(field("marathon_goal")?)?,
+            marathon_level_rule: LevelRule::from_name(field("marathon_level_rule")?)?,
+            ultra_time_limit: parse_field(field("ultra_time_limit")?)?,
+            dig_rows: parse_field(field("dig_rows")?)?,
+            dig_messiness: parse_field(field("dig_messiness")?)?,
+            zen_gravity: match field("zen_gravity")? {
+                "off" => None,
+                level => Some(parse_field(level)?),
+            },
+        })
+    }
+}
+
+/// Parses the value of a field, or returns `None` if it is not valid.
+fn parse_field<T: FromStr>(value: &str) -> Option<T> {
+    value.parse().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{LevelRule, ModeSettings};
+    use std::collections::HashMap;
+
+    #[test]
+    fn mode_settings_are_rejected_if_any_field_is_invalid() {
+        let settings = ModeSettings {
+            marathon_level_rule: LevelRule::Variable,
+            zen_gravity: Some(3),
+            ..ModeSettings::default()
+        };
+        let mut written = Vec::new();
+        settings.write_fields(&mut written).unwrap();
+        let written = String::from_utf8(written).unwrap();
+        let lines: Vec<&str> = written.lines().collect();
+        let fields: HashMap<&str, &str> = lines
+            .iter()
+            .filter_map(|line| {
+                let mut parts = line.splitn(2, ' ');
+                Some((parts.next()?, parts.next()?))
+            })
+            .collect();
+        assert_eq!(Some(settings), ModeSettings::from_fields(&fields));
+
+        for key in fields.keys() {
+            let mut broken = fields.clone();
+            broken.insert(key, "?");
+            assert_eq!(None, ModeSettings::from_fields(&broken), "{}", key);
+            broken.remove(key);
+            assert_eq!(None, ModeSettings::from_fields(&broken), "{}", key);
+        }
+    }
+}
@@ -0,0 +1,188 @@
+use super::game_core::{GameCore, GameEvent};
+use super::game_mode::GameMode;
+use super::ui_element::{UIBlockView, UIMeterView, UITextView};
+use crate::states::Assets;
+use crate::util::{format_time, play_click_sfx};
+use ggez::graphics::Point2;
+use ggez::{graphics, Context, GameResult};
+
+/// Renders a `GameCore`: the well, the current piece and its shadow, and the
+/// HUD. Used by every state that shows a game, whether it is being played or
+/// watched.
+pub struct GameView {
+    ui_level: UITextView,
+    ui_lines: UITextView,
+    ui_score: UITextView,
+    ui_combo: UITextView,
+    ui_b2b: UITextView,
+    ui_time: UITextView,
+    // only shown in Sprint
+    ui_split: Option<UITextView>,
+    // only shown in Dig
+    ui_garbage: Option<UITextView>,
+    // only shown in modes where garbage rises on its own
+    ui_garbage_warning: Option<UIMeterView>,
+    best_splits: Vec<f64>,
+    ui_next: UIBlockView,
+    ui_hold: UIBlockView,
+}
+
+impl GameView {
+    pub fn new(ctx: &mut Context, assets: &Assets, core: &GameCore) -> Self {
+        GameView {
+            ui_next: UIBlockView::new(
+                ctx,
+                assets,
+                Point2::new(790.0, 75.0),
+                "Next",
+                core.next_piece(),
+            ),
+            ui_hold: UIBlockView::new(ctx, assets, Point2::new(790.0, 250.0), "Hold", None),
+            ui_level: UITextView::new(ctx, assets, Point2::new(790.0, 440.0), "Level", "1"),
+            ui_lines: UITextView::new(ctx, assets, Point2::new(790.0, 520.0), "Lines", "0"),
+            ui_score: UITextView::new(ctx, assets, Point2::new(790.0, 600.0), "Score", "0"),
+            ui_combo: UITextView::new(ctx, assets, Point2::new(220.0, 440.0), "Combo", "0"),
+            ui_b2b: UITextView::new(ctx, assets, Point2::new(220.0, 520.0), "Back-to-Back", "0"),
+            ui_time: UITextView::new(ctx, assets, Point2::new(220.0, 600.0), "Time", "0:00.000"),
+            ui_split: if core.mode() == GameMode::Sprint {
+                Some(UITextView::new(
+                    ctx,
+                    assets,
+                    Point2::new(220.0, 360.0),
+                    "Split",
+                    "-",
+                ))
+            } else {
+                None
+            },
+            ui_garbage: if core.mode() == GameMode::Dig {
+                Some(UITextView::new(
+                    ctx,
+                    assets,
+                    Point2::new(220.0, 360.0),
+                    "Garbage",
+                    &core.well().garbage_rows().to_string(),
+                ))
+            } else {
+                None
+            },
+            ui_garbage_warning: if core.garbage_warning().is_some() {
+                Some(UIMeterView::new(
+                    ctx,
+                    assets,
+                    Point2::new(220.0, 360.0),
+                    "Incoming Garbage",
+                ))
+            } else {
+                None
+            },
+            best_splits: Vec::new(),
+        }
+    }
+
+    /// Set the splits of the player's best game, which each split of the
+    /// current game is compared against.
+    pub fn set_best_splits(&mut self, splits: Vec<f64>) {
+        self.best_splits = splits;
+    }
+
+    /// Update the HUD to match the state of `core`.
+    pub fn update(&mut self, ctx: &mut Context, assets: &Assets, core: &GameCore) {
+        self.ui_hold.update(ctx, assets, core.hold_piece());
+        self.ui_next.update(ctx, assets, core.next_piece());
+        self.ui_level
+            .update(ctx, assets, &(core.level() + 1).to_string());
+        self.ui_lines
+            .update(ctx, assets, &core.cleared_lines().to_string());
+        self.ui_score.update(ctx, assets, &core.score().to_string());
+        self.ui_combo.update(ctx, assets, &core.combo().to_string());
+        self.ui_b2b
+            .update(ctx, assets, &core.back_to_back().to_string());
+        // modes with a time limit count down instead of up
+        let time = core.time_remaining().unwrap_or_else(|| core.elapsed_time());
+        self.ui_time.update(ctx, assets, &format_time(time));
+        if let Some(ui_split) = self.ui_split.as_mut() {
+            let text = split_text(core.splits(), &self.best_splits);
+            ui_split.update(ctx, assets, &text);
+        }
+        if let Some(ui_garbage) = self.ui_garbage.as_mut() {
+            ui_garbage.update(ctx, assets, &core.well().garbage_rows().to_string());
+        }
+        if let (Some(ui_warning), Some(warning)) =
+            (self.ui_garbage_warning.as_mut(), core.garbage_warning())
+        {
+            ui_warning.update(warning);
+        }
+    }
+
+    pub fn draw(&mut self, ctx: &mut Context, assets: &Assets, core: &GameCore) -> GameResult<()> {
+        graphics::draw(ctx, assets.get_image("game_bg")?, Point2::origin(), 0.0)?;
+        self.draw_game(ctx, assets, core)
+    }
+
+    /// Draws the well, the pieces and the HUD, without the background, so
+    /// that more than one game can share the screen.
+    pub fn draw_game(
+        &mut self,
+        ctx: &mut Context,
+        assets: &Assets,
+        core: &GameCore,
+    ) -> GameResult<()> {
+        let current_piece = core.current_piece();
+        core.well().draw(ctx, assets.get_image("block")?)?;
+        current_piece.draw_shadow(
+            ctx,
+            assets.get_image("block")?,
+            current_piece.get_shadow_position(),
+        )?;
+        current_piece.draw(ctx, assets.get_image("block")?)?;
+
+        self.ui_next.draw(ctx, assets)?;
+        self.ui_hold.draw(ctx, assets)?;
+        self.ui_level.draw(ctx)?;
+        self.ui_lines.draw(ctx)?;
+        self.ui_score.draw(ctx)?;
+        self.ui_combo.draw(ctx)?;
+        self.ui_b2b.draw(ctx)?;
+        self.ui_time.draw(ctx)?;
+        if let Some(ui_split) = self.ui_split.as_mut() {
+            ui_split.draw(ctx)?;
+        }
+        if let Some(ui_garbage) = self.ui_garbage.as_mut() {
+            ui_garbage.draw(ctx)?;
+        }
+        if let Some(ui_warning) = self.ui_garbage_warning.as_mut() {
+            ui_warning.draw(ctx)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Describes the latest of `splits`, along with how far ahead or behind of
+/// the same split in `best_splits` it was.
+fn split_text(splits: &[f64], best_splits: &[f64]) -> String {
+    let index = match splits.len() {
+        0 => return "-".to_string(),
+        len => len - 1,
+    };
+
+    let time = format_time(splits[index]);
+    match best_splits.get(index) {
+        Some(best) => format!("{} ({:+.3})", time, splits[index] - best),
+        None => time,
+    }
+}
+
+/// Play sounds in response to the events emitted by a core.
+pub fn play_event_sounds(events: &[GameEvent], assets: &Assets) -> GameResult<()> {
+    for event in events {
+        match event {
+            GameEvent::Moved | GameEvent::Rotated | GameEvent::HardDropped => {
+                play_click_sfx(assets)?
+            }
+            _ => (),
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,60 @@
+use super::well::WIDTH;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+/// Picks the column of the hole in each row of garbage added to a well. Rows
+/// tend to line their holes up with the row added before them, which makes
+/// the garbage cleaner to dig through.
+#[derive(Clone)]
+pub struct GarbageGenerator {
+    rng: StdRng,
+    messiness: f64,
+    last_hole: Option<usize>,
+}
+
+impl GarbageGenerator {
+    /// Creates a generator whose holes depend only on `seed`. `messiness` is
+    /// the chance, from 0 to 1, that a hole moves away from the column of the
+    /// last one.
+    pub fn new(seed: u64, messiness: f64) -> Self {
+        GarbageGenerator {
+            rng: StdRng::seed_from_u64(seed),
+            messiness: messiness.clamp(0.0, 1.0),
+            last_hole: None,
+        }
+    }
+
+    /// Returns the column of the hole in the next row of garbage.
+    pub fn next_hole(&mut self) -> usize {
+        let hole = match self.last_hole {
+            None => self.rng.gen_range(0, WIDTH),
+            Some(last) if self.rng.gen_bool(self.messiness) => {
+                (last + self.rng.gen_range(1, WIDTH)) % WIDTH
+            }
+            Some(last) => last,
+        };
+
+        self.last_hole = Some(hole);
+        hole
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::GarbageGenerator;
+
+    #[test]
+    fn holes_only_move_when_messy() {
+        let mut clean = GarbageGenerator::new(7, 0.0);
+        let first = clean.next_hole();
+        assert!((0..20).all(|_| clean.next_hole() == first));
+
+        let mut messy = GarbageGenerator::new(7, 1.0);
+        let mut last = messy.next_hole();
+        for _ in 0..20 {
+            let hole = messy.next_hole();
+            assert_ne!(last, hole);
+            last = hole;
+        }
+    }
+}
@@ -0,0 +1,89 @@
+/// The number of frames in a second. Gravity is measured in rows per frame,
+/// or G, so 1G moves a piece one row every frame.
+pub const FRAMES_PER_SECOND: f64 = 60.0;
+
+/// Gravity strong enough to take a piece from the top of the well to the
+/// bottom in a single frame. Pieces under 20G appear directly on the stack.
+pub const INSTANT_GRAVITY: f64 = 20.0;
+
+// rows per frame at each level, counting from 0, for the guideline curve, where
+// level n takes (0.8 - n * 0.007)^n seconds per row
+const GUIDELINE_GRAVITY: [f64; 15] = [
+    0.016_67, 0.021_02, 0.026_98, 0.035_26, 0.046_92, 0.063_61, 0.087_87, 0.123_70, 0.177_53,
+    0.259_80, 0.387_81, 0.590_65, 0.918_11, 1.456_96, 2.361_18,
+];
+
+// rows per frame at each level, counting from 0, for the master curve. This
+// follows the shape of the TGM curve: fractions of a row at first, then several
+// rows a frame, then 20G
+const MASTER_GRAVITY: [f64; 15] = [
+    0.015_625,
+    0.031_25,
+    0.062_5,
+    0.125,
+    0.25,
+    0.5,
+    1.0,
+    2.0,
+    3.0,
+    4.0,
+    5.0,
+    INSTANT_GRAVITY,
+    INSTANT_GRAVITY,
+    INSTANT_GRAVITY,
+    INSTANT_GRAVITY,
+];
+
+/// Decides how strong gravity is at each level.
+#[derive(Debug, Copy, Clone, PartialEq, Default)]
+pub enum GravityCurve {
+    /// Starts at one row a second and speeds up gently, reaching a little
+    /// over 2G at level 15.
+    #[default]
+    Guideline,
+    /// Speeds up quickly, reaching 20G at level 12.
+    Master,
+    /// 20G at every level.
+    Instant,
+}
+
+impl GravityCurve {
+    /// Returns the short name shown to the player.
+    pub fn name(self) -> &'static str {
+        match self {
+            GravityCurve::Guideline => "Guideline",
+            GravityCurve::Master => "Master",
+            GravityCurve::Instant => "20G",
+        }
+    }
+
+    /// Returns the curve with the short name `name`, if there is one.
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "Guideline" => Some(GravityCurve::Guideline),
+            "Master" => Some(GravityCurve::Master),
+            "20G" => Some(GravityCurve::Instant),
+            _ => None,
+        }
+    }
+
+    /// Returns the curve after this one, wrapping around at the end.
+    pub fn next(self) -> Self {
+        match self {
+            GravityCurve::Guideline => GravityCurve::Master,
+            GravityCurve::Master => GravityCurve::Instant,
+            GravityCurve::Instant => GravityCurve::Guideline,
+        }
+    }
+
+    /// Returns the gravity, in rows per frame, at `level`, counting from 0.
+    /// Levels past the end of the curve keep the gravity of its last level.
+    pub fn gravity(self, level: u32) -> f64 {
+        let table = match self {
+            GravityCurve::Guideline => &GUIDELINE_GRAVITY,
+            GravityCurve::Master => &MASTER_GRAVITY,
+            GravityCurve::Instant => return INSTANT_GRAVITY,
+        };
+        table[(level as usize).min(table.len() - 1)]
+    }
+}
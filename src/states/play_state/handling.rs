@@ -0,0 +1,94 @@
+use ggez::{Context, GameResult};
+use std::collections::HashMap;
+use std::io::{Read, Write};
+
+const HANDLING_PATH: &str = "/handling.cfg";
+
+/// How a player's held inputs repeat. Unlike a `Ruleset`, handling is a
+/// player's own preference, and is kept between games.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Handling {
+    /// Delayed auto shift: how long, in seconds, left or right must be held
+    /// before the piece starts to auto-repeat.
+    pub das: f64,
+    /// Auto repeat rate: the time, in seconds, between each auto-repeated
+    /// move. At 0 the piece moves straight to the wall.
+    pub arr: f64,
+    /// How many times faster than gravity a soft dropped piece falls. An
+    /// infinite factor drops the piece straight to the bottom without locking
+    /// it.
+    pub soft_drop_factor: f64,
+    /// How long, in seconds, auto-repeat is held back after a new piece
+    /// spawns, so a charged DAS does not carry the new piece away.
+    pub das_cut: f64,
+}
+
+impl Default for Handling {
+    fn default() -> Self {
+        Handling {
+            das: 0.15,
+            arr: 0.05,
+            soft_drop_factor: 20.0,
+            das_cut: 0.0,
+        }
+    }
+}
+
+impl Handling {
+    /// Loads the saved handling. Falls back to the default handling if none
+    /// was saved or it cannot be read.
+    pub fn load(ctx: &mut Context) -> Self {
+        let mut contents = String::new();
+        let read = ctx
+            .filesystem
+            .open(HANDLING_PATH)
+            .and_then(|mut file| Ok(file.read_to_string(&mut contents)?));
+        if read.is_err() {
+            return Handling::default();
+        }
+
+        let fields: HashMap<&str, &str> = contents
+            .lines()
+            .filter_map(|line| {
+                let mut parts = line.splitn(2, ' ');
+                Some((parts.next()?, parts.next()?))
+            })
+            .collect();
+
+        Handling::from_fields(&fields).unwrap_or_default()
+    }
+
+    /// Saves the handling so it is used by every game from now on.
+    pub fn save(&self, ctx: &mut Context) -> GameResult<()> {
+        let mut file = ctx.filesystem.create(HANDLING_PATH)?;
+        self.write_fields(&mut file)?;
+        Ok(())
+    }
+
+    /// Writes each value as a `key value` line. Used for both the saved
+    /// handling and the header of a replay.
+    pub fn write_fields<W: Write>(&self, writer: &mut W) -> GameResult<()> {
+        writeln!(writer, "das {}", self.das)?;
+        writeln!(writer, "arr {}", self.arr)?;
+        writeln!(writer, "soft_drop_factor {}", self.soft_drop_factor)?;
+        writeln!(writer, "das_cut {}", self.das_cut)?;
+        Ok(())
+    }
+
+    /// Reads the values written by `Handling::write_fields` from `fields`.
+    /// Returns `None` if any value is missing or invalid.
+    pub fn from_fields(fields: &HashMap<&str, &str>) -> Option<Handling> {
+        let field = |key: &str| fields.get(key)?.parse().ok();
+        Some(Handling {
+            das: field("das")?,
+            arr: field("arr")?,
+            soft_drop_factor: field("soft_drop_factor")?,
+            das_cut: field("das_cut")?,
+        })
+    }
+
+    /// Returns true if soft drop moves the piece straight to the bottom.
+    pub fn is_instant_soft_drop(&self) -> bool {
+        self.soft_drop_factor.is_infinite()
+    }
+}
@@ -23,6 +23,48 @@ impl InputStateField {
         self.initial_delay_timer = 0.0;
         self.secondary_delay_timer = 0.0;
     }
+
+    /// Advance the timers of a held input by `dt` seconds, following the flow
+    /// above with the given delays. Returns the number of times the action
+    /// tied to the input should be performed during this step. A
+    /// `secondary_delay` of 0 returns `u32::MAX`, meaning as many times as
+    /// possible.
+    pub fn tick(&mut self, dt: f64, initial_delay: f64, secondary_delay: f64) -> u32 {
+        // initial action
+        if self.initial_delay_timer == 0.0 {
+            self.initial_delay_timer += dt;
+            return 1;
+        }
+
+        // initial delay
+        if !self.is_charged(initial_delay) {
+            self.initial_delay_timer += dt;
+            if !self.is_charged(initial_delay) {
+                return 0;
+            }
+
+            self.secondary_delay_timer = 0.0;
+            return if secondary_delay == 0.0 { u32::MAX } else { 1 };
+        }
+
+        // secondary actions
+        if secondary_delay == 0.0 {
+            return u32::MAX;
+        }
+        self.secondary_delay_timer += dt;
+        let mut count = 0;
+        while self.secondary_delay_timer >= secondary_delay {
+            self.secondary_delay_timer -= secondary_delay;
+            count += 1;
+        }
+        count
+    }
+
+    /// Returns true once the initial delay has expired and the action has
+    /// started to repeat.
+    pub fn is_charged(&self, initial_delay: f64) -> bool {
+        self.initial_delay_timer >= initial_delay
+    }
 }
 
 impl Default for InputStateField {
@@ -65,3 +107,61 @@ impl Default for InputState {
         }
     }
 }
+
+impl InputState {
+    /// Returns the input field tied to `action`.
+    pub fn field_mut(&mut self, action: InputAction) -> &mut InputStateField {
+        match action {
+            InputAction::Left => &mut self.left,
+            InputAction::Right => &mut self.right,
+            InputAction::SoftDrop => &mut self.soft_drop,
+            InputAction::HardDrop => &mut self.hard_drop,
+            InputAction::RotateClockwise => &mut self.rotate_clockwise,
+            InputAction::RotateCounterclockwise => &mut self.rotate_counterclockwise,
+            InputAction::Hold => &mut self.hold,
+        }
+    }
+
+    /// Marks the input tied to `action` as pressed or released. Returns false
+    /// if the input was already in that state.
+    pub fn set_action(&mut self, action: InputAction, pressed: bool) -> bool {
+        let field = self.field_mut(action);
+        if field.is_active == pressed {
+            return false;
+        }
+
+        if pressed {
+            field.is_active = true;
+        } else {
+            field.reset();
+        }
+        true
+    }
+}
+
+/// The actions a player can perform on their piece. Every change to an
+/// `InputState` made by a player goes through one of these, which lets a game
+/// be recorded and played back.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum InputAction {
+    Left,
+    Right,
+    SoftDrop,
+    HardDrop,
+    RotateClockwise,
+    RotateCounterclockwise,
+    Hold,
+}
+
+impl InputAction {
+    /// Every action, in a fixed order.
+    pub const ALL: [InputAction; 7] = [
+        InputAction::Left,
+        InputAction::Right,
+        InputAction::SoftDrop,
+        InputAction::HardDrop,
+        InputAction::RotateClockwise,
+        InputAction::RotateCounterclockwise,
+        InputAction::Hold,
+    ];
+}
@@ -0,0 +1,252 @@
+use super::t_spin::TSpin;
+use super::tetromino::{char_to_piece_type, PieceType};
+use super::well::{Well, HEIGHT, WIDTH};
+use ggez::{Context, GameError, GameResult};
+use std::ffi::OsStr;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+const PUZZLE_DIR: &str = "/puzzles";
+const PUZZLE_EXTENSION: &str = "puzzle";
+
+/// What has to be done to solve a `Puzzle`.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum PuzzleGoal {
+    /// Leave the well completely empty.
+    ClearAll,
+    /// Lock a T-spin, not a mini, that clears the given number of lines.
+    TSpin(u32),
+    /// Clear the given number of lines in total.
+    Lines(u32),
+}
+
+impl PuzzleGoal {
+    /// Returns the goal as it is shown to the player.
+    pub fn description(self) -> String {
+        match self {
+            PuzzleGoal::ClearAll => "Clear every line".to_string(),
+            PuzzleGoal::TSpin(lines) => format!("Perform a T-spin {}", clear_name(lines)),
+            PuzzleGoal::Lines(1) => "Clear 1 line".to_string(),
+            PuzzleGoal::Lines(lines) => format!("Clear {} lines", lines),
+        }
+    }
+
+    /// Returns true if a piece that just locked reached the goal. `well` is
+    /// the well after its lines were cleared, and `total_lines` counts every
+    /// line cleared in the puzzle so far.
+    pub fn is_met(
+        self,
+        well: &Well,
+        t_spin: Option<TSpin>,
+        lines_cleared: u32,
+        total_lines: u32,
+    ) -> bool {
+        match self {
+            PuzzleGoal::ClearAll => lines_cleared > 0 && well.is_empty(),
+            PuzzleGoal::TSpin(lines) => t_spin == Some(TSpin::Full) && lines_cleared == lines,
+            PuzzleGoal::Lines(lines) => total_lines >= lines,
+        }
+    }
+
+    /// Reads a goal written as `clear_all`, `tspin <lines>` or
+    /// `lines <lines>`. A T-spin can only clear 1 to 3 lines.
+    fn parse(text: &str) -> Option<Self> {
+        let mut parts = text.split_whitespace();
+        let goal = match (parts.next()?, parts.next()) {
+            ("clear_all", None) => PuzzleGoal::ClearAll,
+            ("tspin", Some(lines)) => match lines.parse().ok()? {
+                lines @ 1..=3 => PuzzleGoal::TSpin(lines),
+                _ => return None,
+            },
+            ("lines", Some(lines)) => PuzzleGoal::Lines(lines.parse().ok()?),
+            _ => return None,
+        };
+
+        match parts.next() {
+            Some(_) => None,
+            None => Some(goal),
+        }
+    }
+}
+
+/// A challenge loaded from a puzzle file: a well to start from, the pieces to
+/// solve it with and a goal.
+///
+/// A puzzle file holds a `key value` pair per line, then the word `well`
+/// followed by a picture of the bottom rows of the well, where `#` is a
+/// filled cell and `.` is an empty one:
+///
+/// ```text
+/// name Tetris
+/// goal lines 4
+/// pieces O
+/// hold I
+/// well
+/// #########.
+/// #########.
+/// #########.
+/// #########.
+/// ```
+///
+/// `pieces` lists the pieces dealt, in order, and `hold` is optional. Goals
+/// are written as `clear_all`, `tspin <lines>` or `lines <lines>`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Puzzle {
+    pub name: String,
+    pub goal: PuzzleGoal,
+    pub pieces: Vec<PieceType>,
+    pub hold: Option<PieceType>,
+    /// The bottom rows of the well, top first.
+    pub rows: Vec<String>,
+}
+
+impl Puzzle {
+    /// Reads a puzzle written in the format described on `Puzzle`.
+    pub fn read_from<R: Read>(reader: &mut R) -> GameResult<Puzzle> {
+        let mut contents = String::new();
+        reader.read_to_string(&mut contents)?;
+        let mut lines = contents.lines().map(str::trim_end);
+
+        let mut name = None;
+        let mut goal = None;
+        let mut pieces = None;
+        let mut hold = None;
+        for line in &mut lines {
+            if line == "well" {
+                break;
+            } else if line.is_empty() {
+                continue;
+            }
+
+            let mut parts = line.splitn(2, ' ');
+            match (parts.next(), parts.next()) {
+                (Some("name"), Some(value)) => name = Some(value.to_string()),
+                (Some("goal"), Some(value)) => {
+                    goal = Some(PuzzleGoal::parse(value).ok_or_else(|| invalid_puzzle("goal"))?)
+                }
+                (Some("pieces"), Some(value)) => {
+                    pieces = Some(parse_pieces(value).ok_or_else(|| invalid_puzzle("pieces"))?)
+                }
+                (Some("hold"), Some(value)) => {
+                    let piece = match parse_pieces(value).as_deref() {
+                        Some([piece]) => *piece,
+                        _ => return Err(invalid_puzzle("hold")),
+                    };
+                    hold = Some(piece);
+                }
+                _ => return Err(invalid_puzzle(line)),
+            }
+        }
+
+        let rows: Vec<String> = lines.map(str::to_string).collect();
+        let rows_are_valid = rows.len() < HEIGHT - 2
+            && rows.iter().all(|row| {
+                row.len() == WIDTH && row.chars().all(|cell| cell == '#' || cell == '.')
+            });
+        if !rows_are_valid {
+            return Err(invalid_puzzle("well"));
+        }
+
+        let pieces: Vec<PieceType> = pieces.ok_or_else(|| invalid_puzzle("missing pieces"))?;
+        if pieces.is_empty() {
+            return Err(invalid_puzzle("no pieces"));
+        }
+
+        Ok(Puzzle {
+            name: name.ok_or_else(|| invalid_puzzle("missing name"))?,
+            goal: goal.ok_or_else(|| invalid_puzzle("missing goal"))?,
+            pieces,
+            hold,
+            rows,
+        })
+    }
+
+    /// Loads the puzzle saved at `path`.
+    pub fn load(ctx: &mut Context, path: &Path) -> GameResult<Puzzle> {
+        let mut file = ctx.filesystem.open(path)?;
+        Puzzle::read_from(&mut file)
+    }
+
+    /// Creates a well holding the starting rows of the puzzle.
+    pub fn well(&self) -> Well {
+        let rows: Vec<&str> = self.rows.iter().map(String::as_str).collect();
+        Well::from_rows(&rows)
+    }
+}
+
+/// Loads every puzzle in the puzzle directory, in the order of their file
+/// names. Both the puzzles that ship with the game and any the player adds are
+/// found. Files that cannot be read are skipped.
+pub fn load_puzzles(ctx: &mut Context) -> GameResult<Vec<Puzzle>> {
+    if !ctx.filesystem.is_dir(PUZZLE_DIR) {
+        return Ok(Vec::new());
+    }
+
+    let mut paths: Vec<PathBuf> = ctx
+        .filesystem
+        .read_dir(PUZZLE_DIR)?
+        .filter(|path| path.extension() == Some(OsStr::new(PUZZLE_EXTENSION)))
+        .collect();
+    paths.sort();
+    paths.dedup();
+
+    Ok(paths
+        .iter()
+        .filter_map(|path| Puzzle::load(ctx, path).ok())
+        .collect())
+}
+
+/// Reads a list of pieces, written as their letters separated by spaces.
+fn parse_pieces(text: &str) -> Option<Vec<PieceType>> {
+    text.split_whitespace()
+        .map(|name| {
+            let mut chars = name.chars();
+            match (chars.next(), chars.next()) {
+                (Some(c), None) => char_to_piece_type(c),
+                _ => None,
+            }
+        })
+        .collect()
+}
+
+fn clear_name(lines: u32) -> &'static str {
+    match lines {
+        0 => "with no lines",
+        1 => "single",
+        2 => "double",
+        _ => "triple",
+    }
+}
+
+fn invalid_puzzle(reason: &str) -> GameError {
+    GameError::ResourceLoadError(format!("Invalid puzzle: {}", reason))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Puzzle, PuzzleGoal};
+    use crate::states::play_state::tetromino::PieceType;
+
+    #[test]
+    fn puzzles_are_read_from_text() {
+        let text = "name Tetris\ngoal lines 4\npieces O\nhold I\nwell\n#########.\n#########.\n";
+        let puzzle = Puzzle::read_from(&mut text.as_bytes()).unwrap();
+
+        assert_eq!("Tetris", puzzle.name);
+        assert_eq!(PuzzleGoal::Lines(4), puzzle.goal);
+        assert_eq!(vec![PieceType::O], puzzle.pieces);
+        assert_eq!(Some(PieceType::I), puzzle.hold);
+        assert_eq!(2, puzzle.rows.len());
+
+        let bad_row = "name Bad\ngoal clear_all\npieces T\nwell\n####\n";
+        assert!(Puzzle::read_from(&mut bad_row.as_bytes()).is_err());
+    }
+
+    #[test]
+    fn t_spin_goals_must_clear_one_to_three_lines() {
+        assert_eq!(Some(PuzzleGoal::TSpin(1)), PuzzleGoal::parse("tspin 1"));
+        assert_eq!(Some(PuzzleGoal::TSpin(3)), PuzzleGoal::parse("tspin 3"));
+        assert_eq!(None, PuzzleGoal::parse("tspin 0"));
+        assert_eq!(None, PuzzleGoal::parse("tspin 4"));
+    }
+}
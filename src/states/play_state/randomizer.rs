@@ -0,0 +1,286 @@
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::Rng;
+
+use super::tetromino::{u8_to_piece_type, PieceType};
+
+const PIECE_TYPE_COUNT: u8 = 7;
+const TGM_HISTORY_LEN: usize = 4;
+const TGM_REROLLS: u32 = 4;
+
+/// Decides the order pieces are dealt in. All randomness comes from the `rng`
+/// passed in, so the same seed always gives the same sequence of pieces.
+pub trait Randomizer {
+    /// Returns the next piece in the sequence.
+    fn next_piece(&mut self, rng: &mut StdRng) -> PieceType;
+
+    /// Returns a copy of the randomizer, which deals the same pieces from here
+    /// on when given a copy of the same `rng`.
+    fn box_clone(&self) -> Box<dyn Randomizer>;
+}
+
+impl Clone for Box<dyn Randomizer> {
+    fn clone(&self) -> Self {
+        self.box_clone()
+    }
+}
+
+/// The randomizers a game can be played with.
+#[derive(Debug, Copy, Clone, PartialEq, Default)]
+pub enum RandomizerKind {
+    #[default]
+    SevenBag,
+    FourteenBag,
+    Tgm,
+    Nes,
+    Uniform,
+}
+
+impl RandomizerKind {
+    /// Creates the `Randomizer` described by this kind.
+    pub fn build(self) -> Box<dyn Randomizer> {
+        match self {
+            RandomizerKind::SevenBag => Box::new(Bag::new(1)),
+            RandomizerKind::FourteenBag => Box::new(Bag::new(2)),
+            RandomizerKind::Tgm => Box::new(Tgm::default()),
+            RandomizerKind::Nes => Box::new(Nes::default()),
+            RandomizerKind::Uniform => Box::new(Uniform),
+        }
+    }
+
+    /// Returns the short name shown to the player.
+    pub fn name(self) -> &'static str {
+        match self {
+            RandomizerKind::SevenBag => "7-bag",
+            RandomizerKind::FourteenBag => "14-bag",
+            RandomizerKind::Tgm => "TGM",
+            RandomizerKind::Nes => "NES",
+            RandomizerKind::Uniform => "Random",
+        }
+    }
+
+    /// Returns the randomizer with the short name `name`, if there is one.
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "7-bag" => Some(RandomizerKind::SevenBag),
+            "14-bag" => Some(RandomizerKind::FourteenBag),
+            "TGM" => Some(RandomizerKind::Tgm),
+            "NES" => Some(RandomizerKind::Nes),
+            "Random" => Some(RandomizerKind::Uniform),
+            _ => None,
+        }
+    }
+
+    /// Returns the randomizer after this one, wrapping around at the end.
+    pub fn next(self) -> Self {
+        match self {
+            RandomizerKind::SevenBag => RandomizerKind::FourteenBag,
+            RandomizerKind::FourteenBag => RandomizerKind::Tgm,
+            RandomizerKind::Tgm => RandomizerKind::Nes,
+            RandomizerKind::Nes => RandomizerKind::Uniform,
+            RandomizerKind::Uniform => RandomizerKind::SevenBag,
+        }
+    }
+}
+
+/// Returns one of the seven piece types, each equally likely.
+fn random_piece(rng: &mut StdRng) -> PieceType {
+    u8_to_piece_type(rng.gen_range(0, PIECE_TYPE_COUNT)).unwrap()
+}
+
+/// Fills a bag with `copies` of each piece type and deals the whole bag in a
+/// random order before refilling it. A bag with one copy of each piece is the
+/// standard 7-bag.
+#[derive(Clone)]
+pub struct Bag {
+    copies: u8,
+    pieces: Vec<PieceType>,
+}
+
+impl Bag {
+    pub fn new(copies: u8) -> Self {
+        Bag {
+            copies,
+            pieces: Vec::new(),
+        }
+    }
+}
+
+impl Randomizer for Bag {
+    fn next_piece(&mut self, rng: &mut StdRng) -> PieceType {
+        if self.pieces.is_empty() {
+            for _ in 0..self.copies {
+                for num in 0..PIECE_TYPE_COUNT {
+                    self.pieces.push(u8_to_piece_type(num).unwrap());
+                }
+            }
+            self.pieces.shuffle(rng);
+        }
+
+        self.pieces
+            .pop()
+            .expect("Bag is never empty after refilling")
+    }
+
+    fn box_clone(&self) -> Box<dyn Randomizer> {
+        Box::new(self.clone())
+    }
+}
+
+/// Deals a fixed list of pieces in order, starting over once every piece has
+/// been dealt. Used by puzzles, which list the pieces they are solved with.
+#[derive(Clone)]
+pub struct Sequence {
+    pieces: Vec<PieceType>,
+    next: usize,
+}
+
+impl Sequence {
+    /// Creates a randomizer that deals `pieces`, which must not be empty.
+    pub fn new(pieces: Vec<PieceType>) -> Self {
+        Sequence { pieces, next: 0 }
+    }
+}
+
+impl Randomizer for Sequence {
+    fn next_piece(&mut self, _: &mut StdRng) -> PieceType {
+        let piece = self.pieces[self.next];
+        self.next = (self.next + 1) % self.pieces.len();
+        piece
+    }
+
+    fn box_clone(&self) -> Box<dyn Randomizer> {
+        Box::new(self.clone())
+    }
+}
+
+/// The randomizer from the TGM series. It remembers the last four pieces dealt
+/// and rerolls up to four times to find a piece that is not one of them. The
+/// first piece is never an S, Z or O.
+#[derive(Clone)]
+pub struct Tgm {
+    history: [PieceType; TGM_HISTORY_LEN],
+    is_first_piece: bool,
+}
+
+impl Default for Tgm {
+    fn default() -> Self {
+        Tgm {
+            history: [PieceType::Z; TGM_HISTORY_LEN],
+            is_first_piece: true,
+        }
+    }
+}
+
+impl Randomizer for Tgm {
+    fn next_piece(&mut self, rng: &mut StdRng) -> PieceType {
+        let piece = if self.is_first_piece {
+            self.is_first_piece = false;
+            *[PieceType::I, PieceType::J, PieceType::L, PieceType::T]
+                .choose(rng)
+                .unwrap()
+        } else {
+            let mut piece = random_piece(rng);
+            for _ in 0..TGM_REROLLS {
+                if !self.history.contains(&piece) {
+                    break;
+                }
+                piece = random_piece(rng);
+            }
+            piece
+        };
+
+        self.history.rotate_right(1);
+        self.history[0] = piece;
+        piece
+    }
+
+    fn box_clone(&self) -> Box<dyn Randomizer> {
+        Box::new(self.clone())
+    }
+}
+
+/// The randomizer from the NES version. If it rolls the piece that was just
+/// dealt, it rolls once more, so repeats are rare but still possible.
+#[derive(Clone, Default)]
+pub struct Nes {
+    last_piece: Option<PieceType>,
+}
+
+impl Randomizer for Nes {
+    fn next_piece(&mut self, rng: &mut StdRng) -> PieceType {
+        let mut piece = random_piece(rng);
+        if self.last_piece == Some(piece) {
+            piece = random_piece(rng);
+        }
+
+        self.last_piece = Some(piece);
+        piece
+    }
+
+    fn box_clone(&self) -> Box<dyn Randomizer> {
+        Box::new(self.clone())
+    }
+}
+
+/// Deals each piece with no memory of the pieces before it.
+#[derive(Clone)]
+pub struct Uniform;
+
+impl Randomizer for Uniform {
+    fn next_piece(&mut self, rng: &mut StdRng) -> PieceType {
+        random_piece(rng)
+    }
+
+    fn box_clone(&self) -> Box<dyn Randomizer> {
+        Box::new(self.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::RandomizerKind;
+    use crate::states::play_state::tetromino::PieceType;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    fn deal(kind: RandomizerKind, seed: u64, count: usize) -> Vec<PieceType> {
+        let mut rng = StdRng::seed_from_u64(seed);
+        let mut randomizer = kind.build();
+        (0..count)
+            .map(|_| randomizer.next_piece(&mut rng))
+            .collect()
+    }
+
+    #[test]
+    fn same_seed_deals_same_pieces() {
+        let kinds = [
+            RandomizerKind::SevenBag,
+            RandomizerKind::FourteenBag,
+            RandomizerKind::Tgm,
+            RandomizerKind::Nes,
+            RandomizerKind::Uniform,
+        ];
+        for kind in &kinds {
+            assert_eq!(deal(*kind, 1234, 100), deal(*kind, 1234, 100));
+        }
+    }
+
+    #[test]
+    fn seven_bag_deals_each_piece_once_per_bag() {
+        let pieces = deal(RandomizerKind::SevenBag, 42, 70);
+        for bag in pieces.chunks(7) {
+            for piece in bag {
+                assert_eq!(1, bag.iter().filter(|p| *p == piece).count());
+            }
+        }
+    }
+
+    #[test]
+    fn tgm_never_starts_with_s_z_or_o() {
+        for seed in 0..100 {
+            let first = deal(RandomizerKind::Tgm, seed, 1)[0];
+            assert!(![PieceType::S, PieceType::Z, PieceType::O].contains(&first));
+        }
+    }
+}
@@ -0,0 +1,389 @@
+use super::game_mode::{GameMode, ModeSettings};
+use super::gravity::GravityCurve;
+use super::handling::Handling;
+use super::input_state::{InputAction, InputState};
+use super::randomizer::RandomizerKind;
+use super::rotation::RotationSystemKind;
+use super::ruleset::{LockReset, Ruleset};
+use ggez::{Context, GameError, GameResult};
+use std::collections::HashMap;
+use std::ffi::OsStr;
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// The version of the replay format written by this build. Replays of any
+/// other version are rejected, as they may not play back the same game.
+const REPLAY_VERSION: u32 = 7;
+const REPLAY_MAGIC: &str = "rustris-replay";
+const REPLAY_DIR: &str = "/replays";
+const REPLAY_EXTENSION: &str = "replay";
+
+/// The length of a single frame. Games are always advanced a whole frame at a
+/// time, so that an input recorded on a frame can be fed back in on the very
+/// same frame.
+pub const FRAME_DURATION: Duration = Duration::from_nanos(16_666_667);
+
+/// A single press or release of an input, made just before frame `frame` was
+/// played.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct ReplayEvent {
+    pub frame: u32,
+    pub action: InputAction,
+    pub pressed: bool,
+}
+
+/// A recording of a game. Holds everything needed to create the same
+/// `GameCore` again, and every input fed into it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Replay {
+    pub mode: GameMode,
+    pub settings: ModeSettings,
+    pub ruleset: Ruleset,
+    pub handling: Handling,
+    pub seed: u64,
+    /// The number of frames the game lasted.
+    pub frame_count: u32,
+    pub events: Vec<ReplayEvent>,
+}
+
+impl Replay {
+    pub fn new(
+        mode: GameMode,
+        settings: ModeSettings,
+        ruleset: Ruleset,
+        handling: Handling,
+        seed: u64,
+    ) -> Self {
+        Replay {
+            mode,
+            settings,
+            ruleset,
+            handling,
+            seed,
+            frame_count: 0,
+            events: Vec::new(),
+        }
+    }
+
+    /// Records a press or release of `action` on the current frame.
+    pub fn record(&mut self, action: InputAction, pressed: bool) {
+        self.events.push(ReplayEvent {
+            frame: self.frame_count,
+            action,
+            pressed,
+        });
+    }
+
+    /// Moves recording on to the next frame. Call this once after each
+    /// `GameCore::step`.
+    pub fn end_frame(&mut self) {
+        self.frame_count += 1;
+    }
+
+    /// Writes the replay in its text format.
+    pub fn write_to<W: Write>(&self, writer: &mut W) -> GameResult<()> {
+        writeln!(writer, "{} {}", REPLAY_MAGIC, REPLAY_VERSION)?;
+        writeln!(writer, "mode {}", self.mode.name())?;
+        self.settings.write_fields(writer)?;
+        writeln!(writer, "seed {}", self.seed)?;
+        writeln!(writer, "rotation {}", self.ruleset.rotation_system.name())?;
+        writeln!(writer, "lock_delay {}", self.ruleset.lock_delay)?;
+        writeln!(writer, "lock_reset {}", self.ruleset.lock_reset.name())?;
+        writeln!(writer, "randomizer {}", self.ruleset.randomizer.name())?;
+        writeln!(writer, "gravity {}", self.ruleset.gravity.name())?;
+        self.handling.write_fields(writer)?;
+        writeln!(writer, "frames {}", self.frame_count)?;
+        writeln!(writer, "events")?;
+        for event in &self.events {
+            let kind = if event.pressed { "press" } else { "release" };
+            writeln!(
+                writer,
+                "{} {} {}",
+                event.frame,
+                kind,
+                action_name(event.action)
+            )?;
+        }
+
+        Ok(())
+    }
+
+    /// Reads a replay written by `Replay::write_to`.
+    pub fn read_from<R: Read>(reader: &mut R) -> GameResult<Replay> {
+        let mut contents = String::new();
+        reader.read_to_string(&mut contents)?;
+        let mut lines = contents.lines();
+
+        let magic = format!("{} {}", REPLAY_MAGIC, REPLAY_VERSION);
+        if lines.next() != Some(magic.as_str()) {
+            return Err(invalid_replay("unknown format or version"));
+        }
+
+        // header fields come first, one `key value` pair per line
+        let mut fields: HashMap<&str, &str> = HashMap::new();
+        for line in &mut lines {
+            if line == "events" {
+                break;
+            }
+
+            let mut parts = line.splitn(2, ' ');
+            match (parts.next(), parts.next()) {
+                (Some(key), Some(value)) => fields.insert(key, value),
+                _ => return Err(invalid_replay(line)),
+            };
+        }
+        let field = |key: &str| {
+            fields
+                .get(key)
+                .cloned()
+                .ok_or_else(|| invalid_replay(&format!("missing {}", key)))
+        };
+
+        let ruleset = Ruleset {
+            rotation_system: RotationSystemKind::from_name(field("rotation")?)
+                .ok_or_else(|| invalid_replay("rotation"))?,
+            lock_delay: field("lock_delay")?
+                .parse()
+                .map_err(|_| invalid_replay("lock_delay"))?,
+            lock_reset: LockReset::from_name(field("lock_reset")?)
+                .ok_or_else(|| invalid_replay("lock_reset"))?,
+            randomizer: RandomizerKind::from_name(field("randomizer")?)
+                .ok_or_else(|| invalid_replay("randomizer"))?,
+            gravity: GravityCurve::from_name(field("gravity")?)
+                .ok_or_else(|| invalid_replay("gravity"))?,
+        };
+        let mut replay = Replay {
+            mode: GameMode::from_name(field("mode")?).ok_or_else(|| invalid_replay("mode"))?,
+            settings: ModeSettings::from_fields(&fields)
+                .ok_or_else(|| invalid_replay("mode settings"))?,
+            ruleset,
+            handling: Handling::from_fields(&fields).ok_or_else(|| invalid_replay("handling"))?,
+            seed: field("seed")?.parse().map_err(|_| invalid_replay("seed"))?,
+            frame_count: field("frames")?
+                .parse()
+                .map_err(|_| invalid_replay("frames"))?,
+            events: Vec::new(),
+        };
+
+        // every remaining line is an event
+        for line in lines {
+            let parts: Vec<&str> = line.split_whitespace().collect();
+            if parts.len() != 3 {
+                return Err(invalid_replay(line));
+            }
+
+            let frame = parts[0].parse().map_err(|_| invalid_replay(line))?;
+            let pressed = match parts[1] {
+                "press" => true,
+                "release" => false,
+                _ => return Err(invalid_replay(line)),
+            };
+            let action = action_from_name(parts[2]).ok_or_else(|| invalid_replay(line))?;
+
+            replay.events.push(ReplayEvent {
+                frame,
+                action,
+                pressed,
+            });
+        }
+
+        Ok(replay)
+    }
+
+    /// Saves the replay to the replay directory, named after the current time
+    /// in milliseconds, so that replays saved in quick succession do not
+    /// overwrite each other. Returns the path the replay was saved to.
+    pub fn save(&self, ctx: &mut Context) -> GameResult<PathBuf> {
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|elapsed| elapsed.as_millis())
+            .unwrap_or(0);
+        let path = PathBuf::from(format!("{}/{}.{}", REPLAY_DIR, timestamp, REPLAY_EXTENSION));
+
+        ctx.filesystem.create_dir(REPLAY_DIR)?;
+        let mut file = ctx.filesystem.create(&path)?;
+        self.write_to(&mut file)?;
+
+        Ok(path)
+    }
+
+    /// Loads the replay saved at `path`.
+    pub fn load(ctx: &mut Context, path: &Path) -> GameResult<Replay> {
+        let mut file = ctx.filesystem.open(path)?;
+        Replay::read_from(&mut file)
+    }
+}
+
+/// Returns the paths of all saved replays, newest first.
+pub fn saved_replays(ctx: &mut Context) -> GameResult<Vec<PathBuf>> {
+    if !ctx.filesystem.is_dir(REPLAY_DIR) {
+        return Ok(Vec::new());
+    }
+
+    let mut paths: Vec<PathBuf> = ctx
+        .filesystem
+        .read_dir(REPLAY_DIR)?
+        .filter(|path| path.extension() == Some(OsStr::new(REPLAY_EXTENSION)))
+        .collect();
+    paths.sort();
+    paths.reverse();
+
+    Ok(paths)
+}
+
+/// Feeds the inputs recorded in a `Replay` back into an `InputState`, one
+/// frame at a time.
+pub struct ReplayPlayer {
+    replay: Replay,
+    frame: u32,
+    next_event: usize,
+}
+
+impl ReplayPlayer {
+    pub fn new(replay: Replay) -> Self {
+        ReplayPlayer {
+            replay,
+            frame: 0,
+            next_event: 0,
+        }
+    }
+
+    /// Returns true once every recorded frame has been played.
+    pub fn is_finished(&self) -> bool {
+        self.frame >= self.replay.frame_count
+    }
+
+    /// Applies the inputs recorded for the next frame to `input`. Call this
+    /// once before each `GameCore::step`.
+    pub fn advance(&mut self, input: &mut InputState) {
+        while let Some(event) = self.replay.events.get(self.next_event) {
+            if event.frame > self.frame {
+                break;
+            }
+
+            input.set_action(event.action, event.pressed);
+            self.next_event += 1;
+        }
+
+        self.frame += 1;
+    }
+}
+
+fn invalid_replay(reason: &str) -> GameError {
+    GameError::ResourceLoadError(format!("Invalid replay: {}", reason))
+}
+
+fn action_name(action: InputAction) -> &'static str {
+    match action {
+        InputAction::Left => "left",
+        InputAction::Right => "right",
+        InputAction::SoftDrop => "soft_drop",
+        InputAction::HardDrop => "hard_drop",
+        InputAction::RotateClockwise => "rotate_cw",
+        InputAction::RotateCounterclockwise => "rotate_ccw",
+        InputAction::Hold => "hold",
+    }
+}
+
+fn action_from_name(name: &str) -> Option<InputAction> {
+    match name {
+        "left" => Some(InputAction::Left),
+        "right" => Some(InputAction::Right),
+        "soft_drop" => Some(InputAction::SoftDrop),
+        "hard_drop" => Some(InputAction::HardDrop),
+        "rotate_cw" => Some(InputAction::RotateClockwise),
+        "rotate_ccw" => Some(InputAction::RotateCounterclockwise),
+        "hold" => Some(InputAction::Hold),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Replay, ReplayPlayer, FRAME_DURATION};
+    use crate::states::play_state::game_core::{GameCore, GameEvent};
+    use crate::states::play_state::game_mode::{GameMode, ModeSettings};
+    use crate::states::play_state::handling::Handling;
+    use crate::states::play_state::input_state::{InputAction, InputState};
+    use crate::states::play_state::ruleset::Ruleset;
+
+    /// Plays a short scripted game, recording it as it goes. Returns the
+    /// replay and every event the core emitted.
+    fn record_game() -> (Replay, Vec<GameEvent>) {
+        let script = [
+            (0, InputAction::Left, true),
+            (20, InputAction::Left, false),
+            (25, InputAction::RotateClockwise, true),
+            (26, InputAction::RotateClockwise, false),
+            (30, InputAction::HardDrop, true),
+            (31, InputAction::HardDrop, false),
+            (40, InputAction::Hold, true),
+            (41, InputAction::Hold, false),
+            (50, InputAction::SoftDrop, true),
+            (90, InputAction::SoftDrop, false),
+        ];
+
+        let mut replay = Replay::new(
+            GameMode::Marathon,
+            ModeSettings::default(),
+            Ruleset::default(),
+            Handling::default(),
+            7,
+        );
+        let mut core = GameCore::new(
+            replay.mode,
+            replay.settings,
+            replay.ruleset,
+            replay.handling,
+            replay.seed,
+        );
+        let mut input = InputState::default();
+        let mut events = Vec::new();
+
+        for frame in 0..200 {
+            for (_, action, pressed) in script.iter().filter(|step| step.0 == frame) {
+                if input.set_action(*action, *pressed) {
+                    replay.record(*action, *pressed);
+                }
+            }
+            events.extend(core.step(&mut input, FRAME_DURATION));
+            replay.end_frame();
+        }
+
+        (replay, events)
+    }
+
+    #[test]
+    fn replay_survives_writing_and_reading() {
+        let (replay, _) = record_game();
+
+        let mut bytes = Vec::new();
+        replay.write_to(&mut bytes).unwrap();
+
+        assert_eq!(replay, Replay::read_from(&mut bytes.as_slice()).unwrap());
+    }
+
+    #[test]
+    fn playback_reproduces_the_game() {
+        let (replay, recorded_events) = record_game();
+
+        let mut core = GameCore::new(
+            replay.mode,
+            replay.settings,
+            replay.ruleset,
+            replay.handling,
+            replay.seed,
+        );
+        let mut input = InputState::default();
+        let mut player = ReplayPlayer::new(replay);
+        let mut events = Vec::new();
+
+        while !player.is_finished() {
+            player.advance(&mut input);
+            events.extend(core.step(&mut input, FRAME_DURATION));
+        }
+
+        assert_eq!(recorded_events, events);
+    }
+}
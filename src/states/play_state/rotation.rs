@@ -0,0 +1,277 @@
+use super::shapes::*;
+use super::tetromino::{Piece, PieceShape, PieceType};
+use super::well::Well;
+
+/// Describes how pieces look in each orientation and how they behave when
+/// rotated. Each `GameCore` uses a single `RotationSystem` for the whole game.
+pub trait RotationSystem {
+    /// Returns the four orientations of `piece_type`, indexed by rotation
+    /// state. State 0 is the spawn orientation and each clockwise rotation
+    /// adds 1.
+    fn shapes(&self, piece_type: PieceType) -> &'static [PieceShape; 4];
+
+    /// Returns the rotation state a new piece of `piece_type` spawns in.
+    fn spawn_rotation(&self, _piece_type: PieceType) -> u32 {
+        0
+    }
+
+    /// Returns the (x, y) offsets to try, in order, when rotating `piece` into
+    /// rotation state `to`. The first offset where the rotated piece fits is
+    /// used. If none of them fit, the rotation fails.
+    fn kicks(&self, well: &Well, piece: &Piece, to: u32) -> Vec<(i32, i32)>;
+
+    /// Returns a copy of the rotation system.
+    fn box_clone(&self) -> Box<dyn RotationSystem>;
+}
+
+impl Clone for Box<dyn RotationSystem> {
+    fn clone(&self) -> Self {
+        self.box_clone()
+    }
+}
+
+/// The rotation systems a game can be played with.
+#[derive(Debug, Copy, Clone, PartialEq, Default)]
+pub enum RotationSystemKind {
+    #[default]
+    Srs,
+    Ars,
+    Nrs,
+}
+
+impl RotationSystemKind {
+    /// Creates the `RotationSystem` described by this kind.
+    pub fn build(self) -> Box<dyn RotationSystem> {
+        match self {
+            RotationSystemKind::Srs => Box::new(Srs),
+            RotationSystemKind::Ars => Box::new(Ars),
+            RotationSystemKind::Nrs => Box::new(Nrs),
+        }
+    }
+
+    /// Returns the short name shown to the player.
+    pub fn name(self) -> &'static str {
+        match self {
+            RotationSystemKind::Srs => "SRS",
+            RotationSystemKind::Ars => "ARS",
+            RotationSystemKind::Nrs => "NRS",
+        }
+    }
+
+    /// Returns the rotation system with the short name `name`, if there is one.
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "SRS" => Some(RotationSystemKind::Srs),
+            "ARS" => Some(RotationSystemKind::Ars),
+            "NRS" => Some(RotationSystemKind::Nrs),
+            _ => None,
+        }
+    }
+
+    /// Returns the rotation system after this one, wrapping around at the end.
+    pub fn next(self) -> Self {
+        match self {
+            RotationSystemKind::Srs => RotationSystemKind::Ars,
+            RotationSystemKind::Ars => RotationSystemKind::Nrs,
+            RotationSystemKind::Nrs => RotationSystemKind::Srs,
+        }
+    }
+}
+
+/// The Super Rotation System used by most modern games. Every rotation tries
+/// five positions taken from the SRS wall kick tables.
+#[derive(Clone)]
+pub struct Srs;
+
+impl RotationSystem for Srs {
+    fn shapes(&self, piece_type: PieceType) -> &'static [PieceShape; 4] {
+        match piece_type {
+            PieceType::O => &SRS_O_SHAPES,
+            PieceType::J => &SRS_J_SHAPES,
+            PieceType::I => &SRS_I_SHAPES,
+            PieceType::S => &SRS_S_SHAPES,
+            PieceType::Z => &SRS_Z_SHAPES,
+            PieceType::L => &SRS_L_SHAPES,
+            PieceType::T => &SRS_T_SHAPES,
+        }
+    }
+
+    fn kicks(&self, _well: &Well, piece: &Piece, to: u32) -> Vec<(i32, i32)> {
+        let index = kick_table_index(piece.get_rotation_index(), to);
+        match piece.get_type() {
+            PieceType::O => vec![(0, 0)],
+            PieceType::I => SRS_I_KICKS[index].to_vec(),
+            _ => SRS_JLSTZ_KICKS[index].to_vec(),
+        }
+    }
+
+    fn box_clone(&self) -> Box<dyn RotationSystem> {
+        Box::new(self.clone())
+    }
+}
+
+/// The Arika Rotation System from the TGM series. A blocked rotation may kick
+/// one cell to the right and then one cell to the left. The I piece never
+/// kicks, and J, L and T follow the centre column rule.
+#[derive(Clone)]
+pub struct Ars;
+
+impl Ars {
+    /// The centre column rule: scanning the new orientation of a J, L or T
+    /// piece in reading order, if the first block that overlaps the stack is in
+    /// the centre column of the piece's 3x3 box, the rotation may not kick.
+    fn is_centre_column_blocked(well: &Well, piece: &Piece, to: u32) -> bool {
+        let shape = Ars.shapes(piece.get_type())[to as usize];
+
+        for (r, row) in shape.iter().enumerate() {
+            for (c, block) in row.iter().enumerate() {
+                if *block == 0 {
+                    continue;
+                }
+
+                let mut single = [[0; 4]; 4];
+                single[r][c] = *block;
+                if well.check_for_collisions(&single, piece.top_left) {
+                    return c == 1;
+                }
+            }
+        }
+
+        false
+    }
+}
+
+impl RotationSystem for Ars {
+    fn shapes(&self, piece_type: PieceType) -> &'static [PieceShape; 4] {
+        match piece_type {
+            PieceType::O => &ARS_O_SHAPES,
+            PieceType::J => &ARS_J_SHAPES,
+            PieceType::I => &ARS_I_SHAPES,
+            PieceType::S => &ARS_S_SHAPES,
+            PieceType::Z => &ARS_Z_SHAPES,
+            PieceType::L => &ARS_L_SHAPES,
+            PieceType::T => &ARS_T_SHAPES,
+        }
+    }
+
+    fn kicks(&self, well: &Well, piece: &Piece, to: u32) -> Vec<(i32, i32)> {
+        match piece.get_type() {
+            PieceType::I | PieceType::O => vec![(0, 0)],
+            PieceType::J | PieceType::L | PieceType::T
+                if Ars::is_centre_column_blocked(well, piece, to) =>
+            {
+                vec![(0, 0)]
+            }
+            _ => vec![(0, 0), (1, 0), (-1, 0)],
+        }
+    }
+
+    fn box_clone(&self) -> Box<dyn RotationSystem> {
+        Box::new(self.clone())
+    }
+}
+
+/// The Nintendo Rotation System from the NES version. Pieces never kick: a
+/// rotation either fits where it is or fails.
+#[derive(Clone)]
+pub struct Nrs;
+
+impl RotationSystem for Nrs {
+    fn shapes(&self, piece_type: PieceType) -> &'static [PieceShape; 4] {
+        match piece_type {
+            PieceType::O => &NRS_O_SHAPES,
+            PieceType::J => &NRS_J_SHAPES,
+            PieceType::I => &NRS_I_SHAPES,
+            PieceType::S => &NRS_S_SHAPES,
+            PieceType::Z => &NRS_Z_SHAPES,
+            PieceType::L => &NRS_L_SHAPES,
+            PieceType::T => &NRS_T_SHAPES,
+        }
+    }
+
+    fn kicks(&self, _well: &Well, _piece: &Piece, _to: u32) -> Vec<(i32, i32)> {
+        vec![(0, 0)]
+    }
+
+    fn box_clone(&self) -> Box<dyn RotationSystem> {
+        Box::new(self.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Ars, RotationSystem, Srs};
+    use crate::states::play_state::tetromino::{next_rotation_index, Piece, PieceType};
+    use crate::states::play_state::well::Well;
+    use ggez::graphics::Point2;
+
+    /// Returns a well with an O piece filling columns 4 and 5 of rows 10 and 11.
+    fn well_with_block() -> Well {
+        let mut well = Well::new();
+        let mut block = Piece::new(PieceType::O, &Ars);
+        block.top_left = Point2::new(3.0, 9.0);
+        well.land(&block);
+        well
+    }
+
+    #[test]
+    fn ars_centre_column_prevents_kicks() {
+        let well = well_with_block();
+        let mut piece = Piece::new(PieceType::T, &Ars);
+
+        // the top of the centre column overlaps the block
+        piece.top_left = Point2::new(4.0, 10.0);
+        assert_eq!(vec![(0, 0)], Ars.kicks(&well, &piece, 1));
+
+        // only the left column overlaps the block
+        piece.top_left = Point2::new(5.0, 10.0);
+        assert_eq!(vec![(0, 0), (1, 0), (-1, 0)], Ars.kicks(&well, &piece, 1));
+    }
+
+    /// Returns the first of the SRS kicks that lets `piece` rotate in
+    /// `direction`, as the game picks it, or `None` if the rotation is refused.
+    fn srs_kick(well: &Well, piece: &Piece, direction: i32) -> Option<(i32, i32)> {
+        let to = next_rotation_index(piece.get_rotation_index(), direction);
+        let shape = piece.get_next_shape(direction);
+        Srs.kicks(well, piece, to).into_iter().find(|(x, y)| {
+            let position = Point2::new(piece.top_left.x + *x as f32, piece.top_left.y + *y as f32);
+            !well.check_for_collisions(&shape, position)
+        })
+    }
+
+    #[test]
+    fn srs_t_kicks_off_the_left_wall() {
+        let well = Well::new();
+        let mut piece = Piece::new(PieceType::T, &Srs);
+        piece.change_shape(1);
+        // the stem of the T is against the left wall
+        piece.top_left = Point2::new(-1.0, 10.0);
+
+        assert_eq!(Some((1, 0)), srs_kick(&well, &piece, -1));
+    }
+
+    #[test]
+    fn srs_i_uses_its_own_kick_table() {
+        let well = Well::new();
+        let mut piece = Piece::new(PieceType::I, &Srs);
+        piece.change_shape(1);
+        // standing upright in the last column
+        piece.top_left = Point2::new(7.0, 10.0);
+
+        // a JLSTZ piece would try one column to the right before anything else
+        assert_eq!(Some((-1, 0)), srs_kick(&well, &piece, -1));
+    }
+
+    #[test]
+    fn srs_rotation_is_refused_when_no_kick_fits() {
+        let well = Well::from_rows(&["#########.", "#########.", "#########.", "#########."]);
+        let mut piece = Piece::new(PieceType::I, &Srs);
+        piece.change_shape(1);
+        // standing upright at the bottom of the gap in the last column
+        piece.top_left = Point2::new(7.0, 18.0);
+        assert!(!well.check_for_collisions(&piece.get_shape(), piece.top_left));
+
+        assert_eq!(None, srs_kick(&well, &piece, -1));
+        assert_eq!(None, srs_kick(&well, &piece, 1));
+    }
+}
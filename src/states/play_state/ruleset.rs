@@ -0,0 +1,79 @@
+use super::gravity::GravityCurve;
+use super::randomizer::RandomizerKind;
+use super::rotation::RotationSystemKind;
+
+/// The default time, in seconds, that a piece can rest on the stack before it
+/// locks.
+const DEFAULT_LOCK_DELAY: f64 = 0.5;
+
+/// Describes what resets the lock delay timer of a piece resting on the stack.
+#[derive(Debug, Copy, Clone, PartialEq, Default)]
+pub enum LockReset {
+    /// Moving or rotating resets the timer, up to a limited number of times.
+    /// Reaching a new lowest row restores all of the resets.
+    #[default]
+    Move,
+    /// Only reaching a new lowest row resets the timer.
+    Step,
+    /// Nothing resets the timer once it has started.
+    Classic,
+    /// Moving or rotating always resets the timer.
+    Infinity,
+}
+
+impl LockReset {
+    /// Returns the short name shown to the player.
+    pub fn name(self) -> &'static str {
+        match self {
+            LockReset::Move => "Move",
+            LockReset::Step => "Step",
+            LockReset::Classic => "Classic",
+            LockReset::Infinity => "Infinity",
+        }
+    }
+
+    /// Returns the reset policy with the short name `name`, if there is one.
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "Move" => Some(LockReset::Move),
+            "Step" => Some(LockReset::Step),
+            "Classic" => Some(LockReset::Classic),
+            "Infinity" => Some(LockReset::Infinity),
+            _ => None,
+        }
+    }
+
+    /// Returns the reset policy after this one, wrapping around at the end.
+    pub fn next(self) -> Self {
+        match self {
+            LockReset::Move => LockReset::Step,
+            LockReset::Step => LockReset::Classic,
+            LockReset::Classic => LockReset::Infinity,
+            LockReset::Infinity => LockReset::Move,
+        }
+    }
+}
+
+/// The rules a game is played with. A `Ruleset` is chosen before a game starts
+/// and stays the same for the whole game.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Ruleset {
+    pub rotation_system: RotationSystemKind,
+    /// How long, in seconds, a piece can rest on the stack before it locks.
+    pub lock_delay: f64,
+    pub lock_reset: LockReset,
+    pub randomizer: RandomizerKind,
+    pub gravity: GravityCurve,
+}
+
+impl Default for Ruleset {
+    fn default() -> Self {
+        Ruleset {
+            rotation_system: RotationSystemKind::default(),
+            lock_delay: DEFAULT_LOCK_DELAY,
+            lock_reset: LockReset::default(),
+            randomizer: RandomizerKind::default(),
+            gravity: GravityCurve::default(),
+        }
+    }
+}
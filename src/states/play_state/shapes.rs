@@ -1,61 +1,198 @@
 use crate::states::play_state::tetromino::PieceShape;
 
-// define all of our shapes & rotations
-pub const O_SHAPES: [PieceShape; 4] = [
+// define all of our shapes & rotations. In every set of shapes, index 0 is the
+// spawn orientation and each following index is one clockwise rotation away
+// from the previous one.
+
+// Super Rotation System (SRS). J, L, S, T and Z rotate around the centre of the
+// top-left 3x3 of their 4x4 box while I and O rotate around the centre of the
+// whole box.
+pub const SRS_O_SHAPES: [PieceShape; 4] = [
+    [[0, 4, 4, 0],
+     [0, 4, 4, 0],
+     [0, 0, 0, 0],
+     [0, 0, 0, 0]],
+    [[0, 4, 4, 0],
+     [0, 4, 4, 0],
+     [0, 0, 0, 0],
+     [0, 0, 0, 0]],
+    [[0, 4, 4, 0],
+     [0, 4, 4, 0],
+     [0, 0, 0, 0],
+     [0, 0, 0, 0]],
+    [[0, 4, 4, 0],
+     [0, 4, 4, 0],
+     [0, 0, 0, 0],
+     [0, 0, 0, 0]],
+];
+pub const SRS_J_SHAPES: [PieceShape; 4] = [
+    [[2, 0, 0, 0],
+     [2, 2, 2, 0],
+     [0, 0, 0, 0],
+     [0, 0, 0, 0]],
+    [[0, 2, 2, 0],
+     [0, 2, 0, 0],
+     [0, 2, 0, 0],
+     [0, 0, 0, 0]],
     [[0, 0, 0, 0],
-     [0, 0, 4, 4],
-     [0, 0, 4, 4],
+     [2, 2, 2, 0],
+     [0, 0, 2, 0],
+     [0, 0, 0, 0]],
+    [[0, 2, 0, 0],
+     [0, 2, 0, 0],
+     [2, 2, 0, 0],
+     [0, 0, 0, 0]],
+];
+pub const SRS_L_SHAPES: [PieceShape; 4] = [
+    [[0, 0, 3, 0],
+     [3, 3, 3, 0],
+     [0, 0, 0, 0],
+     [0, 0, 0, 0]],
+    [[0, 3, 0, 0],
+     [0, 3, 0, 0],
+     [0, 3, 3, 0],
      [0, 0, 0, 0]],
     [[0, 0, 0, 0],
-     [0, 0, 4, 4],
-     [0, 0, 4, 4],
+     [3, 3, 3, 0],
+     [3, 0, 0, 0],
+     [0, 0, 0, 0]],
+    [[3, 3, 0, 0],
+     [0, 3, 0, 0],
+     [0, 3, 0, 0],
      [0, 0, 0, 0]],
+];
+pub const SRS_I_SHAPES: [PieceShape; 4] = [
     [[0, 0, 0, 0],
-     [0, 0, 4, 4],
-     [0, 0, 4, 4],
+     [1, 1, 1, 1],
+     [0, 0, 0, 0],
      [0, 0, 0, 0]],
+    [[0, 0, 1, 0],
+     [0, 0, 1, 0],
+     [0, 0, 1, 0],
+     [0, 0, 1, 0]],
     [[0, 0, 0, 0],
-     [0, 0, 4, 4],
-     [0, 0, 4, 4],
+     [0, 0, 0, 0],
+     [1, 1, 1, 1],
      [0, 0, 0, 0]],
+    [[0, 1, 0, 0],
+     [0, 1, 0, 0],
+     [0, 1, 0, 0],
+     [0, 1, 0, 0]],
 ];
-pub const J_SHAPES: [PieceShape; 4] = [
-    [[0, 0, 2, 0],
-     [0, 0, 2, 0],
-     [0, 2, 2, 0],
+pub const SRS_S_SHAPES: [PieceShape; 4] = [
+    [[0, 5, 5, 0],
+     [5, 5, 0, 0],
+     [0, 0, 0, 0],
      [0, 0, 0, 0]],
-    [[0, 2, 0, 0],
-     [0, 2, 2, 2],
+    [[0, 5, 0, 0],
+     [0, 5, 5, 0],
+     [0, 0, 5, 0],
+     [0, 0, 0, 0]],
+    [[0, 0, 0, 0],
+     [0, 5, 5, 0],
+     [5, 5, 0, 0],
+     [0, 0, 0, 0]],
+    [[5, 0, 0, 0],
+     [5, 5, 0, 0],
+     [0, 5, 0, 0],
+     [0, 0, 0, 0]],
+];
+pub const SRS_Z_SHAPES: [PieceShape; 4] = [
+    [[7, 7, 0, 0],
+     [0, 7, 7, 0],
      [0, 0, 0, 0],
      [0, 0, 0, 0]],
-    [[0, 0, 2, 2],
-     [0, 0, 2, 0],
-     [0, 0, 2, 0],
+    [[0, 0, 7, 0],
+     [0, 7, 7, 0],
+     [0, 7, 0, 0],
      [0, 0, 0, 0]],
     [[0, 0, 0, 0],
-     [0, 2, 2, 2],
-     [0, 0, 0, 2],
+     [7, 7, 0, 0],
+     [0, 7, 7, 0],
+     [0, 0, 0, 0]],
+    [[0, 7, 0, 0],
+     [7, 7, 0, 0],
+     [7, 0, 0, 0],
      [0, 0, 0, 0]],
 ];
-pub const L_SHAPES: [PieceShape; 4] = [
-    [[0, 0, 0, 3],
-     [0, 3, 3, 3],
+pub const SRS_T_SHAPES: [PieceShape; 4] = [
+    [[0, 6, 0, 0],
+     [6, 6, 6, 0],
      [0, 0, 0, 0],
      [0, 0, 0, 0]],
-    [[0, 0, 3, 0],
-     [0, 0, 3, 0],
-     [0, 0, 3, 3],
+    [[0, 6, 0, 0],
+     [0, 6, 6, 0],
+     [0, 6, 0, 0],
+     [0, 0, 0, 0]],
+    [[0, 0, 0, 0],
+     [6, 6, 6, 0],
+     [0, 6, 0, 0],
+     [0, 0, 0, 0]],
+    [[0, 6, 0, 0],
+     [6, 6, 0, 0],
+     [0, 6, 0, 0],
+     [0, 0, 0, 0]],
+];
+
+
+// Arika Rotation System (ARS), as used by the TGM series. J, L and T spawn flat
+// side up and every orientation rests on the bottom of its 3x3 box. I, S and Z
+// only have two distinct orientations.
+pub const ARS_O_SHAPES: [PieceShape; 4] = [
+    [[0, 0, 0, 0],
+     [0, 4, 4, 0],
+     [0, 4, 4, 0],
+     [0, 0, 0, 0]],
+    [[0, 0, 0, 0],
+     [0, 4, 4, 0],
+     [0, 4, 4, 0],
      [0, 0, 0, 0]],
     [[0, 0, 0, 0],
-     [0, 3, 3, 3],
+     [0, 4, 4, 0],
+     [0, 4, 4, 0],
+     [0, 0, 0, 0]],
+    [[0, 0, 0, 0],
+     [0, 4, 4, 0],
+     [0, 4, 4, 0],
+     [0, 0, 0, 0]],
+];
+pub const ARS_J_SHAPES: [PieceShape; 4] = [
+    [[0, 0, 0, 0],
+     [2, 2, 2, 0],
+     [0, 0, 2, 0],
+     [0, 0, 0, 0]],
+    [[0, 2, 0, 0],
+     [0, 2, 0, 0],
+     [2, 2, 0, 0],
+     [0, 0, 0, 0]],
+    [[0, 0, 0, 0],
+     [2, 0, 0, 0],
+     [2, 2, 2, 0],
+     [0, 0, 0, 0]],
+    [[0, 2, 2, 0],
+     [0, 2, 0, 0],
+     [0, 2, 0, 0],
+     [0, 0, 0, 0]],
+];
+pub const ARS_L_SHAPES: [PieceShape; 4] = [
+    [[0, 0, 0, 0],
+     [3, 3, 3, 0],
+     [3, 0, 0, 0],
+     [0, 0, 0, 0]],
+    [[3, 3, 0, 0],
+     [0, 3, 0, 0],
      [0, 3, 0, 0],
      [0, 0, 0, 0]],
-    [[0, 3, 3, 0],
-     [0, 0, 3, 0],
+    [[0, 0, 0, 0],
      [0, 0, 3, 0],
+     [3, 3, 3, 0],
+     [0, 0, 0, 0]],
+    [[0, 3, 0, 0],
+     [0, 3, 0, 0],
+     [0, 3, 3, 0],
      [0, 0, 0, 0]],
 ];
-pub const I_SHAPES: [PieceShape; 4] = [
+pub const ARS_I_SHAPES: [PieceShape; 4] = [
     [[0, 0, 0, 0],
      [1, 1, 1, 1],
      [0, 0, 0, 0],
@@ -73,57 +210,229 @@ pub const I_SHAPES: [PieceShape; 4] = [
      [0, 0, 1, 0],
      [0, 0, 1, 0]],
 ];
-pub const S_SHAPES: [PieceShape; 4] = [
+pub const ARS_S_SHAPES: [PieceShape; 4] = [
     [[0, 0, 0, 0],
-     [0, 0, 5, 5],
      [0, 5, 5, 0],
+     [5, 5, 0, 0],
      [0, 0, 0, 0]],
-    [[0, 0, 5, 0],
-     [0, 0, 5, 5],
-     [0, 0, 0, 5],
+    [[5, 0, 0, 0],
+     [5, 5, 0, 0],
+     [0, 5, 0, 0],
      [0, 0, 0, 0]],
     [[0, 0, 0, 0],
-     [0, 0, 5, 5],
      [0, 5, 5, 0],
+     [5, 5, 0, 0],
      [0, 0, 0, 0]],
-    [[0, 0, 5, 0],
-     [0, 0, 5, 5],
-     [0, 0, 0, 5],
+    [[5, 0, 0, 0],
+     [5, 5, 0, 0],
+     [0, 5, 0, 0],
      [0, 0, 0, 0]],
 ];
-pub const Z_SHAPES: [PieceShape; 4] = [
+pub const ARS_Z_SHAPES: [PieceShape; 4] = [
     [[0, 0, 0, 0],
+     [7, 7, 0, 0],
      [0, 7, 7, 0],
-     [0, 0, 7, 7],
      [0, 0, 0, 0]],
-    [[0, 0, 0, 7],
-     [0, 0, 7, 7],
-     [0, 0, 7, 0],
+    [[0, 0, 7, 0],
+     [0, 7, 7, 0],
+     [0, 7, 0, 0],
      [0, 0, 0, 0]],
     [[0, 0, 0, 0],
+     [7, 7, 0, 0],
      [0, 7, 7, 0],
-     [0, 0, 7, 7],
      [0, 0, 0, 0]],
-    [[0, 0, 0, 7],
-     [0, 0, 7, 7],
-     [0, 0, 7, 0],
+    [[0, 0, 7, 0],
+     [0, 7, 7, 0],
+     [0, 7, 0, 0],
      [0, 0, 0, 0]],
 ];
-pub const T_SHAPES: [PieceShape; 4] = [
+pub const ARS_T_SHAPES: [PieceShape; 4] = [
+    [[0, 0, 0, 0],
+     [6, 6, 6, 0],
+     [0, 6, 0, 0],
+     [0, 0, 0, 0]],
+    [[0, 6, 0, 0],
+     [6, 6, 0, 0],
+     [0, 6, 0, 0],
+     [0, 0, 0, 0]],
+    [[0, 0, 0, 0],
+     [0, 6, 0, 0],
+     [6, 6, 6, 0],
+     [0, 0, 0, 0]],
+    [[0, 6, 0, 0],
+     [0, 6, 6, 0],
+     [0, 6, 0, 0],
+     [0, 0, 0, 0]],
+];
+
+// Nintendo Rotation System (NRS), as used by the NES version. Pieces rotate
+// around a fixed centre block and I, S and Z only have two distinct
+// orientations.
+pub const NRS_O_SHAPES: [PieceShape; 4] = [
     [[0, 0, 0, 0],
-     [0, 0, 6, 0],
-     [0, 6, 6, 6],
+     [0, 4, 4, 0],
+     [0, 4, 4, 0],
      [0, 0, 0, 0]],
     [[0, 0, 0, 0],
-     [0, 0, 6, 0],
-     [0, 0, 6, 6],
-     [0, 0, 6, 0]],
+     [0, 4, 4, 0],
+     [0, 4, 4, 0],
+     [0, 0, 0, 0]],
+    [[0, 0, 0, 0],
+     [0, 4, 4, 0],
+     [0, 4, 4, 0],
+     [0, 0, 0, 0]],
+    [[0, 0, 0, 0],
+     [0, 4, 4, 0],
+     [0, 4, 4, 0],
+     [0, 0, 0, 0]],
+];
+pub const NRS_J_SHAPES: [PieceShape; 4] = [
+    [[0, 0, 0, 0],
+     [2, 2, 2, 0],
+     [0, 0, 2, 0],
+     [0, 0, 0, 0]],
+    [[0, 2, 0, 0],
+     [0, 2, 0, 0],
+     [2, 2, 0, 0],
+     [0, 0, 0, 0]],
+    [[2, 0, 0, 0],
+     [2, 2, 2, 0],
+     [0, 0, 0, 0],
+     [0, 0, 0, 0]],
+    [[0, 2, 2, 0],
+     [0, 2, 0, 0],
+     [0, 2, 0, 0],
+     [0, 0, 0, 0]],
+];
+pub const NRS_L_SHAPES: [PieceShape; 4] = [
     [[0, 0, 0, 0],
+     [3, 3, 3, 0],
+     [3, 0, 0, 0],
+     [0, 0, 0, 0]],
+    [[3, 3, 0, 0],
+     [0, 3, 0, 0],
+     [0, 3, 0, 0],
+     [0, 0, 0, 0]],
+    [[0, 0, 3, 0],
+     [3, 3, 3, 0],
      [0, 0, 0, 0],
-     [0, 6, 6, 6],
-     [0, 0, 6, 0]],
+     [0, 0, 0, 0]],
+    [[0, 3, 0, 0],
+     [0, 3, 0, 0],
+     [0, 3, 3, 0],
+     [0, 0, 0, 0]],
+];
+pub const NRS_I_SHAPES: [PieceShape; 4] = [
     [[0, 0, 0, 0],
-     [0, 0, 6, 0],
+     [0, 0, 0, 0],
+     [1, 1, 1, 1],
+     [0, 0, 0, 0]],
+    [[0, 0, 1, 0],
+     [0, 0, 1, 0],
+     [0, 0, 1, 0],
+     [0, 0, 1, 0]],
+    [[0, 0, 0, 0],
+     [0, 0, 0, 0],
+     [1, 1, 1, 1],
+     [0, 0, 0, 0]],
+    [[0, 0, 1, 0],
+     [0, 0, 1, 0],
+     [0, 0, 1, 0],
+     [0, 0, 1, 0]],
+];
+pub const NRS_S_SHAPES: [PieceShape; 4] = [
+    [[0, 0, 0, 0],
+     [0, 5, 5, 0],
+     [5, 5, 0, 0],
+     [0, 0, 0, 0]],
+    [[0, 5, 0, 0],
+     [0, 5, 5, 0],
+     [0, 0, 5, 0],
+     [0, 0, 0, 0]],
+    [[0, 0, 0, 0],
+     [0, 5, 5, 0],
+     [5, 5, 0, 0],
+     [0, 0, 0, 0]],
+    [[0, 5, 0, 0],
+     [0, 5, 5, 0],
+     [0, 0, 5, 0],
+     [0, 0, 0, 0]],
+];
+pub const NRS_Z_SHAPES: [PieceShape; 4] = [
+    [[0, 0, 0, 0],
+     [7, 7, 0, 0],
+     [0, 7, 7, 0],
+     [0, 0, 0, 0]],
+    [[0, 0, 7, 0],
+     [0, 7, 7, 0],
+     [0, 7, 0, 0],
+     [0, 0, 0, 0]],
+    [[0, 0, 0, 0],
+     [7, 7, 0, 0],
+     [0, 7, 7, 0],
+     [0, 0, 0, 0]],
+    [[0, 0, 7, 0],
+     [0, 7, 7, 0],
+     [0, 7, 0, 0],
+     [0, 0, 0, 0]],
+];
+pub const NRS_T_SHAPES: [PieceShape; 4] = [
+    [[0, 0, 0, 0],
+     [6, 6, 6, 0],
+     [0, 6, 0, 0],
+     [0, 0, 0, 0]],
+    [[0, 6, 0, 0],
+     [6, 6, 0, 0],
+     [0, 6, 0, 0],
+     [0, 0, 0, 0]],
+    [[0, 6, 0, 0],
+     [6, 6, 6, 0],
+     [0, 0, 0, 0],
+     [0, 0, 0, 0]],
+    [[0, 6, 0, 0],
      [0, 6, 6, 0],
-     [0, 0, 6, 0]],
+     [0, 6, 0, 0],
+     [0, 0, 0, 0]],
+];
+/// A list of (x, y) offsets to try, in order, when rotating a piece.
+pub type KickTable = [(i32, i32); 5];
+
+// SRS wall kick offsets, one row per rotation transition in the order given by
+// `kick_table_index`. The offsets are the usual published SRS values with the
+// y-axis flipped, as rows in the well count downwards.
+pub const SRS_JLSTZ_KICKS: [KickTable; 8] = [
+    [(0, 0), (-1, 0), (-1, -1), (0, 2), (-1, 2)], // 0 -> R
+    [(0, 0), (1, 0), (1, 1), (0, -2), (1, -2)], // R -> 0
+    [(0, 0), (1, 0), (1, 1), (0, -2), (1, -2)], // R -> 2
+    [(0, 0), (-1, 0), (-1, -1), (0, 2), (-1, 2)], // 2 -> R
+    [(0, 0), (1, 0), (1, -1), (0, 2), (1, 2)], // 2 -> L
+    [(0, 0), (-1, 0), (-1, 1), (0, -2), (-1, -2)], // L -> 2
+    [(0, 0), (-1, 0), (-1, 1), (0, -2), (-1, -2)], // L -> 0
+    [(0, 0), (1, 0), (1, -1), (0, 2), (1, 2)], // 0 -> L
 ];
+pub const SRS_I_KICKS: [KickTable; 8] = [
+    [(0, 0), (-2, 0), (1, 0), (-2, 1), (1, -2)], // 0 -> R
+    [(0, 0), (2, 0), (-1, 0), (2, -1), (-1, 2)], // R -> 0
+    [(0, 0), (-1, 0), (2, 0), (-1, -2), (2, 1)], // R -> 2
+    [(0, 0), (1, 0), (-2, 0), (1, 2), (-2, -1)], // 2 -> R
+    [(0, 0), (2, 0), (-1, 0), (2, -1), (-1, 2)], // 2 -> L
+    [(0, 0), (-2, 0), (1, 0), (-2, 1), (1, -2)], // L -> 2
+    [(0, 0), (1, 0), (-2, 0), (1, 2), (-2, -1)], // L -> 0
+    [(0, 0), (-1, 0), (2, 0), (-1, -2), (2, 1)], // 0 -> L
+];
+
+/// Returns the row of a kick table for a rotation from state `from` to state
+/// `to`. States are numbered 0 (spawn), 1 (R), 2 and 3 (L).
+pub fn kick_table_index(from: u32, to: u32) -> usize {
+    match (from, to) {
+        (0, 1) => 0,
+        (1, 0) => 1,
+        (1, 2) => 2,
+        (2, 1) => 3,
+        (2, 3) => 4,
+        (3, 2) => 5,
+        (3, 0) => 6,
+        (0, 3) => 7,
+        _ => unreachable!(),
+    }
+}
@@ -0,0 +1,125 @@
+use super::tetromino::{Piece, PieceType};
+use super::well::Well;
+
+/// The index of the SRS kick that moves a piece one column over and two rows
+/// down. A T-spin that needed this kick always counts as a full T-spin.
+const TST_KICK_INDEX: usize = 4;
+
+/// The kinds of T-spin that can be performed.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum TSpin {
+    Mini,
+    Full,
+}
+
+/// Check whether locking `piece` where it is would be a T-spin, using the
+/// 3-corner rule. At least three of the four cells diagonal to the centre of
+/// the T must be occupied (the walls and floor count as occupied). If both of
+/// the corners on the side the T is pointing to are occupied, or the rotation
+/// into place used the last SRS kick, it is a full T-spin; otherwise it is a
+/// mini T-spin.
+///
+/// This only looks at the well and the piece, so the caller must make sure the
+/// last successful action on the piece was a rotation.
+pub fn detect_t_spin(well: &Well, piece: &Piece, kick_index: usize) -> Option<TSpin> {
+    if piece.get_type() != PieceType::T {
+        return None;
+    }
+
+    let shape = piece.get_shape();
+    let is_block =
+        |r: i32, c: i32| r >= 0 && c >= 0 && r < 4 && c < 4 && shape[r as usize][c as usize] != 0;
+    let directions = [(-1, 0), (0, 1), (1, 0), (0, -1)];
+
+    // the centre of a T is the only block with three neighbours
+    let mut centre = None;
+    for r in 0..4 {
+        for c in 0..4 {
+            let neighbours = directions
+                .iter()
+                .filter(|(dr, dc)| is_block(r + dr, c + dc))
+                .count();
+            if is_block(r, c) && neighbours == 3 {
+                centre = Some((r, c));
+            }
+        }
+    }
+    let (centre_r, centre_c) = centre?;
+
+    // the T points away from the side of the centre without a neighbour
+    let (back_r, back_c) = *directions
+        .iter()
+        .find(|(dr, dc)| !is_block(centre_r + dr, centre_c + dc))?;
+    let (front_r, front_c) = (-back_r, -back_c);
+
+    let well_r = centre_r + piece.top_left.y as i32;
+    let well_c = centre_c + piece.top_left.x as i32;
+    let corners = [(-1, -1), (-1, 1), (1, -1), (1, 1)];
+    let occupied = corners
+        .iter()
+        .filter(|(dr, dc)| well.is_occupied(well_c + dc, well_r + dr))
+        .count();
+
+    if occupied < 3 {
+        return None;
+    }
+
+    // front corners share their row (or column) offset with the pointing side
+    let front_occupied = corners
+        .iter()
+        .filter(|(dr, dc)| (front_r != 0 && *dr == front_r) || (front_c != 0 && *dc == front_c))
+        .filter(|(dr, dc)| well.is_occupied(well_c + dc, well_r + dr))
+        .count();
+
+    if front_occupied == 2 || kick_index == TST_KICK_INDEX {
+        Some(TSpin::Full)
+    } else {
+        Some(TSpin::Mini)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{detect_t_spin, TSpin};
+    use crate::states::play_state::rotation::Srs;
+    use crate::states::play_state::tetromino::{Piece, PieceType};
+    use crate::states::play_state::well::Well;
+    use ggez::graphics::Point2;
+
+    /// Returns a T piece pointing down with its centre at column 4 of row 20.
+    fn t_pointing_down() -> Piece {
+        let mut piece = Piece::new(PieceType::T, &Srs);
+        piece.change_shape(1);
+        piece.change_shape(1);
+        piece.top_left = Point2::new(3.0, 19.0);
+        piece
+    }
+
+    #[test]
+    fn both_front_corners_is_full_t_spin() {
+        let well = Well::from_rows(&["...#......", "##.....###", "####.#####"]);
+        assert_eq!(
+            Some(TSpin::Full),
+            detect_t_spin(&well, &t_pointing_down(), 0)
+        );
+    }
+
+    #[test]
+    fn one_front_corner_is_mini_unless_last_kick() {
+        let well = Well::from_rows(&["...#.#....", "##.....###", "####..####"]);
+        assert_eq!(
+            Some(TSpin::Mini),
+            detect_t_spin(&well, &t_pointing_down(), 0)
+        );
+        assert_eq!(
+            Some(TSpin::Full),
+            detect_t_spin(&well, &t_pointing_down(), 4)
+        );
+    }
+
+    #[test]
+    fn two_corners_is_not_a_t_spin() {
+        let well = Well::from_rows(&["..........", "##.....###", "####.#####"]);
+        assert_eq!(None, detect_t_spin(&well, &t_pointing_down(), 0));
+    }
+}
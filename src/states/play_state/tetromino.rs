@@ -1,5 +1,5 @@
-use super::shapes::*;
-use super::well::Y_OFFSET;
+use super::rotation::RotationSystem;
+use super::well::{CENTRE_X, Y_OFFSET};
 use super::BLOCK_SIZE;
 use ggez::graphics::{Color, DrawParam, Point2};
 use ggez::{graphics, Context, GameResult};
@@ -8,6 +8,9 @@ use ggez::{graphics, Context, GameResult};
 /// an empty space while a 1 is solid.
 pub type PieceShape = [[u32; 4]; 4];
 
+/// The column of the well that a new piece's 4x4 box is placed in.
+const SPAWN_X: f32 = 3.0;
+
 /// All the possible piece types that can be taken out of `PieceBag`.
 #[derive(Debug, Copy, Clone, PartialEq)]
 pub enum PieceType {
@@ -21,7 +24,9 @@ pub enum PieceType {
 }
 
 /// The tetromino piece that the player controls.
+#[derive(Debug, Copy, Clone)]
 pub struct Piece {
+    shapes: &'static [PieceShape; 4],
     shape: PieceShape,
     shape_type: PieceType,
     current_rotation_index: u32,
@@ -31,42 +36,35 @@ pub struct Piece {
 }
 
 impl Piece {
-    /// Creates a new piece of type `PieceType`.
-    pub fn new(shape_type: PieceType) -> Self {
-        let shape = piece_type_to_shape(shape_type, 0);
+    /// Creates a new piece of type `PieceType`, using the orientations and
+    /// spawn orientation given by `rotation_system`. The piece is placed so
+    /// that the bottom of its shape is in the first visible row of the well.
+    pub fn new(shape_type: PieceType, rotation_system: &dyn RotationSystem) -> Self {
+        let shapes = rotation_system.shapes(shape_type);
+        let rotation_index = rotation_system.spawn_rotation(shape_type);
+        let shape = shapes[rotation_index as usize];
+
+        let bottom_row = shape
+            .iter()
+            .rposition(|row| row.iter().any(|block| *block != 0))
+            .unwrap_or(0);
+        let spawn_position = Point2::new(SPAWN_X, 2.0 - bottom_row as f32);
 
         Piece {
+            shapes,
             shape,
             shape_type,
-            top_left: Point2::new(3.0, 0.0),
-            potential_top_left: Point2::new(3.0, 0.0),
-            shadow_position: Point2::new(3.0, 0.0),
-            current_rotation_index: 0,
-        }
-    }
-
-    // I don't remember why I did this.
-    /// Create a new piece from a reference of a `PieceType`.
-    pub fn new_from_ref(shape_type: PieceType) -> Self {
-        let shape = piece_type_to_shape(shape_type, 0);
-
-        Piece {
-            shape,
-            shape_type,
-            top_left: Point2::new(3.0, 0.0),
-            potential_top_left: Point2::new(3.0, 0.0),
-            shadow_position: Point2::new(3.0, 0.0),
-            current_rotation_index: 0,
+            top_left: spawn_position,
+            potential_top_left: spawn_position,
+            shadow_position: spawn_position,
+            current_rotation_index: rotation_index,
         }
     }
 
     /// Draw the current piece.
     pub fn draw(&self, ctx: &mut Context, image: &graphics::Image) -> GameResult<()> {
         // get starting position to draw window
-        // TODO: doing all of this work every frame seems bad
-        let width = graphics::get_screen_coordinates(ctx).w;
-        let middle = width / 2.0;
-        let starting_pos = middle - ((BLOCK_SIZE as f32 * 10.0) / 2.0);
+        let starting_pos = CENTRE_X - ((BLOCK_SIZE as f32 * 10.0) / 2.0);
 
         for (r, _) in self.shape.iter().enumerate() {
             for (c, _) in self.shape[r].iter().enumerate() {
@@ -97,10 +95,7 @@ impl Piece {
         shadow_position: Point2,
     ) -> GameResult<()> {
         // get starting position to draw window
-        // TODO: doing all of this work every frame seems bad
-        let width = graphics::get_screen_coordinates(ctx).w;
-        let middle = width / 2.0;
-        let starting_pos = middle - ((BLOCK_SIZE as f32 * 10.0) / 2.0);
+        let starting_pos = CENTRE_X - ((BLOCK_SIZE as f32 * 10.0) / 2.0);
 
         for (r, _) in self.shape.iter().enumerate() {
             for (c, _) in self.shape[r].iter().enumerate() {
@@ -185,7 +180,7 @@ impl Piece {
     pub fn get_next_shape(&self, direction: i32) -> PieceShape {
         let next_index = next_rotation_index(self.current_rotation_index, direction);
 
-        piece_type_to_shape(self.shape_type, next_index as usize)
+        self.shapes[next_index as usize]
     }
 
     /// Returns the current piece's shape.
@@ -198,11 +193,17 @@ impl Piece {
         self.shape_type
     }
 
+    /// Returns the current piece's rotation state. 0 is the spawn orientation
+    /// and each clockwise rotation adds 1.
+    pub fn get_rotation_index(&self) -> u32 {
+        self.current_rotation_index
+    }
+
     /// Given a direction, change the current piece's shape.
     pub fn change_shape(&mut self, direction: i32) {
         let next_index = next_rotation_index(self.current_rotation_index, direction);
 
-        self.shape = piece_type_to_shape(self.shape_type, next_index as usize);
+        self.shape = self.shapes[next_index as usize];
         self.current_rotation_index = next_index;
     }
 
@@ -215,25 +216,21 @@ impl Piece {
     pub fn get_shadow_position(&self) -> Point2 {
         self.shadow_position
     }
+
+    /// Returns true if any block of the piece is inside of the two hidden rows
+    /// at the top of the well.
+    pub fn is_in_vanish_zone(&self) -> bool {
+        self.shape
+            .iter()
+            .enumerate()
+            .any(|(r, row)| r as f32 + self.top_left.y < 2.0 && row.iter().any(|block| *block != 0))
+    }
 }
 
 /*
  * Utility methods
 */
 
-/// Given a type of piece, return the shape at the given rotation index.
-pub fn piece_type_to_shape(shape: PieceType, index: usize) -> PieceShape {
-    match shape {
-        PieceType::O => O_SHAPES[index],
-        PieceType::J => J_SHAPES[index],
-        PieceType::I => I_SHAPES[index],
-        PieceType::S => S_SHAPES[index],
-        PieceType::Z => Z_SHAPES[index],
-        PieceType::L => L_SHAPES[index],
-        PieceType::T => T_SHAPES[index],
-    }
-}
-
 /// Given an integer that represents a type of piece, return the `PieceType`.
 pub fn u8_to_piece_type(num: u8) -> Option<PieceType> {
     match num {
@@ -248,6 +245,20 @@ pub fn u8_to_piece_type(num: u8) -> Option<PieceType> {
     }
 }
 
+/// Returns the `PieceType` named by `letter`, such as `T` for a T piece.
+pub fn char_to_piece_type(letter: char) -> Option<PieceType> {
+    match letter.to_ascii_uppercase() {
+        'I' => Some(PieceType::I),
+        'J' => Some(PieceType::J),
+        'L' => Some(PieceType::L),
+        'O' => Some(PieceType::O),
+        'S' => Some(PieceType::S),
+        'T' => Some(PieceType::T),
+        'Z' => Some(PieceType::Z),
+        _ => None,
+    }
+}
+
 /// Get the block colour depending on the shape. If `shadow` is true, the
 /// colour's alpha channel is reduced.
 pub fn block_to_colour(num: u32, shadow: bool) -> Color {
@@ -271,6 +282,7 @@ pub fn block_to_colour(num: u32, shadow: bool) -> Color {
             5 => Color::from((128, 255, 0, 255)),   // S
             6 => Color::from((255, 128, 128, 255)), // T
             7 => Color::from((255, 0, 0, 255)),     // Z
+            8 => Color::from((128, 128, 128, 255)), // garbage
             _ => unreachable!(),
         }
     }
@@ -280,14 +292,6 @@ pub fn block_to_colour(num: u32, shadow: bool) -> Color {
 /// shape's index and a direction.
 pub fn next_rotation_index(current_index: u32, direction: i32) -> u32 {
     if direction == -1 {
-        match current_index {
-            0 => 1,
-            1 => 2,
-            2 => 3,
-            3 => 0,
-            _ => unreachable!(),
-        }
-    } else {
         match current_index {
             0 => 3,
             1 => 0,
@@ -295,5 +299,13 @@ pub fn next_rotation_index(current_index: u32, direction: i32) -> u32 {
             3 => 2,
             _ => unreachable!(),
         }
+    } else {
+        match current_index {
+            0 => 1,
+            1 => 2,
+            2 => 3,
+            3 => 0,
+            _ => unreachable!(),
+        }
     }
 }
@@ -1,4 +1,5 @@
-use crate::states::play_state::tetromino::{Piece, PieceType};
+use crate::states::play_state::tetromino::Piece;
+use crate::states::play_state::BLOCK_SIZE;
 use crate::states::Assets;
 use ggez::graphics::{Color, DrawMode, Point2, Rect};
 use ggez::{graphics, Context, GameResult};
@@ -6,6 +7,7 @@ use ggez::{graphics, Context, GameResult};
 const WIDTH: f32 = 150.0;
 const UIBLOCK_HEIGHT: f32 = 130.0;
 const UITEXT_HEIGHT: f32 = 40.0;
+const TITLE_HEIGHT: f32 = 25.0;
 const UI_BG_COLOUR: Color = Color {
     r: 0.3,
     b: 0.3,
@@ -85,11 +87,60 @@ impl UITextView {
     }
 }
 
+/// A UI element that shows how full something is as a bar below its title.
+/// The bar turns red once it is nearly full.
+pub struct UIMeterView {
+    top_left: Point2,
+    title_text: graphics::Text,
+    fill: f64,
+}
+
+impl UIMeterView {
+    pub fn new(ctx: &mut Context, assets: &Assets, top_left: Point2, title: &str) -> Self {
+        let title_txt = graphics::Text::new(ctx, title, assets.get_font("ui").unwrap()).unwrap();
+        Self {
+            top_left,
+            title_text: title_txt,
+            fill: 0.0,
+        }
+    }
+
+    /// Sets how full the meter is, from 0 to 1.
+    pub fn update(&mut self, fill: f64) {
+        self.fill = fill.clamp(0.0, 1.0);
+    }
+
+    pub fn draw(&mut self, ctx: &mut Context) -> GameResult<()> {
+        let width = WIDTH + 100.0;
+        let bg_rect = Rect::new(self.top_left.x, self.top_left.y, width, UITEXT_HEIGHT);
+        let title_point = Point2::new(self.top_left.x + 8.0, self.top_left.y + 5.0);
+        graphics::set_color(ctx, UI_BG_COLOUR)?;
+        graphics::rectangle(ctx, DrawMode::Fill, bg_rect)?;
+        graphics::set_color(ctx, Color::new(1.0, 1.0, 1.0, 1.0))?;
+        graphics::draw(ctx, &self.title_text, title_point, 0.0)?;
+
+        let bar_rect = Rect::new(
+            self.top_left.x,
+            self.top_left.y + UITEXT_HEIGHT - 8.0,
+            width * self.fill as f32,
+            8.0,
+        );
+        let bar_colour = if self.fill >= 0.75 {
+            Color::new(1.0, 0.0, 0.0, 1.0)
+        } else {
+            Color::new(1.0, 1.0, 0.0, 1.0)
+        };
+        graphics::set_color(ctx, bar_colour)?;
+        graphics::rectangle(ctx, DrawMode::Fill, bar_rect)?;
+        Ok(())
+    }
+}
+
 /// A UI element that renders `Pieces`. Used for elements like 'Next' or 'Hold'.
 pub struct UIBlockView {
     top_left: Point2,
     title_text: graphics::Text,
-    shape: Option<PieceType>,
+    piece: Option<Piece>,
 }
 
 impl UIBlockView {
@@ -98,20 +149,48 @@ impl UIBlockView {
         assets: &Assets,
         top_left: Point2,
         title: &str,
-        shape: Option<PieceType>,
+        piece: Option<Piece>,
     ) -> Self {
         let title_txt = graphics::Text::new(ctx, title, assets.get_font("ui").unwrap()).unwrap();
         Self {
             top_left,
             title_text: title_txt,
-            shape,
+            piece,
         }
     }
 
-    pub fn update(&mut self, _: &mut Context, _: &Assets, new_value: Option<PieceType>) {
-        if self.shape != new_value {
-            self.shape = new_value;
+    pub fn update(&mut self, _: &mut Context, _: &Assets, new_value: Option<Piece>) {
+        self.piece = new_value;
+    }
+
+    /// Returns the point to hand to `Piece::draw_at_point` so that the filled
+    /// blocks of `piece` are centred below the title.
+    fn centred_piece_position(&self, piece: &Piece) -> Point2 {
+        let shape = piece.get_shape();
+        let (mut min_r, mut max_r, mut min_c, mut max_c) = (3, 0, 3, 0);
+        for (r, row) in shape.iter().enumerate() {
+            for (c, block) in row.iter().enumerate() {
+                if *block != 0 {
+                    min_r = min_r.min(r);
+                    max_r = max_r.max(r);
+                    min_c = min_c.min(c);
+                    max_c = max_c.max(c);
+                }
+            }
         }
+
+        let piece_width = (max_c - min_c + 1) as f32 * BLOCK_SIZE;
+        let piece_height = (max_r - min_r + 1) as f32 * BLOCK_SIZE;
+        let centre = Point2::new(
+            self.top_left.x + WIDTH / 2.0,
+            self.top_left.y + TITLE_HEIGHT + (UIBLOCK_HEIGHT - TITLE_HEIGHT) / 2.0,
+        );
+
+        // `draw_at_point` draws each block one block to the right of its column
+        Point2::new(
+            centre.x - piece_width / 2.0 - (min_c + 1) as f32 * BLOCK_SIZE,
+            centre.y - piece_height / 2.0 - min_r as f32 * BLOCK_SIZE,
+        )
     }
 
     pub fn draw(&mut self, ctx: &mut Context, assets: &Assets) -> GameResult<()> {
@@ -123,20 +202,8 @@ impl UIBlockView {
         graphics::set_color(ctx, Color::new(1.0, 1.0, 1.0, 1.0))?;
         graphics::draw(ctx, &self.title_text, title_point, 0.0)?;
 
-        if let Some(shape) = self.shape {
-            let piece = Piece::new(shape);
-            // the magic numbers below were derived by fiddling with values
-            // until it looked right. ideally, some kind of object graph would
-            // be used to make relative positioning easier
-            let next_piece_pos = if piece.get_type() == PieceType::I {
-                Point2::new(self.top_left.x - 10.0, self.top_left.y + 30.0)
-            } else if piece.get_type() == PieceType::L {
-                Point2::new(self.top_left.x - 10.0, self.top_left.y + 40.0)
-            } else if piece.get_type() == PieceType::J {
-                Point2::new(self.top_left.x, self.top_left.y + 35.0)
-            } else {
-                Point2::new(self.top_left.x - 10.0, self.top_left.y + 25.0)
-            };
+        if let Some(piece) = self.piece {
+            let next_piece_pos = self.centred_piece_position(&piece);
             piece.draw_at_point(ctx, assets.get_image("block")?, next_piece_pos, 0.0)?;
         }
         Ok(())
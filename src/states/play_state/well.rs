@@ -5,21 +5,45 @@ use ggez::{graphics, Context, GameResult};
 
 /// The y-offset to use as a starting point when drawing
 pub const Y_OFFSET: f32 = 10f32;
+/// The x coordinate the well is centred on. The game is laid out on a logical
+/// screen 1280 pixels wide, so this is its middle.
+pub const CENTRE_X: f32 = 640.0;
+/// The number of columns in the well.
+pub const WIDTH: usize = 10;
+/// The number of rows in the well, including the two hidden rows at the top.
+pub const HEIGHT: usize = 22;
+/// The value of a cell filled with garbage rather than part of a piece.
+pub const GARBAGE_BLOCK: u32 = 8;
 
 /// The playfield. The top two rows are where pieces spawn but they are not
 /// rendered.
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct Well {
-    data: [[u32; 10]; 22],
+    data: [[u32; WIDTH]; HEIGHT],
 }
 
 impl Well {
     pub fn new() -> Self {
         Well {
-            data: [[0; 10]; 22],
+            data: [[0; WIDTH]; HEIGHT],
         }
     }
 
+    /// Creates a well from a picture of its bottom rows, where `#` is a filled
+    /// cell and anything else is empty. The last row given is the bottom row.
+    pub fn from_rows(rows: &[&str]) -> Self {
+        let mut well = Well::new();
+        let offset = well.data.len() - rows.len();
+        for (r, row) in rows.iter().enumerate() {
+            for (c, cell) in row.chars().enumerate() {
+                if cell == '#' {
+                    well.data[offset + r][c] = GARBAGE_BLOCK;
+                }
+            }
+        }
+        well
+    }
+
     /// Add's the current piece, `current_t`, to the well.
     pub fn land(&mut self, current_t: &Piece) {
         let current_shape = current_t.get_shape();
@@ -27,34 +51,35 @@ impl Well {
         for (r, _) in current_shape.iter().enumerate() {
             for (c, _) in current_shape[r].iter().enumerate() {
                 if current_shape[r][c] != 0 {
-                    // add shape to well
-                    self.data[r.wrapping_add(current_t.top_left.y as usize)]
-                        [c.wrapping_add(current_t.top_left.x as usize)] = current_shape[r][c];
+                    // add shape to well. The piece's box may stick out past the
+                    // left wall, so work out the cell before casting to usize
+                    let y = r as i32 + current_t.top_left.y as i32;
+                    let x = c as i32 + current_t.top_left.x as i32;
+                    self.data[y as usize][x as usize] = current_shape[r][c];
                 }
             }
         }
     }
 
+    /// Returns the area of the screen the visible part of the well is drawn
+    /// in, as passed to `graphics::rectangle`.
+    pub fn backside() -> Rect {
+        Rect {
+            x: CENTRE_X - (BLOCK_SIZE / 2.0),
+            y: Y_OFFSET + ((BLOCK_SIZE / 2.0) * 20.0) + (BLOCK_SIZE / 2.0) * 3.0,
+            w: BLOCK_SIZE * 10.0,
+            h: BLOCK_SIZE * 20.0,
+        }
+    }
+
     /// Renders the well.
     pub fn draw(&self, ctx: &mut Context, image: &graphics::Image) -> GameResult<()> {
         // get starting position to draw window
-        // TODO: doing all of this work every frame seems bad
-        let width = graphics::get_screen_coordinates(ctx).w;
-        let middle = width / 2.0;
-        let starting_pos = middle - ((BLOCK_SIZE as f32 * self.data[0].len() as f32) / 2.0);
+        let starting_pos = CENTRE_X - ((BLOCK_SIZE as f32 * self.data[0].len() as f32) / 2.0);
 
         // draw the "backside" of the well
         graphics::set_color(ctx, Color::from((15, 15, 15, 20)))?;
-        graphics::rectangle(
-            ctx,
-            DrawMode::Fill,
-            Rect {
-                x: middle - (BLOCK_SIZE / 2.0),
-                y: Y_OFFSET + ((BLOCK_SIZE / 2.0) * 20.0) + (BLOCK_SIZE / 2.0) * 3.0,
-                w: BLOCK_SIZE * 10.0,
-                h: BLOCK_SIZE * 20.0,
-            },
-        )?;
+        graphics::rectangle(ctx, DrawMode::Fill, Well::backside())?;
 
         for (r, _) in self.data.iter().enumerate() {
             if r < 2 {
@@ -93,7 +118,9 @@ impl Well {
     /// of rows cleared.
     pub fn clear_lines(&mut self) -> u32 {
         let mut lines_cleared: u32 = 0;
-        for r in (0..self.data.len()).rev() {
+        let mut r = self.data.len();
+        while r > 0 {
+            r -= 1;
             let mut is_row_filled = true;
             for (c, _) in self.data[r].iter().enumerate() {
                 if self.data[r][c] == 0 {
@@ -107,6 +134,9 @@ impl Well {
                 // TODO: make the current line clearing algorithm user selectable
                 self.naive_line_clear(r);
                 lines_cleared += 1;
+                // the row above has been pulled down into this one, so check
+                // this row again
+                r += 1;
             }
         }
 
@@ -123,11 +153,58 @@ impl Well {
             } else {
                 // if current row is 0, there is nothing above to pull down
                 // clearing a line should always lead to top row being clear, so empty it
-                self.data[row] = [0; 10];
+                self.data[row] = [0; WIDTH];
             }
         }
     }
 
+    /// Pushes every row up by one and adds a row of garbage at the bottom,
+    /// filled in every column except `hole`. Returns true if any filled cell
+    /// was pushed out of the top of the well.
+    pub fn push_garbage_row(&mut self, hole: usize) -> bool {
+        let overflowed = self.data[0].iter().any(|cell| *cell != 0);
+        for row in 0..HEIGHT - 1 {
+            self.data[row] = self.data[row + 1];
+        }
+
+        self.data[HEIGHT - 1] = [GARBAGE_BLOCK; WIDTH];
+        self.data[HEIGHT - 1][hole] = 0;
+        overflowed
+    }
+
+    /// Empties the top `count` rows of the well, including the hidden rows.
+    /// Cells below them are left where they are.
+    pub fn clear_top_rows(&mut self, count: usize) {
+        for row in self.data.iter_mut().take(count) {
+            *row = [0; WIDTH];
+        }
+    }
+
+    /// Returns true if every cell of the well is empty.
+    pub fn is_empty(&self) -> bool {
+        self.data
+            .iter()
+            .all(|row| row.iter().all(|cell| *cell == 0))
+    }
+
+    /// Returns the number of rows that still hold any garbage.
+    pub fn garbage_rows(&self) -> u32 {
+        self.data
+            .iter()
+            .filter(|row| row.contains(&GARBAGE_BLOCK))
+            .count() as u32
+    }
+
+    /// Returns true if the cell at column `x` and row `y` is filled. The walls
+    /// and floor of the well count as filled while the space above it does not.
+    pub fn is_occupied(&self, x: i32, y: i32) -> bool {
+        if x < 0 || x >= self.data[0].len() as i32 || y >= self.data.len() as i32 {
+            return true;
+        }
+
+        y >= 0 && self.data[y as usize][x as usize] != 0
+    }
+
     /// Check if a collision would occur in the well given the shape and shape's
     /// position.
     pub fn check_for_collisions(&self, shape: &PieceShape, position: Point2) -> bool {
@@ -138,6 +215,7 @@ impl Well {
                 if shape[r][c] != 0 && // if the space isn't empty AND one of the following:
                     (c as f32 + position.x < 0.0 || // outside left side
                          c as f32 + position.x >= self.data[r].len() as f32 || // outside right side
+                         r as f32 + position.y < 0.0 || // outside top
                          r as f32 + position.y >= self.data.len() as f32 || // outside bottom
                          self.data[(r as f32 + position.y) as usize][(c as f32 + position.x) as
                                                                          usize] !=
@@ -160,10 +238,8 @@ impl Well {
             for (c, _) in shape[r].iter().enumerate() {
                 if shape[r][c] != 0 {
                     if r as f32 + position.y >= self.data.len() as f32 || // outside bottom
-                        self.data[(r.wrapping_add(position.y as usize))][(c.wrapping_add(
-                            position.x as
-                                usize,
-                        ))] != 0
+                        self.data[(r as i32 + position.y as i32) as usize]
+                            [(c as i32 + position.x as i32) as usize] != 0
                     // space is not empty
                     {
                         collision_found = true;
@@ -179,3 +255,39 @@ impl Well {
         collision_found
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::Well;
+    use crate::states::play_state::rotation::RotationSystemKind;
+    use crate::states::play_state::tetromino::{Piece, PieceType};
+    use ggez::graphics::Point2;
+
+    #[test]
+    fn adjacent_filled_rows_are_all_cleared() {
+        let mut well = Well::from_rows(&["#.........", "##########", "##########", "##########"]);
+
+        assert_eq!(3, well.clear_lines());
+        assert!(well.is_occupied(0, 21));
+        assert_eq!(Well::from_rows(&["#........."]).data, well.data);
+    }
+
+    #[test]
+    fn pieces_sticking_out_past_the_left_wall_land_in_the_first_column() {
+        let rotation_system = RotationSystemKind::default().build();
+        let mut piece = Piece::new(PieceType::I, rotation_system.as_ref());
+        // the counterclockwise orientation of an I fills the second column of
+        // its box
+        piece.change_shape(-1);
+        piece.top_left = Point2::new(-1.0, 18.0);
+
+        let mut well = Well::new();
+        let below = Point2::new(-1.0, 19.0);
+        assert!(well.check_for_landing(&piece.get_shape(), below));
+        well.land(&piece);
+        for y in 18..22 {
+            assert!(well.is_occupied(0, y));
+            assert!(!well.is_occupied(1, y));
+        }
+    }
+}
@@ -0,0 +1,250 @@
+use super::PuzzleState;
+use crate::states::menu_state::MenuState;
+use crate::states::play_state::puzzle::{load_puzzles, Puzzle};
+use crate::states::play_state::ruleset::Ruleset;
+use crate::states::shared::option::{Option, OptionInputCommand};
+use crate::states::{Assets, State, Transition};
+use crate::util::play_click_sfx;
+use ggez::event::{Button, Keycode, Mod};
+use ggez::graphics::Point2;
+use ggez::{graphics, Context, GameResult};
+use std::time::Duration;
+
+const MAX_LISTED_PUZZLES: usize = 7;
+const FIRST_OPTION_Y: f32 = 200.0;
+const OPTION_SPACING: f32 = 55.0;
+
+/// Lists the puzzles in the puzzle directory and starts a `PuzzleState` for
+/// the one picked. Only a window of the list is shown at a time, scrolling to
+/// follow the selection. The last option returns to the menu.
+pub struct PuzzleListState {
+    title_text: graphics::Text,
+    empty_text: std::option::Option<graphics::Text>,
+    puzzles: Vec<Puzzle>,
+    ruleset: Ruleset,
+    options: Vec<Option>,
+    // index of the first puzzle shown in the list
+    first_listed: usize,
+    // index into `puzzles`, or `puzzles.len()` for the back option
+    current_selection: usize,
+
+    request_puzzle: bool,
+    request_menu: bool,
+}
+
+impl PuzzleListState {
+    /// Creates the list with the puzzle at `selected` picked, so returning
+    /// from a puzzle leaves the player where they were.
+    pub fn new(
+        ctx: &mut Context,
+        assets: &Assets,
+        ruleset: Ruleset,
+        selected: usize,
+    ) -> GameResult<Self> {
+        let title_text = graphics::Text::new(ctx, "Puzzles", assets.get_font("title")?)?;
+        let puzzles = load_puzzles(ctx)?;
+
+        let empty_text = if puzzles.is_empty() {
+            Some(graphics::Text::new(
+                ctx,
+                "No puzzles found",
+                assets.get_font("normal")?,
+            )?)
+        } else {
+            None
+        };
+
+        let mut list = PuzzleListState {
+            title_text,
+            empty_text,
+            current_selection: selected.min(puzzles.len()),
+            puzzles,
+            ruleset,
+            options: Vec::new(),
+            first_listed: 0,
+
+            request_puzzle: false,
+            request_menu: false,
+        };
+        list.scroll_to_selection();
+        list.build_options(ctx, assets);
+
+        Ok(list)
+    }
+
+    /// Moves the window of listed puzzles so that it shows the current
+    /// selection. Returns true if the window moved.
+    fn scroll_to_selection(&mut self) -> bool {
+        let first_listed = self.first_listed;
+        if self.current_selection < self.puzzles.len() {
+            if self.current_selection < self.first_listed {
+                self.first_listed = self.current_selection;
+            } else if self.current_selection >= self.first_listed + MAX_LISTED_PUZZLES {
+                self.first_listed = self.current_selection + 1 - MAX_LISTED_PUZZLES;
+            }
+        }
+        first_listed != self.first_listed
+    }
+
+    /// Rebuilds the options for the puzzles in the listed window.
+    fn build_options(&mut self, ctx: &mut Context, assets: &Assets) {
+        let coords = graphics::get_screen_coordinates(ctx);
+        let listed = self
+            .puzzles
+            .iter()
+            .skip(self.first_listed)
+            .take(MAX_LISTED_PUZZLES);
+
+        self.options.clear();
+        for (i, puzzle) in listed.enumerate() {
+            let name = format!("{}. {}", self.first_listed + i + 1, puzzle.name);
+            self.options.push(Option::new(
+                ctx,
+                assets,
+                &name,
+                Point2::new(coords.w / 2.0, FIRST_OPTION_Y + OPTION_SPACING * i as f32),
+            ));
+        }
+
+        let back_row = self.options.len() + 1;
+        self.options.push(Option::new(
+            ctx,
+            assets,
+            "Back",
+            Point2::new(
+                coords.w / 2.0,
+                FIRST_OPTION_Y + OPTION_SPACING * back_row as f32,
+            ),
+        ));
+    }
+
+    /// Returns the index into `options` of the current selection.
+    fn selected_option(&self) -> usize {
+        if self.current_selection < self.puzzles.len() {
+            self.current_selection - self.first_listed
+        } else {
+            self.options.len() - 1
+        }
+    }
+
+    fn handle_input(&mut self, command: &OptionInputCommand, assets: &Assets) {
+        let last = self.puzzles.len();
+        match command {
+            OptionInputCommand::Up => {
+                play_click_sfx(assets).expect("Could not play click sfx in puzzle list -> up");
+                if self.current_selection == 0 {
+                    self.current_selection = last;
+                } else {
+                    self.current_selection -= 1;
+                }
+            }
+            OptionInputCommand::Down => {
+                play_click_sfx(assets).expect("Could not play click sfx in puzzle list -> down");
+                if self.current_selection >= last {
+                    self.current_selection = 0;
+                } else {
+                    self.current_selection += 1;
+                }
+            }
+            OptionInputCommand::Select => {
+                if self.current_selection < last {
+                    self.request_puzzle = true;
+                } else {
+                    self.request_menu = true;
+                }
+            }
+        }
+    }
+}
+
+impl State for PuzzleListState {
+    fn update(
+        &mut self,
+        ctx: &mut Context,
+        assets: &Assets,
+        _: Duration,
+    ) -> GameResult<Transition> {
+        if self.request_menu {
+            return Ok(Transition::Swap(Box::new(MenuState::new(ctx, assets)?)));
+        } else if self.request_puzzle {
+            assets.get_music("menu")?.pause();
+            return Ok(Transition::Swap(Box::new(PuzzleState::new(
+                ctx,
+                assets,
+                self.puzzles.clone(),
+                self.current_selection,
+                self.ruleset,
+            )?)));
+        }
+
+        if self.scroll_to_selection() {
+            self.build_options(ctx, assets);
+        }
+
+        let selected = self.selected_option();
+        for (i, option) in self.options.iter_mut().enumerate() {
+            option.update(i == selected)?;
+        }
+
+        Ok(Transition::None)
+    }
+
+    fn draw(&mut self, ctx: &mut Context, _: &Assets) -> GameResult<()> {
+        let coords = graphics::get_screen_coordinates(ctx);
+
+        let title_dest = Point2::new(coords.w / 2.0 - (self.title_text.width() / 2) as f32, 100.0);
+        graphics::set_color(ctx, graphics::Color::new(1.0, 1.0, 1.0, 1.0))?;
+        graphics::draw(ctx, &self.title_text, title_dest, 0.0)?;
+
+        if let Some(empty_text) = &self.empty_text {
+            let empty_dest = Point2::new(
+                coords.w / 2.0 - (empty_text.width() / 2) as f32,
+                FIRST_OPTION_Y,
+            );
+            graphics::draw(ctx, empty_text, empty_dest, 0.0)?;
+        }
+
+        for option in &self.options {
+            option.draw(ctx)?;
+        }
+
+        Ok(())
+    }
+
+    fn key_down_event(
+        &mut self,
+        _ctx: &mut Context,
+        keycode: Keycode,
+        _keymod: Mod,
+        repeat: bool,
+        assets: &Assets,
+    ) {
+        if repeat {
+            return;
+        }
+
+        match keycode {
+            Keycode::Up => self.handle_input(&OptionInputCommand::Up, assets),
+            Keycode::Down => self.handle_input(&OptionInputCommand::Down, assets),
+            Keycode::Return => self.handle_input(&OptionInputCommand::Select, assets),
+            Keycode::Escape => self.request_menu = true,
+            _ => (),
+        }
+    }
+
+    fn controller_button_down_event(
+        &mut self,
+        _ctx: &mut Context,
+        btn: Button,
+        _instance_id: i32,
+        assets: &Assets,
+    ) {
+        match btn {
+            Button::DPadUp => self.handle_input(&OptionInputCommand::Up, assets),
+            Button::DPadDown => self.handle_input(&OptionInputCommand::Down, assets),
+            Button::A => self.handle_input(&OptionInputCommand::Select, assets),
+            Button::B => self.request_menu = true,
+            _ => (),
+        }
+    }
+}
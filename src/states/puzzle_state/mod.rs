@@ -0,0 +1,304 @@
+mod list;
+
+pub use self::list::PuzzleListState;
+
+use crate::states::play_state::game_core::GameCore;
+use crate::states::play_state::game_view::{play_event_sounds, GameView};
+use crate::states::play_state::handling::Handling;
+use crate::states::play_state::input_state::InputState;
+use crate::states::play_state::puzzle::Puzzle;
+use crate::states::play_state::replay::FRAME_DURATION;
+use crate::states::play_state::ruleset::Ruleset;
+use crate::states::play_state::{button_to_action, keycode_to_action, random_play_track};
+use crate::states::shared::option::{Option, OptionInputCommand};
+use crate::states::{Assets, State, Transition};
+use crate::util::{play_click_sfx, DurationExt};
+use ggez::event::{Button, Keycode, Mod};
+use ggez::graphics::Point2;
+use ggez::{graphics, Context, GameResult};
+use std::time::Duration;
+
+const FIRST_OPTION_Y: f32 = 350.0;
+const OPTION_SPACING: f32 = 65.0;
+
+/// What the player can do once a puzzle is solved or failed.
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum PuzzleOption {
+    Retry,
+    Next,
+    List,
+}
+
+/// Plays a single `Puzzle`. Once the puzzle is solved or failed, the player
+/// can retry it, move on to the next puzzle, or go back to the puzzle list.
+pub struct PuzzleState {
+    puzzles: Vec<Puzzle>,
+    index: usize,
+    ruleset: Ruleset,
+
+    input: InputState,
+    core: GameCore,
+    // time that has passed but has not been played as a frame yet
+    frame_timer: f64,
+    current_track_name: String,
+
+    view: GameView,
+    name_text: graphics::Text,
+    goal_text: graphics::Text,
+    result_text: std::option::Option<graphics::Text>,
+    // only filled in once the puzzle is over
+    option_kinds: Vec<PuzzleOption>,
+    options: Vec<Option>,
+    current_selection: usize,
+
+    request_option: bool,
+    request_list: bool,
+}
+
+impl PuzzleState {
+    /// Creates a game of the puzzle at `index` in `puzzles`, played with
+    /// `ruleset`. The rest of the puzzles are offered once it is solved.
+    pub fn new(
+        ctx: &mut Context,
+        assets: &Assets,
+        puzzles: Vec<Puzzle>,
+        index: usize,
+        ruleset: Ruleset,
+    ) -> GameResult<Self> {
+        let puzzle = &puzzles[index];
+        let core = GameCore::from_puzzle(puzzle, ruleset, Handling::load(ctx));
+        let view = GameView::new(ctx, assets, &core);
+        let name_text = graphics::Text::new(ctx, &puzzle.name, assets.get_font("normal")?)?;
+        let goal_text =
+            graphics::Text::new(ctx, &puzzle.goal.description(), assets.get_font("ui")?)?;
+
+        Ok(PuzzleState {
+            puzzles,
+            index,
+            ruleset,
+
+            input: InputState::default(),
+            core,
+            frame_timer: 0.0,
+            current_track_name: random_play_track(assets),
+
+            view,
+            name_text,
+            goal_text,
+            result_text: None,
+            option_kinds: Vec::new(),
+            options: Vec::new(),
+            current_selection: 0,
+
+            request_option: false,
+            request_list: false,
+        })
+    }
+
+    /// Shows whether the puzzle was solved, and the options for what to do
+    /// next.
+    fn show_result(&mut self, ctx: &mut Context, assets: &Assets) -> GameResult<()> {
+        let solved = self.core.has_won();
+        let result = if solved { "SOLVED!" } else { "FAILED" };
+        self.result_text = Some(graphics::Text::new(ctx, result, assets.get_font("title")?)?);
+        if solved {
+            assets.get_sfx("gameover_win")?.play()?;
+        } else {
+            assets.get_sfx("gameover_lose")?.play()?;
+        }
+
+        self.option_kinds = vec![PuzzleOption::Retry];
+        if solved && self.index + 1 < self.puzzles.len() {
+            self.option_kinds.insert(0, PuzzleOption::Next);
+        }
+        self.option_kinds.push(PuzzleOption::List);
+
+        let coords = graphics::get_screen_coordinates(ctx);
+        for (i, kind) in self.option_kinds.iter().enumerate() {
+            let name = match kind {
+                PuzzleOption::Retry => "Retry",
+                PuzzleOption::Next => "Next Puzzle",
+                PuzzleOption::List => "Puzzle List",
+            };
+            self.options.push(Option::new(
+                ctx,
+                assets,
+                name,
+                Point2::new(coords.w / 2.0, FIRST_OPTION_Y + OPTION_SPACING * i as f32),
+            ));
+        }
+
+        Ok(())
+    }
+
+    fn handle_input(&mut self, command: &OptionInputCommand, assets: &Assets) {
+        if self.options.is_empty() {
+            return;
+        }
+
+        match command {
+            OptionInputCommand::Up => {
+                play_click_sfx(assets).expect("Could not play click sfx in puzzle state -> up");
+                if self.current_selection == 0 {
+                    self.current_selection = self.options.len() - 1;
+                } else {
+                    self.current_selection -= 1;
+                }
+            }
+            OptionInputCommand::Down => {
+                play_click_sfx(assets).expect("Could not play click sfx in puzzle state -> down");
+                if self.current_selection >= self.options.len() - 1 {
+                    self.current_selection = 0;
+                } else {
+                    self.current_selection += 1;
+                }
+            }
+            OptionInputCommand::Select => self.request_option = true,
+        }
+    }
+}
+
+impl State for PuzzleState {
+    fn update(
+        &mut self,
+        ctx: &mut Context,
+        assets: &Assets,
+        dt: Duration,
+    ) -> GameResult<Transition> {
+        // currently necessary to keep audio looping
+        let current_song = assets.get_music(&self.current_track_name)?;
+        if current_song.paused() {
+            current_song.resume();
+        } else {
+            current_song.play()?;
+        }
+
+        if self.request_list {
+            assets.get_music(&self.current_track_name)?.pause();
+            return Ok(Transition::Swap(Box::new(PuzzleListState::new(
+                ctx,
+                assets,
+                self.ruleset,
+                self.index,
+            )?)));
+        } else if self.request_option {
+            self.request_option = false;
+            let index = match self.option_kinds[self.current_selection] {
+                PuzzleOption::Retry => self.index,
+                PuzzleOption::Next => self.index + 1,
+                PuzzleOption::List => {
+                    self.request_list = true;
+                    return Ok(Transition::None);
+                }
+            };
+            assets.get_music(&self.current_track_name)?.pause();
+            return Ok(Transition::Swap(Box::new(PuzzleState::new(
+                ctx,
+                assets,
+                self.puzzles.clone(),
+                index,
+                self.ruleset,
+            )?)));
+        }
+
+        // play every whole frame that has passed since the last update
+        self.frame_timer += dt.as_subsec_millis();
+        while self.frame_timer >= FRAME_DURATION.as_subsec_millis() && !self.core.is_finished() {
+            self.frame_timer -= FRAME_DURATION.as_subsec_millis();
+            let events = self.core.step(&mut self.input, FRAME_DURATION);
+            play_event_sounds(&events, assets)?;
+        }
+
+        self.view.update(ctx, assets, &self.core);
+
+        if self.core.is_finished() && self.result_text.is_none() {
+            self.show_result(ctx, assets)?;
+        }
+        for (i, option) in self.options.iter_mut().enumerate() {
+            option.update(i == self.current_selection)?;
+        }
+
+        Ok(Transition::None)
+    }
+
+    fn draw(&mut self, ctx: &mut Context, assets: &Assets) -> GameResult<()> {
+        self.view.draw(ctx, assets, &self.core)?;
+
+        graphics::set_color(ctx, graphics::Color::new(1.0, 1.0, 1.0, 1.0))?;
+        graphics::draw(ctx, &self.name_text, Point2::new(220.0, 75.0), 0.0)?;
+        graphics::set_color(ctx, graphics::Color::new(1.0, 1.0, 0.0, 1.0))?;
+        graphics::draw(ctx, &self.goal_text, Point2::new(220.0, 130.0), 0.0)?;
+
+        if let Some(result_text) = &self.result_text {
+            let coords = graphics::get_screen_coordinates(ctx);
+            let result_dest = Point2::new(coords.w / 2.0 - (result_text.width() / 2) as f32, 200.0);
+
+            graphics::set_color(ctx, graphics::Color::new(0.0, 0.0, 0.0, 0.75))?;
+            graphics::rectangle(ctx, graphics::DrawMode::Fill, coords)?;
+            graphics::set_color(ctx, graphics::Color::new(1.0, 1.0, 1.0, 1.0))?;
+            graphics::draw(ctx, result_text, result_dest, 0.0)?;
+
+            for option in &self.options {
+                option.draw(ctx)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn key_down_event(
+        &mut self,
+        _ctx: &mut Context,
+        keycode: Keycode,
+        _keymod: Mod,
+        _repeat: bool,
+        assets: &Assets,
+    ) {
+        if keycode == Keycode::Escape {
+            self.request_list = true;
+        } else if self.core.is_finished() {
+            match keycode {
+                Keycode::Up => self.handle_input(&OptionInputCommand::Up, assets),
+                Keycode::Down => self.handle_input(&OptionInputCommand::Down, assets),
+                Keycode::Return => self.handle_input(&OptionInputCommand::Select, assets),
+                _ => (),
+            }
+        } else if let Some(action) = keycode_to_action(keycode) {
+            self.input.set_action(action, true);
+        }
+    }
+
+    fn key_up_event(&mut self, _ctx: &mut Context, keycode: Keycode, _keymod: Mod, _repeat: bool) {
+        if let Some(action) = keycode_to_action(keycode) {
+            self.input.set_action(action, false);
+        }
+    }
+
+    fn controller_button_down_event(
+        &mut self,
+        _ctx: &mut Context,
+        btn: Button,
+        _instance_id: i32,
+        assets: &Assets,
+    ) {
+        if btn == Button::Start {
+            self.request_list = true;
+        } else if self.core.is_finished() {
+            match btn {
+                Button::DPadUp => self.handle_input(&OptionInputCommand::Up, assets),
+                Button::DPadDown => self.handle_input(&OptionInputCommand::Down, assets),
+                Button::A => self.handle_input(&OptionInputCommand::Select, assets),
+                Button::B => self.request_list = true,
+                _ => (),
+            }
+        } else if let Some(action) = button_to_action(btn) {
+            self.input.set_action(action, true);
+        }
+    }
+
+    fn controller_button_up_event(&mut self, _ctx: &mut Context, btn: Button, _instance_id: i32) {
+        if let Some(action) = button_to_action(btn) {
+            self.input.set_action(action, false);
+        }
+    }
+}
@@ -0,0 +1,205 @@
+use super::ReplayState;
+use crate::states::menu_state::MenuState;
+use crate::states::play_state::replay::{saved_replays, Replay};
+use crate::states::shared::option::{Option, OptionInputCommand};
+use crate::states::{Assets, State, Transition};
+use crate::util::play_click_sfx;
+use ggez::event::{Button, Keycode, Mod};
+use ggez::graphics::Point2;
+use ggez::{graphics, Context, GameResult};
+use std::path::PathBuf;
+use std::time::Duration;
+
+const MAX_LISTED_REPLAYS: usize = 6;
+const FIRST_OPTION_Y: f32 = 200.0;
+const OPTION_SPACING: f32 = 60.0;
+
+/// Lists the saved replays, newest first, and starts a `ReplayState` for the
+/// one picked. The last option returns to the menu.
+pub struct ReplayListState {
+    title_text: graphics::Text,
+    empty_text: std::option::Option<graphics::Text>,
+    paths: Vec<PathBuf>,
+    options: Vec<Option>,
+    current_selection: usize,
+
+    request_replay: bool,
+    request_menu: bool,
+}
+
+impl ReplayListState {
+    pub fn new(ctx: &mut Context, assets: &Assets) -> GameResult<Self> {
+        let title_text = graphics::Text::new(ctx, "Replays", assets.get_font("title")?)?;
+
+        let mut paths = saved_replays(ctx)?;
+        paths.truncate(MAX_LISTED_REPLAYS);
+
+        let coords = graphics::get_screen_coordinates(ctx);
+        let mut options: Vec<Option> = Vec::new();
+        for (i, path) in paths.iter().enumerate() {
+            let name = path
+                .file_stem()
+                .map(|stem| stem.to_string_lossy().into_owned())
+                .unwrap_or_default();
+            options.push(Option::new(
+                ctx,
+                assets,
+                &name,
+                Point2::new(coords.w / 2.0, FIRST_OPTION_Y + OPTION_SPACING * i as f32),
+            ));
+        }
+
+        let empty_text = if paths.is_empty() {
+            Some(graphics::Text::new(
+                ctx,
+                "No saved replays",
+                assets.get_font("normal")?,
+            )?)
+        } else {
+            None
+        };
+        options.push(Option::new(
+            ctx,
+            assets,
+            "Back",
+            Point2::new(
+                coords.w / 2.0,
+                FIRST_OPTION_Y + OPTION_SPACING * (paths.len() + 1) as f32,
+            ),
+        ));
+
+        Ok(ReplayListState {
+            title_text,
+            empty_text,
+            paths,
+            options,
+            current_selection: 0,
+
+            request_replay: false,
+            request_menu: false,
+        })
+    }
+
+    fn handle_input(&mut self, command: &OptionInputCommand, assets: &Assets) {
+        match command {
+            OptionInputCommand::Up => {
+                play_click_sfx(assets).expect("Could not play click sfx in replay list -> up");
+                if self.current_selection == 0 {
+                    self.current_selection = self.options.len() - 1;
+                } else {
+                    self.current_selection -= 1;
+                }
+            }
+            OptionInputCommand::Down => {
+                play_click_sfx(assets).expect("Could not play click sfx in replay list -> down");
+                if self.current_selection >= self.options.len() - 1 {
+                    self.current_selection = 0;
+                } else {
+                    self.current_selection += 1;
+                }
+            }
+            OptionInputCommand::Select => {
+                if self.current_selection < self.paths.len() {
+                    self.request_replay = true;
+                } else {
+                    self.request_menu = true;
+                }
+            }
+        }
+    }
+}
+
+impl State for ReplayListState {
+    fn update(
+        &mut self,
+        ctx: &mut Context,
+        assets: &Assets,
+        _: Duration,
+    ) -> GameResult<Transition> {
+        if self.request_menu {
+            return Ok(Transition::Swap(Box::new(MenuState::new(ctx, assets)?)));
+        } else if self.request_replay {
+            self.request_replay = false;
+            match Replay::load(ctx, &self.paths[self.current_selection]) {
+                Ok(replay) => {
+                    assets.get_music("menu")?.pause();
+                    return Ok(Transition::Swap(Box::new(ReplayState::new(
+                        ctx, assets, replay,
+                    )?)));
+                }
+                Err(_) => {
+                    self.options[self.current_selection].set_text(
+                        ctx,
+                        assets,
+                        "Could not load replay",
+                    )?;
+                }
+            }
+        }
+
+        for (i, option) in self.options.iter_mut().enumerate() {
+            option.update(i == self.current_selection)?;
+        }
+
+        Ok(Transition::None)
+    }
+
+    fn draw(&mut self, ctx: &mut Context, _: &Assets) -> GameResult<()> {
+        let coords = graphics::get_screen_coordinates(ctx);
+
+        let title_dest = Point2::new(coords.w / 2.0 - (self.title_text.width() / 2) as f32, 100.0);
+        graphics::set_color(ctx, graphics::Color::new(1.0, 1.0, 1.0, 1.0))?;
+        graphics::draw(ctx, &self.title_text, title_dest, 0.0)?;
+
+        if let Some(empty_text) = &self.empty_text {
+            let empty_dest = Point2::new(
+                coords.w / 2.0 - (empty_text.width() / 2) as f32,
+                FIRST_OPTION_Y,
+            );
+            graphics::draw(ctx, empty_text, empty_dest, 0.0)?;
+        }
+
+        for option in &self.options {
+            option.draw(ctx)?;
+        }
+
+        Ok(())
+    }
+
+    fn key_down_event(
+        &mut self,
+        _ctx: &mut Context,
+        keycode: Keycode,
+        _keymod: Mod,
+        repeat: bool,
+        assets: &Assets,
+    ) {
+        if repeat {
+            return;
+        }
+
+        match keycode {
+            Keycode::Up => self.handle_input(&OptionInputCommand::Up, assets),
+            Keycode::Down => self.handle_input(&OptionInputCommand::Down, assets),
+            Keycode::Return => self.handle_input(&OptionInputCommand::Select, assets),
+            Keycode::Escape => self.request_menu = true,
+            _ => (),
+        }
+    }
+
+    fn controller_button_down_event(
+        &mut self,
+        _ctx: &mut Context,
+        btn: Button,
+        _instance_id: i32,
+        assets: &Assets,
+    ) {
+        match btn {
+            Button::DPadUp => self.handle_input(&OptionInputCommand::Up, assets),
+            Button::DPadDown => self.handle_input(&OptionInputCommand::Down, assets),
+            Button::A => self.handle_input(&OptionInputCommand::Select, assets),
+            Button::B => self.request_menu = true,
+            _ => (),
+        }
+    }
+}
@@ -0,0 +1,145 @@
+mod list;
+
+pub use self::list::ReplayListState;
+
+use crate::states::menu_state::MenuState;
+use crate::states::play_state::game_core::GameCore;
+use crate::states::play_state::game_view::{play_event_sounds, GameView};
+use crate::states::play_state::input_state::InputState;
+use crate::states::play_state::random_play_track;
+use crate::states::play_state::replay::{Replay, ReplayPlayer, FRAME_DURATION};
+use crate::states::{Assets, State, Transition};
+use crate::util::DurationExt;
+use ggez::event::{Button, Keycode, Mod};
+use ggez::graphics::Point2;
+use ggez::{graphics, Context, GameResult};
+use std::time::Duration;
+
+/// Plays back a recorded game. The recorded inputs are fed into a new
+/// `GameCore` one frame at a time, exactly as they were during the game, so the
+/// game plays out just as it was recorded.
+pub struct ReplayState {
+    input: InputState,
+    core: GameCore,
+    player: ReplayPlayer,
+    // time that has passed but has not been played as a frame yet
+    frame_timer: f64,
+
+    current_track_name: String,
+    request_menu: bool,
+
+    view: GameView,
+    end_text: graphics::Text,
+}
+
+impl ReplayState {
+    pub fn new(ctx: &mut Context, assets: &Assets, replay: Replay) -> GameResult<Self> {
+        let core = GameCore::new(
+            replay.mode,
+            replay.settings,
+            replay.ruleset,
+            replay.handling,
+            replay.seed,
+        );
+        let view = GameView::new(ctx, assets, &core);
+        let end_text = graphics::Text::new(ctx, "END OF REPLAY", assets.get_font("title")?)?;
+
+        Ok(ReplayState {
+            input: InputState::default(),
+            core,
+            player: ReplayPlayer::new(replay),
+            frame_timer: 0.0,
+
+            current_track_name: random_play_track(assets),
+            request_menu: false,
+
+            view,
+            end_text,
+        })
+    }
+
+    /// Returns true once the recorded game has been played back in full.
+    fn is_finished(&self) -> bool {
+        self.player.is_finished() || self.core.is_finished()
+    }
+}
+
+impl State for ReplayState {
+    fn update(
+        &mut self,
+        ctx: &mut Context,
+        assets: &Assets,
+        dt: Duration,
+    ) -> GameResult<Transition> {
+        // currently necessary to keep audio looping
+        let current_song = assets.get_music(&self.current_track_name)?;
+        if current_song.paused() {
+            current_song.resume();
+        } else {
+            current_song.play()?;
+        }
+
+        if self.request_menu {
+            assets.get_music(&self.current_track_name)?.pause();
+            return Ok(Transition::Swap(Box::new(MenuState::new(ctx, assets)?)));
+        }
+
+        // play every whole frame that has passed since the last update
+        self.frame_timer += dt.as_subsec_millis();
+        while self.frame_timer >= FRAME_DURATION.as_subsec_millis() && !self.is_finished() {
+            self.frame_timer -= FRAME_DURATION.as_subsec_millis();
+            self.player.advance(&mut self.input);
+            let events = self.core.step(&mut self.input, FRAME_DURATION);
+            play_event_sounds(&events, assets)?;
+        }
+
+        self.view.update(ctx, assets, &self.core);
+
+        Ok(Transition::None)
+    }
+
+    fn draw(&mut self, ctx: &mut Context, assets: &Assets) -> GameResult<()> {
+        self.view.draw(ctx, assets, &self.core)?;
+
+        if self.is_finished() {
+            let coords = graphics::get_screen_coordinates(ctx);
+            let end_dest = Point2::new(coords.w / 2.0 - (self.end_text.width() / 2) as f32, 300.0);
+
+            graphics::set_color(ctx, graphics::Color::new(0.0, 0.0, 0.0, 0.75))?;
+            graphics::rectangle(ctx, graphics::DrawMode::Fill, coords)?;
+            graphics::set_color(ctx, graphics::Color::new(1.0, 1.0, 1.0, 1.0))?;
+            graphics::draw(ctx, &self.end_text, end_dest, 0.0)?;
+        }
+
+        Ok(())
+    }
+
+    fn key_down_event(
+        &mut self,
+        _ctx: &mut Context,
+        keycode: Keycode,
+        _keymod: Mod,
+        _repeat: bool,
+        _assets: &Assets,
+    ) {
+        match keycode {
+            Keycode::Escape => self.request_menu = true,
+            Keycode::Return if self.is_finished() => self.request_menu = true,
+            _ => (),
+        }
+    }
+
+    fn controller_button_down_event(
+        &mut self,
+        _ctx: &mut Context,
+        btn: Button,
+        _instance_id: i32,
+        _assets: &Assets,
+    ) {
+        match btn {
+            Button::B => self.request_menu = true,
+            Button::A if self.is_finished() => self.request_menu = true,
+            _ => (),
+        }
+    }
+}
@@ -0,0 +1,310 @@
+use crate::states::play_state::bot::{next_player, player_name, BotDifficulty};
+use crate::states::play_state::game_mode::{GameMode, ModeSettings, MAX_START_LEVEL};
+use crate::states::play_state::ruleset::Ruleset;
+use crate::states::play_state::PlayState;
+use crate::states::shared::option::{Option, OptionInputCommand};
+use crate::states::{Assets, State, Transition};
+use crate::util::{cycle, format_time, play_click_sfx};
+use ggez::event::{Button, Keycode, Mod};
+use ggez::graphics::Point2;
+use ggez::{graphics, Context, GameResult};
+use std::time::Duration;
+
+const FIRST_OPTION_Y: f32 = 250.0;
+const OPTION_SPACING: f32 = 65.0;
+
+// the values each setting can be cycled through
+const ULTRA_TIME_LIMITS: [f64; 4] = [60.0, 120.0, 180.0, 300.0];
+const DIG_ROWS: [f64; 4] = [5.0, 10.0, 15.0, 18.0];
+const DIG_MESSINESS: [f64; 5] = [0.0, 0.1, 0.25, 0.5, 1.0];
+
+/// A line of the setup screen.
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum SetupOption {
+    Mode,
+    StartLevel,
+    Goal,
+    LevelRule,
+    UltraTimeLimit,
+    DigRows,
+    DigMessiness,
+    ZenGravity,
+    Player,
+    Start,
+    Back,
+}
+
+/// Lets the player choose a mode, and the options of that mode, before a game
+/// starts. The game can also be handed to a bot, to watch it play. Drawn on
+/// top of the menu that pushed it, and pops itself when closed.
+pub struct SetupState {
+    mode: GameMode,
+    settings: ModeSettings,
+    ruleset: Ruleset,
+    // the bot that plays the game, or `None` for a person
+    bot: std::option::Option<BotDifficulty>,
+    title_text: graphics::Text,
+    // the options shown for the current mode, in order
+    option_kinds: Vec<SetupOption>,
+    options: Vec<Option>,
+    current_selection: usize,
+
+    request_start: bool,
+    request_back: bool,
+    request_refresh: bool,
+}
+
+impl SetupState {
+    /// Creates a setup screen for a game played with `ruleset`.
+    pub fn new(ctx: &mut Context, assets: &Assets, ruleset: Ruleset) -> GameResult<Self> {
+        let title_text = graphics::Text::new(ctx, "New Game", assets.get_font("title")?)?;
+
+        let mut state = SetupState {
+            mode: GameMode::default(),
+            settings: ModeSettings::default(),
+            ruleset,
+            bot: None,
+            title_text,
+            option_kinds: Vec::new(),
+            options: Vec::new(),
+            current_selection: 0,
+
+            request_start: false,
+            request_back: false,
+            request_refresh: false,
+        };
+        state.build_options(ctx, assets);
+
+        Ok(state)
+    }
+
+    /// Returns the options shown for `mode`.
+    fn option_kinds(mode: GameMode) -> Vec<SetupOption> {
+        let mut kinds = vec![SetupOption::Mode];
+        match mode {
+            GameMode::Marathon => {
+                kinds.push(SetupOption::StartLevel);
+                kinds.push(SetupOption::Goal);
+                kinds.push(SetupOption::LevelRule);
+            }
+            GameMode::Ultra => kinds.push(SetupOption::UltraTimeLimit),
+            GameMode::Dig => {
+                kinds.push(SetupOption::DigRows);
+                kinds.push(SetupOption::DigMessiness);
+            }
+            GameMode::Zen => kinds.push(SetupOption::ZenGravity),
+            GameMode::Sprint | GameMode::Survival | GameMode::Puzzle | GameMode::Versus => (),
+        }
+        kinds.push(SetupOption::Player);
+        kinds.push(SetupOption::Start);
+        kinds.push(SetupOption::Back);
+        kinds
+    }
+
+    fn option_text(&self, kind: SetupOption) -> String {
+        match kind {
+            SetupOption::Mode => format!("Mode: {}", self.mode.name()),
+            SetupOption::StartLevel => {
+                format!("Start level: {}", self.settings.marathon_start_level + 1)
+            }
+            SetupOption::Goal => format!("Goal: {}", self.settings.marathon_goal.name()),
+            SetupOption::LevelRule => format!(
+                "Lines per level: {}",
+                self.settings.marathon_level_rule.name()
+            ),
+            SetupOption::UltraTimeLimit => format!(
+                "Time limit: {}",
+                format_time(self.settings.ultra_time_limit)
+            ),
+            SetupOption::DigRows => format!("Garbage rows: {}", self.settings.dig_rows),
+            SetupOption::DigMessiness => {
+                format!("Messiness: {:.0}%", self.settings.dig_messiness * 100.0)
+            }
+            SetupOption::ZenGravity => match self.settings.zen_gravity {
+                Some(level) => format!("Gravity: Level {}", level + 1),
+                None => "Gravity: Off".to_string(),
+            },
+            SetupOption::Player => format!("Player: {}", player_name(self.bot)),
+            SetupOption::Start => "Start".to_string(),
+            SetupOption::Back => "Back".to_string(),
+        }
+    }
+
+    /// Creates an option for each line shown for the current mode.
+    fn build_options(&mut self, ctx: &mut Context, assets: &Assets) {
+        let coords = graphics::get_screen_coordinates(ctx);
+        self.option_kinds = SetupState::option_kinds(self.mode);
+        self.options.clear();
+        for (i, kind) in self.option_kinds.clone().iter().enumerate() {
+            let text = self.option_text(*kind);
+            self.options.push(Option::new(
+                ctx,
+                assets,
+                &text,
+                Point2::new(coords.w / 2.0, FIRST_OPTION_Y + OPTION_SPACING * i as f32),
+            ));
+        }
+        self.current_selection = self.current_selection.min(self.options.len() - 1);
+    }
+
+    /// Change the selected setting to its next value, or its previous value
+    /// if `direction` is negative.
+    fn change_setting(&mut self, direction: i32, assets: &Assets) {
+        match self.option_kinds[self.current_selection] {
+            SetupOption::Mode => self.mode = self.mode.next(),
+            SetupOption::StartLevel => {
+                let levels = MAX_START_LEVEL as i32 + 1;
+                let level = self.settings.marathon_start_level as i32 + direction;
+                self.settings.marathon_start_level = level.rem_euclid(levels) as u32;
+            }
+            SetupOption::Goal => self.settings.marathon_goal = self.settings.marathon_goal.next(),
+            SetupOption::LevelRule => {
+                self.settings.marathon_level_rule = self.settings.marathon_level_rule.next()
+            }
+            SetupOption::UltraTimeLimit => {
+                self.settings.ultra_time_limit = cycle(
+                    &ULTRA_TIME_LIMITS,
+                    self.settings.ultra_time_limit,
+                    direction,
+                )
+            }
+            SetupOption::DigRows => {
+                let rows = cycle(&DIG_ROWS, f64::from(self.settings.dig_rows), direction);
+                self.settings.dig_rows = rows as u32;
+            }
+            SetupOption::DigMessiness => {
+                self.settings.dig_messiness =
+                    cycle(&DIG_MESSINESS, self.settings.dig_messiness, direction)
+            }
+            SetupOption::ZenGravity => {
+                // off comes before the first level
+                let levels = MAX_START_LEVEL as i32 + 2;
+                let current = self.settings.zen_gravity.map_or(-1, |level| level as i32);
+                let next = (current + 1 + direction).rem_euclid(levels) - 1;
+                self.settings.zen_gravity = if next < 0 { None } else { Some(next as u32) };
+            }
+            SetupOption::Player => self.bot = next_player(self.bot),
+            SetupOption::Start | SetupOption::Back => return,
+        }
+
+        play_click_sfx(assets).expect("Could not play click sfx in setup state -> change");
+        self.request_refresh = true;
+    }
+
+    fn handle_input(&mut self, command: &OptionInputCommand, assets: &Assets) {
+        match command {
+            OptionInputCommand::Up => {
+                play_click_sfx(assets).expect("Could not play click sfx in setup state -> up");
+                if self.current_selection == 0 {
+                    self.current_selection = self.options.len() - 1;
+                } else {
+                    self.current_selection -= 1;
+                }
+            }
+            OptionInputCommand::Down => {
+                play_click_sfx(assets).expect("Could not play click sfx in setup state -> down");
+                if self.current_selection >= self.options.len() - 1 {
+                    self.current_selection = 0;
+                } else {
+                    self.current_selection += 1;
+                }
+            }
+            OptionInputCommand::Select => match self.option_kinds[self.current_selection] {
+                SetupOption::Start => self.request_start = true,
+                SetupOption::Back => self.request_back = true,
+                _ => self.change_setting(1, assets),
+            },
+        }
+    }
+}
+
+impl State for SetupState {
+    fn update(
+        &mut self,
+        ctx: &mut Context,
+        assets: &Assets,
+        _: Duration,
+    ) -> GameResult<Transition> {
+        if self.request_back {
+            return Ok(Transition::Pop);
+        } else if self.request_start {
+            assets.get_music("menu")?.pause();
+            return Ok(Transition::Swap(Box::new(PlayState::new(
+                ctx,
+                assets,
+                self.mode,
+                self.settings,
+                self.ruleset,
+                self.bot,
+            )?)));
+        }
+
+        if self.request_refresh {
+            self.build_options(ctx, assets);
+            self.request_refresh = false;
+        }
+
+        for (i, option) in self.options.iter_mut().enumerate() {
+            option.update(i == self.current_selection)?;
+        }
+
+        Ok(Transition::None)
+    }
+
+    fn draw(&mut self, ctx: &mut Context, _: &Assets) -> GameResult<()> {
+        let coords = graphics::get_screen_coordinates(ctx);
+        let title_dest = Point2::new(coords.w / 2.0 - (self.title_text.width() / 2) as f32, 100.0);
+
+        graphics::set_color(ctx, graphics::Color::new(0.0, 0.0, 0.0, 0.75))?;
+        graphics::rectangle(ctx, graphics::DrawMode::Fill, coords)?;
+        graphics::set_color(ctx, graphics::Color::new(1.0, 1.0, 1.0, 1.0))?;
+        graphics::draw(ctx, &self.title_text, title_dest, 0.0)?;
+
+        for option in &self.options {
+            option.draw(ctx)?;
+        }
+
+        Ok(())
+    }
+
+    fn key_down_event(
+        &mut self,
+        _ctx: &mut Context,
+        keycode: Keycode,
+        _keymod: Mod,
+        repeat: bool,
+        assets: &Assets,
+    ) {
+        if repeat {
+            return;
+        }
+
+        match keycode {
+            Keycode::Up => self.handle_input(&OptionInputCommand::Up, assets),
+            Keycode::Down => self.handle_input(&OptionInputCommand::Down, assets),
+            Keycode::Return => self.handle_input(&OptionInputCommand::Select, assets),
+            Keycode::Left => self.change_setting(-1, assets),
+            Keycode::Right => self.change_setting(1, assets),
+            Keycode::Escape => self.request_back = true,
+            _ => (),
+        }
+    }
+
+    fn controller_button_down_event(
+        &mut self,
+        _ctx: &mut Context,
+        btn: Button,
+        _instance_id: i32,
+        assets: &Assets,
+    ) {
+        match btn {
+            Button::DPadUp => self.handle_input(&OptionInputCommand::Up, assets),
+            Button::DPadDown => self.handle_input(&OptionInputCommand::Down, assets),
+            Button::A => self.handle_input(&OptionInputCommand::Select, assets),
+            Button::DPadLeft => self.change_setting(-1, assets),
+            Button::DPadRight => self.change_setting(1, assets),
+            Button::B => self.request_back = true,
+            _ => (),
+        }
+    }
+}
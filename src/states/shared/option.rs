@@ -19,7 +19,7 @@ pub struct Option {
 
 impl Option {
     /// Creates a new `Option`.
-    pub fn new(ctx: &mut Context, assets: &Assets, name: &'static str, top_left: Point2) -> Self {
+    pub fn new(ctx: &mut Context, assets: &Assets, name: &str, top_left: Point2) -> Self {
         let text = graphics::Text::new(ctx, name, assets.get_font("normal").unwrap()).unwrap();
         let real_pos = Point2::new(top_left.x - (text.width() / 2) as f32, top_left.y);
         Self {
@@ -38,6 +38,14 @@ impl Option {
         Ok(())
     }
 
+    /// Replaces the text of the `Option`, keeping it centred on the same point.
+    pub fn set_text(&mut self, ctx: &mut Context, assets: &Assets, name: &str) -> GameResult<()> {
+        let centre_x = self.position.x + (self.text.width() / 2) as f32;
+        self.text = graphics::Text::new(ctx, name, assets.get_font("normal")?)?;
+        self.position.x = centre_x - (self.text.width() / 2) as f32;
+        Ok(())
+    }
+
     /// Draws the `Option`. The colour of the text rendered depends on
     /// `is_selected`.
     pub fn draw(&self, ctx: &mut Context) -> GameResult<()> {
@@ -0,0 +1,278 @@
+use super::protocol::{MatchSettings, PROTOCOL_VERSION};
+use crate::states::play_state::game_mode::GameMode;
+use crate::states::play_state::gravity::GravityCurve;
+use crate::states::play_state::randomizer::RandomizerKind;
+use crate::states::play_state::rotation::RotationSystemKind;
+use crate::states::play_state::ruleset::{LockReset, Ruleset};
+use std::io::{self, ErrorKind};
+use std::net::{Ipv4Addr, SocketAddr, UdpSocket};
+use std::time::{Duration, Instant};
+
+/// The UDP port hosts listen for lobby queries on.
+pub const LOBBY_PORT: u16 = 7879;
+/// The number of players in a network match.
+pub const MAX_PLAYERS: u32 = 2;
+
+const QUERY: &str = "rustris-query";
+const QUERY_INTERVAL: Duration = Duration::from_secs(1);
+// a game that has not answered for this long is taken to be gone
+const LISTING_TIMEOUT: Duration = Duration::from_secs(3);
+const MAX_NAME_LEN: usize = 16;
+const DATAGRAM_SIZE: usize = 512;
+
+/// A game hosted on the local network, as told by its host.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Listing {
+    pub name: String,
+    /// The address to connect to, to join the game.
+    pub address: SocketAddr,
+    pub mode: GameMode,
+    pub settings: MatchSettings,
+    /// The players in the game so far, including the host.
+    pub players: u32,
+}
+
+impl Listing {
+    /// Returns true if no one else can join the game.
+    pub fn is_full(&self) -> bool {
+        self.players >= MAX_PLAYERS
+    }
+
+    /// Returns the listing as the answer to a query, written as a line of
+    /// text. The name comes last, as the only part that may contain spaces.
+    fn encode(&self) -> String {
+        let ruleset = self.settings.ruleset;
+        format!(
+            "rustris-game {} {} {} {} {} {} {} {} {} {} {}",
+            PROTOCOL_VERSION,
+            self.address.port(),
+            self.mode.name(),
+            self.players,
+            self.settings.best_of,
+            ruleset.rotation_system.name(),
+            ruleset.lock_delay,
+            ruleset.lock_reset.name(),
+            ruleset.randomizer.name(),
+            ruleset.gravity.name(),
+            self.name
+        )
+    }
+
+    /// Reads an answer written by `Listing::encode`, sent from `sender`.
+    /// Returns `None` if the answer is not valid, or comes from a different
+    /// version of the game.
+    fn decode(line: &str, sender: SocketAddr) -> Option<Listing> {
+        let parts: Vec<&str> = line.splitn(12, ' ').collect();
+        match parts.as_slice() {
+            ["rustris-game", version, port, mode, players, best_of, rotation, lock_delay, lock_reset, randomizer, gravity, name]
+                if version.parse() == Ok(PROTOCOL_VERSION) =>
+            {
+                Some(Listing {
+                    name: name.to_string(),
+                    address: SocketAddr::new(sender.ip(), port.parse().ok()?),
+                    mode: GameMode::from_name(mode)?,
+                    settings: MatchSettings {
+                        best_of: best_of.parse().ok()?,
+                        ruleset: Ruleset {
+                            rotation_system: RotationSystemKind::from_name(rotation)?,
+                            lock_delay: lock_delay.parse().ok()?,
+                            lock_reset: LockReset::from_name(lock_reset)?,
+                            randomizer: RandomizerKind::from_name(randomizer)?,
+                            gravity: GravityCurve::from_name(gravity)?,
+                        },
+                    },
+                    players: players.parse().ok()?,
+                })
+            }
+            _ => None,
+        }
+    }
+}
+
+/// Returns the name this machine is listed under: its host name, cut down to
+/// a short run of letters, digits and dashes.
+pub fn host_name() -> String {
+    let name = ["HOSTNAME", "COMPUTERNAME"]
+        .iter()
+        .filter_map(|key| std::env::var(key).ok())
+        .chain(std::fs::read_to_string("/etc/hostname").ok())
+        .map(|name| {
+            name.chars()
+                .filter(|c| c.is_ascii_alphanumeric() || *c == '-')
+                .take(MAX_NAME_LEN)
+                .collect::<String>()
+        })
+        .find(|name| !name.is_empty());
+    name.unwrap_or_else(|| "Rustris".to_string())
+}
+
+/// Answers the queries of machines looking for games, on behalf of a host.
+pub struct Announcer {
+    socket: UdpSocket,
+}
+
+impl Announcer {
+    /// Listens for queries on `port` of every network interface.
+    pub fn bind(port: u16) -> io::Result<Self> {
+        let socket = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, port))?;
+        socket.set_nonblocking(true)?;
+        Ok(Announcer { socket })
+    }
+
+    /// Answers every query that has arrived since the last call with
+    /// `listing`. The address in the listing is only used for its port, as
+    /// each machine reaches the host at the address the answer came from.
+    pub fn answer(&self, listing: &Listing) -> io::Result<()> {
+        let mut buffer = [0; DATAGRAM_SIZE];
+        loop {
+            match self.socket.recv_from(&mut buffer) {
+                Ok((read, sender)) => {
+                    if &buffer[..read] == QUERY.as_bytes() {
+                        let _ = self.socket.send_to(listing.encode().as_bytes(), sender);
+                    }
+                }
+                Err(ref e) if e.kind() == ErrorKind::WouldBlock => return Ok(()),
+                // answers that could not be delivered can show up as errors
+                // on the next receive
+                Err(ref e) if e.kind() == ErrorKind::ConnectionReset => (),
+                Err(e) => return Err(e),
+            }
+        }
+    }
+}
+
+/// Finds games on the local network, by sending out a query every second and
+/// collecting the answers. A game drops off the list once its host stops
+/// answering.
+pub struct Browser {
+    socket: UdpSocket,
+    // where queries are sent
+    targets: Vec<SocketAddr>,
+    // each game found, and when it last answered
+    listings: Vec<(Listing, Instant)>,
+    last_query: Option<Instant>,
+}
+
+impl Browser {
+    /// Creates a browser that looks for games across the local network.
+    pub fn new() -> io::Result<Self> {
+        Browser::with_targets(vec![SocketAddr::new(
+            Ipv4Addr::BROADCAST.into(),
+            LOBBY_PORT,
+        )])
+    }
+
+    /// Creates a browser that sends its queries to `targets` only.
+    pub fn with_targets(targets: Vec<SocketAddr>) -> io::Result<Self> {
+        let socket = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0))?;
+        socket.set_broadcast(true)?;
+        socket.set_nonblocking(true)?;
+        Ok(Browser {
+            socket,
+            targets,
+            listings: Vec::new(),
+            last_query: None,
+        })
+    }
+
+    /// Returns the games found, in the order they were first found.
+    pub fn listings(&self) -> Vec<&Listing> {
+        self.listings.iter().map(|(listing, _)| listing).collect()
+    }
+
+    /// Sends a query if it is time to, takes in the answers that have arrived
+    /// and forgets games that have stopped answering. Returns true if the
+    /// list of games changed.
+    pub fn update(&mut self) -> bool {
+        let now = Instant::now();
+        if self
+            .last_query
+            .map(|last| now.duration_since(last) >= QUERY_INTERVAL)
+            .unwrap_or(true)
+        {
+            for target in &self.targets {
+                // a network that cannot be reached has no games to find
+                let _ = self.socket.send_to(QUERY.as_bytes(), target);
+            }
+            self.last_query = Some(now);
+        }
+
+        let mut changed = false;
+        let mut buffer = [0; DATAGRAM_SIZE];
+        while let Ok((read, sender)) = self.socket.recv_from(&mut buffer) {
+            let listing = std::str::from_utf8(&buffer[..read])
+                .ok()
+                .and_then(|line| Listing::decode(line, sender));
+            if let Some(listing) = listing {
+                let known = self
+                    .listings
+                    .iter_mut()
+                    .find(|(known, _)| known.address == listing.address);
+                match known {
+                    Some((known, answered)) => {
+                        changed |= *known != listing;
+                        *known = listing;
+                        *answered = now;
+                    }
+                    None => {
+                        self.listings.push((listing, now));
+                        changed = true;
+                    }
+                }
+            }
+        }
+
+        let found = self.listings.len();
+        self.listings
+            .retain(|(_, answered)| now.duration_since(*answered) < LISTING_TIMEOUT);
+        changed || self.listings.len() != found
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Announcer, Browser, Listing};
+    use crate::states::play_state::game_mode::GameMode;
+    use crate::states::play_state::ruleset::Ruleset;
+    use crate::states::versus_state::protocol::MatchSettings;
+    use std::net::SocketAddr;
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn browser_finds_a_game_on_localhost() {
+        let announcer = Announcer::bind(0).unwrap();
+        let port = announcer.socket.local_addr().unwrap().port();
+        let mut browser =
+            Browser::with_targets(vec![SocketAddr::from(([127, 0, 0, 1], port))]).unwrap();
+        let listing = Listing {
+            name: "office pc".to_string(),
+            address: SocketAddr::from(([0, 0, 0, 0], 7878)),
+            mode: GameMode::Versus,
+            settings: MatchSettings {
+                best_of: 5,
+                ruleset: Ruleset::default(),
+            },
+            players: 1,
+        };
+
+        let started = Instant::now();
+        while browser.listings().is_empty() {
+            assert!(started.elapsed() < Duration::from_secs(5));
+            browser.update();
+            announcer.answer(&listing).unwrap();
+            std::thread::sleep(Duration::from_millis(1));
+        }
+
+        // the game is reached at the address the answer came from
+        let found = browser.listings()[0].clone();
+        assert_eq!("127.0.0.1:7878", found.address.to_string());
+        assert_eq!(
+            Listing {
+                address: found.address,
+                ..listing
+            },
+            found
+        );
+        assert!(!found.is_full());
+    }
+}
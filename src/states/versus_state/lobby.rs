@@ -0,0 +1,659 @@
+use super::discovery::{host_name, Announcer, Browser, Listing, LOBBY_PORT, MAX_PLAYERS};
+use super::net::{NetVersusState, Peer};
+use super::protocol::{Connection, MatchSettings, Message, DEFAULT_PORT, PROTOCOL_VERSION};
+use crate::states::play_state::game_mode::GameMode;
+use crate::states::play_state::handling::Handling;
+use crate::states::play_state::ruleset::Ruleset;
+use crate::states::shared::option::{Option, OptionInputCommand};
+use crate::states::{Assets, State, Transition};
+use crate::util::{cycle, play_click_sfx};
+use ggez::event::{Button, Keycode, Mod};
+use ggez::graphics::Point2;
+use ggez::{graphics, Context, GameResult};
+use std::io::ErrorKind;
+use std::net::TcpListener;
+use std::time::Duration;
+
+// the position of each option while in a game. Only the host can change the
+// settings
+const ROTATION_OPTION: usize = 0;
+const LOCK_RESET_OPTION: usize = 1;
+const RANDOMIZER_OPTION: usize = 2;
+const GRAVITY_OPTION: usize = 3;
+const FIRST_TO_OPTION: usize = 4;
+const READY_OPTION: usize = 5;
+const LEAVE_OPTION: usize = 6;
+
+// the most games listed at once
+const MAX_LISTINGS: usize = 8;
+
+const STATUS_Y: f32 = 170.0;
+const FIRST_OPTION_Y: f32 = 230.0;
+const OPTION_SPACING: f32 = 45.0;
+const HELP_Y: f32 = 670.0;
+
+// the number of rounds a player can be asked to win
+const FIRST_TO: [u32; 4] = [1, 2, 3, 4];
+const DEFAULT_FIRST_TO: u32 = 2;
+
+/// Where the lobby is at.
+enum Phase {
+    /// Looking for games on the local network.
+    Browsing(Browser),
+    /// Hosting a game, and waiting for someone to join it.
+    Hosting,
+    /// In a game with an opponent, getting ready to start.
+    Room(Connection),
+    /// Something went wrong, as described by the status text.
+    Failed,
+}
+
+/// Finds games hosted on the local network, or hosts one, so a network match
+/// can be started without typing in an address. Hosts answer queries broadcast
+/// over UDP with the name of the machine, the ruleset, the mode and the number
+/// of players. Once someone joins, the host chooses the ruleset and the number
+/// of rounds to win, and the match starts when both sides are ready. Drawn on
+/// top of the menu that pushed it, and pops itself when closed.
+pub struct LobbyState {
+    phase: Phase,
+    is_host: bool,
+    name: String,
+    // the ruleset a hosted game starts out with
+    ruleset: Ruleset,
+    // the settings of the game hosted or joined. Only known by the joining
+    // side once the host has sent them
+    settings: std::option::Option<MatchSettings>,
+    // only set while hosting
+    listener: std::option::Option<TcpListener>,
+    announcer: std::option::Option<Announcer>,
+    // the games listed as options, while browsing
+    listings: Vec<Listing>,
+
+    hello_received: bool,
+    opponent_handling: std::option::Option<Handling>,
+    ready: bool,
+    opponent_ready: bool,
+
+    title_text: graphics::Text,
+    status_text: graphics::Text,
+    help_text: graphics::Text,
+    options: Vec<Option>,
+    current_selection: usize,
+
+    request_select: bool,
+    request_back: bool,
+    request_refresh: bool,
+    // set on the host when the settings change, so the opponent is told
+    request_settings: bool,
+}
+
+impl LobbyState {
+    /// Creates a lobby that starts out looking for games. A game hosted from
+    /// it is played with `ruleset` unless the host changes it.
+    pub fn new(ctx: &mut Context, assets: &Assets, ruleset: Ruleset) -> GameResult<Self> {
+        let mut state = LobbyState {
+            phase: Phase::Failed,
+            is_host: false,
+            name: host_name(),
+            ruleset,
+            settings: None,
+            listener: None,
+            announcer: None,
+            listings: Vec::new(),
+
+            hello_received: false,
+            opponent_handling: None,
+            ready: false,
+            opponent_ready: false,
+
+            title_text: graphics::Text::new(ctx, "LAN Lobby", assets.get_font("title")?)?,
+            status_text: graphics::Text::new(ctx, " ", assets.get_font("normal")?)?,
+            help_text: graphics::Text::new(ctx, " ", assets.get_font("ui")?)?,
+            options: Vec::new(),
+            current_selection: 0,
+
+            request_select: false,
+            request_back: false,
+            request_refresh: false,
+            request_settings: false,
+        };
+        state.browse(ctx, assets)?;
+        Ok(state)
+    }
+
+    /// Starts looking for games again, leaving any game hosted or joined.
+    fn browse(&mut self, ctx: &mut Context, assets: &Assets) -> GameResult<()> {
+        self.leave_room();
+        self.is_host = false;
+        self.ready = false;
+        self.listener = None;
+        self.announcer = None;
+        self.listings.clear();
+        self.phase = match Browser::new() {
+            Ok(browser) => Phase::Browsing(browser),
+            Err(_) => {
+                return self.fail(ctx, assets, "Could not look for games on the network");
+            }
+        };
+        self.set_help(ctx, assets, "Choose a game to join, or host one")?;
+        self.rebuild(ctx, assets)
+    }
+
+    /// Hosts a game, taking connections on `DEFAULT_PORT` and answering
+    /// queries on `LOBBY_PORT`.
+    fn host(&mut self, ctx: &mut Context, assets: &Assets) -> GameResult<()> {
+        let listener = TcpListener::bind(("0.0.0.0", DEFAULT_PORT))
+            .and_then(|listener| listener.set_nonblocking(true).map(|_| listener));
+        let announcer = Announcer::bind(LOBBY_PORT);
+        match (listener, announcer) {
+            (Ok(listener), Ok(announcer)) => {
+                self.is_host = true;
+                self.listener = Some(listener);
+                self.announcer = Some(announcer);
+                self.settings = Some(MatchSettings {
+                    best_of: DEFAULT_FIRST_TO * 2 - 1,
+                    ruleset: self.ruleset,
+                });
+                self.phase = Phase::Hosting;
+                self.set_help(ctx, assets, "Left and Right change the settings")?;
+                self.rebuild(ctx, assets)
+            }
+            _ => self.fail(ctx, assets, "Could not host a game, is one already hosted?"),
+        }
+    }
+
+    /// Joins the game in `listing`.
+    fn join(&mut self, ctx: &mut Context, assets: &Assets, listing: &Listing) -> GameResult<()> {
+        if listing.is_full() {
+            return self.fail(ctx, assets, "That game is full");
+        }
+        match Connection::connect(&listing.address.to_string(), DEFAULT_PORT) {
+            Ok(connection) => {
+                self.settings = None;
+                self.enter_room(ctx, assets, connection)
+            }
+            Err(_) => self.fail(ctx, assets, "Could not connect to the host"),
+        }
+    }
+
+    /// Says hello to the other side of a new connection, along with this
+    /// player's handling and, from the host, the settings.
+    fn enter_room(
+        &mut self,
+        ctx: &mut Context,
+        assets: &Assets,
+        mut connection: Connection,
+    ) -> GameResult<()> {
+        let _ = connection.send(&Message::Hello(PROTOCOL_VERSION));
+        let _ = connection.send(&Message::Handling(Handling::load(ctx)));
+        if let (true, Some(settings)) = (self.is_host, self.settings) {
+            let _ = connection.send(&Message::Start(settings));
+        }
+        let _ = connection.send(&Message::Ready(self.ready));
+        self.hello_received = false;
+        self.opponent_handling = None;
+        self.opponent_ready = false;
+        self.phase = Phase::Room(connection);
+        self.rebuild(ctx, assets)
+    }
+
+    /// Says goodbye to the opponent, if there is one.
+    fn leave_room(&mut self) {
+        if let Phase::Room(connection) = &mut self.phase {
+            let _ = connection.send(&Message::Bye);
+        }
+    }
+
+    /// Shows `reason` as the status text, with the option to go back to
+    /// looking for games.
+    fn fail(&mut self, ctx: &mut Context, assets: &Assets, reason: &str) -> GameResult<()> {
+        self.leave_room();
+        self.listener = None;
+        self.announcer = None;
+        self.phase = Phase::Failed;
+        self.set_help(ctx, assets, "Press Escape to go back")?;
+        self.set_status(ctx, assets, reason)?;
+        self.options = vec![Option::new(
+            ctx,
+            assets,
+            "Back",
+            LobbyState::option_position(ctx, 0),
+        )];
+        self.current_selection = 0;
+        Ok(())
+    }
+
+    /// Handles the messages sent by the opponent. Returns the seed of the
+    /// first round once the host has started the match.
+    fn handle_messages(
+        &mut self,
+        ctx: &mut Context,
+        assets: &Assets,
+    ) -> GameResult<std::option::Option<u64>> {
+        loop {
+            let message = match &mut self.phase {
+                Phase::Room(connection) => match connection.receive() {
+                    Ok(Some(message)) => message,
+                    Ok(None) => return Ok(None),
+                    Err(_) => Message::Bye,
+                },
+                _ => return Ok(None),
+            };
+            match message {
+                Message::Hello(version) if version != PROTOCOL_VERSION => {
+                    self.fail(ctx, assets, "The other side is running a different version")?;
+                }
+                Message::Hello(_) => self.hello_received = true,
+                Message::Handling(handling) if self.hello_received => {
+                    self.opponent_handling = Some(handling)
+                }
+                Message::Start(settings) if !self.is_host => {
+                    // the settings changed, so both sides have to be ready again
+                    self.settings = Some(settings);
+                    self.set_ready(false);
+                    self.opponent_ready = false;
+                    self.request_refresh = true;
+                }
+                Message::Ready(ready) => {
+                    self.opponent_ready = ready;
+                    self.request_refresh = true;
+                }
+                Message::Round(seed) if !self.is_host => return Ok(Some(seed)),
+                Message::Bye if self.is_host => {
+                    // go back to waiting for someone to join
+                    self.phase = Phase::Hosting;
+                    self.ready = false;
+                    self.rebuild(ctx, assets)?;
+                }
+                Message::Bye => self.fail(ctx, assets, "The host left")?,
+                _ => (),
+            }
+        }
+    }
+
+    /// Marks this side as ready or not, and tells the opponent.
+    fn set_ready(&mut self, ready: bool) {
+        self.ready = ready;
+        if let Phase::Room(connection) = &mut self.phase {
+            let _ = connection.send(&Message::Ready(ready));
+        }
+    }
+
+    /// Returns the listing for the game hosted on this machine.
+    fn own_listing(&self) -> std::option::Option<Listing> {
+        let players = match self.phase {
+            Phase::Room(_) => MAX_PLAYERS,
+            _ => 1,
+        };
+        Some(Listing {
+            name: self.name.clone(),
+            address: self.listener.as_ref()?.local_addr().ok()?,
+            mode: GameMode::Versus,
+            settings: self.settings?,
+            players,
+        })
+    }
+
+    /// Changes the selected setting to its next value, or its previous one if
+    /// `direction` is negative. Only the host can change settings.
+    fn change_setting(&mut self, direction: i32, assets: &Assets) {
+        let settings = match (&self.phase, &mut self.settings) {
+            (Phase::Hosting, Some(settings)) | (Phase::Room(_), Some(settings)) if self.is_host => {
+                settings
+            }
+            _ => return,
+        };
+        let ruleset = &mut settings.ruleset;
+        match self.current_selection {
+            ROTATION_OPTION => ruleset.rotation_system = ruleset.rotation_system.next(),
+            LOCK_RESET_OPTION => ruleset.lock_reset = ruleset.lock_reset.next(),
+            RANDOMIZER_OPTION => ruleset.randomizer = ruleset.randomizer.next(),
+            GRAVITY_OPTION => ruleset.gravity = ruleset.gravity.next(),
+            FIRST_TO_OPTION => {
+                let first_to = cycle(&FIRST_TO, settings.best_of / 2 + 1, direction);
+                settings.best_of = first_to * 2 - 1;
+            }
+            _ => return,
+        }
+
+        play_click_sfx(assets).expect("Could not play click sfx in lobby -> change");
+        self.request_settings = true;
+        self.request_refresh = true;
+    }
+
+    fn handle_input(&mut self, command: &OptionInputCommand, assets: &Assets) {
+        match command {
+            OptionInputCommand::Up => {
+                play_click_sfx(assets).expect("Could not play click sfx in lobby -> up");
+                if self.current_selection == 0 {
+                    self.current_selection = self.options.len() - 1;
+                } else {
+                    self.current_selection -= 1;
+                }
+            }
+            OptionInputCommand::Down => {
+                play_click_sfx(assets).expect("Could not play click sfx in lobby -> down");
+                if self.current_selection >= self.options.len() - 1 {
+                    self.current_selection = 0;
+                } else {
+                    self.current_selection += 1;
+                }
+            }
+            OptionInputCommand::Select => self.request_select = true,
+        }
+    }
+
+    /// Acts on the selected option.
+    fn select(&mut self, ctx: &mut Context, assets: &Assets) -> GameResult<()> {
+        play_click_sfx(assets).expect("Could not play click sfx in lobby -> select");
+        let selection = self.current_selection;
+        match self.phase {
+            Phase::Browsing(_) if selection < self.listings.len() => {
+                let listing = self.listings[selection].clone();
+                self.join(ctx, assets, &listing)
+            }
+            Phase::Browsing(_) if selection == self.listings.len() => self.host(ctx, assets),
+            Phase::Browsing(_) => {
+                self.request_back = true;
+                Ok(())
+            }
+            // only leaving is possible until the host has sent the settings
+            Phase::Room(_) if self.settings.is_none() => self.browse(ctx, assets),
+            Phase::Hosting | Phase::Room(_) if selection == READY_OPTION => {
+                let ready = !self.ready;
+                self.set_ready(ready);
+                self.rebuild(ctx, assets)
+            }
+            Phase::Hosting | Phase::Room(_) if selection == LEAVE_OPTION => {
+                self.browse(ctx, assets)
+            }
+            Phase::Hosting | Phase::Room(_) => {
+                self.change_setting(1, assets);
+                Ok(())
+            }
+            Phase::Failed => self.browse(ctx, assets),
+        }
+    }
+
+    /// Goes back a step: out of a game hosted or joined, or out of the lobby.
+    fn back(&mut self, ctx: &mut Context, assets: &Assets) -> GameResult<bool> {
+        match self.phase {
+            Phase::Browsing(_) => Ok(true),
+            _ => {
+                self.browse(ctx, assets)?;
+                Ok(false)
+            }
+        }
+    }
+
+    fn set_status(&mut self, ctx: &mut Context, assets: &Assets, status: &str) -> GameResult<()> {
+        self.status_text = graphics::Text::new(ctx, status, assets.get_font("normal")?)?;
+        Ok(())
+    }
+
+    fn set_help(&mut self, ctx: &mut Context, assets: &Assets, help: &str) -> GameResult<()> {
+        self.help_text = graphics::Text::new(ctx, help, assets.get_font("ui")?)?;
+        Ok(())
+    }
+
+    fn option_position(ctx: &Context, index: usize) -> Point2 {
+        let coords = graphics::get_screen_coordinates(ctx);
+        Point2::new(
+            coords.w / 2.0,
+            FIRST_OPTION_Y + OPTION_SPACING * index as f32,
+        )
+    }
+
+    /// Returns the text shown for each player's readiness.
+    fn ready_text(ready: bool) -> &'static str {
+        if ready {
+            "Ready"
+        } else {
+            "Not ready"
+        }
+    }
+
+    fn listing_text(listing: &Listing) -> String {
+        let ruleset = listing.settings.ruleset;
+        format!(
+            "{}: {}, {} {} {} {}, first to {}, {}/{} players",
+            listing.name,
+            listing.mode.name(),
+            ruleset.rotation_system.name(),
+            ruleset.lock_reset.name(),
+            ruleset.randomizer.name(),
+            ruleset.gravity.name(),
+            listing.settings.best_of / 2 + 1,
+            listing.players,
+            MAX_PLAYERS
+        )
+    }
+
+    /// Rebuilds the status text and the options to match the phase.
+    fn rebuild(&mut self, ctx: &mut Context, assets: &Assets) -> GameResult<()> {
+        let (status, names) = match &self.phase {
+            Phase::Browsing(browser) => {
+                self.listings = browser
+                    .listings()
+                    .into_iter()
+                    .take(MAX_LISTINGS)
+                    .cloned()
+                    .collect();
+                let status = if self.listings.is_empty() {
+                    "Looking for games on the local network...".to_string()
+                } else {
+                    "Games on the local network:".to_string()
+                };
+                let mut names: Vec<String> =
+                    self.listings.iter().map(LobbyState::listing_text).collect();
+                names.push("Host Game".to_string());
+                names.push("Back".to_string());
+                (status, names)
+            }
+            Phase::Hosting | Phase::Room(_) => {
+                let status = match (&self.phase, self.is_host) {
+                    (Phase::Hosting, _) => {
+                        format!("Hosting as {}, waiting for an opponent...", self.name)
+                    }
+                    (_, true) => format!(
+                        "You (host): {}   Opponent: {}",
+                        LobbyState::ready_text(self.ready),
+                        LobbyState::ready_text(self.opponent_ready)
+                    ),
+                    (_, false) => format!(
+                        "You: {}   Host: {}",
+                        LobbyState::ready_text(self.ready),
+                        LobbyState::ready_text(self.opponent_ready)
+                    ),
+                };
+                let names = match self.settings {
+                    Some(MatchSettings { best_of, ruleset }) => vec![
+                        format!("Rotation: {}", ruleset.rotation_system.name()),
+                        format!("Lock reset: {}", ruleset.lock_reset.name()),
+                        format!("Randomizer: {}", ruleset.randomizer.name()),
+                        format!("Gravity: {}", ruleset.gravity.name()),
+                        format!("First to: {}", best_of / 2 + 1),
+                        format!("Ready: {}", if self.ready { "Yes" } else { "No" }),
+                        "Leave".to_string(),
+                    ],
+                    // the host has not sent the settings yet
+                    None => vec!["Leave".to_string()],
+                };
+                (status, names)
+            }
+            Phase::Failed => return Ok(()),
+        };
+
+        self.set_status(ctx, assets, &status)?;
+        let mut options = Vec::new();
+        for (i, name) in names.iter().enumerate() {
+            options.push(Option::new(
+                ctx,
+                assets,
+                name,
+                LobbyState::option_position(ctx, i),
+            ));
+        }
+        self.options = options;
+        self.current_selection = self.current_selection.min(self.options.len() - 1);
+        Ok(())
+    }
+}
+
+impl State for LobbyState {
+    fn update(
+        &mut self,
+        ctx: &mut Context,
+        assets: &Assets,
+        _: Duration,
+    ) -> GameResult<Transition> {
+        if self.request_back {
+            self.request_back = false;
+            if self.back(ctx, assets)? {
+                return Ok(Transition::Pop);
+            }
+        } else if self.request_select {
+            self.request_select = false;
+            self.select(ctx, assets)?;
+        }
+
+        if let Phase::Browsing(browser) = &mut self.phase {
+            self.request_refresh |= browser.update();
+        }
+
+        if let (Phase::Hosting, Some(listener)) = (&self.phase, &self.listener) {
+            match listener.accept() {
+                Ok((stream, _)) => match Connection::new(stream) {
+                    Ok(connection) => self.enter_room(ctx, assets, connection)?,
+                    Err(_) => self.fail(ctx, assets, "Could not connect to the opponent")?,
+                },
+                Err(ref e) if e.kind() == ErrorKind::WouldBlock => (),
+                Err(_) => self.fail(ctx, assets, "Could not connect to the opponent")?,
+            }
+        }
+
+        if let (Some(announcer), Some(listing)) = (&self.announcer, self.own_listing()) {
+            // queries that cannot be answered are left for the next update
+            let _ = announcer.answer(&listing);
+        }
+
+        if self.request_settings {
+            self.request_settings = false;
+            if let (Phase::Room(connection), Some(settings)) = (&mut self.phase, self.settings) {
+                let _ = connection.send(&Message::Start(settings));
+            }
+            // the settings changed, so both sides have to be ready again
+            self.set_ready(false);
+            self.opponent_ready = false;
+        }
+
+        let seed = match self.handle_messages(ctx, assets)? {
+            Some(seed) => Some(seed),
+            None if self.is_host && self.ready && self.opponent_ready => Some(rand::random()),
+            None => None,
+        };
+        if let (Some(seed), Some(settings), Some(handling)) =
+            (seed, self.settings, self.opponent_handling)
+        {
+            let phase = std::mem::replace(&mut self.phase, Phase::Failed);
+            if let Phase::Room(connection) = phase {
+                assets.get_music("menu")?.pause();
+                return Ok(Transition::Swap(Box::new(NetVersusState::new(
+                    ctx,
+                    assets,
+                    Peer {
+                        connection,
+                        handling,
+                    },
+                    self.is_host,
+                    settings,
+                    seed,
+                    None,
+                )?)));
+            }
+            self.phase = phase;
+        }
+
+        if self.request_refresh {
+            self.request_refresh = false;
+            self.rebuild(ctx, assets)?;
+        }
+
+        for (i, option) in self.options.iter_mut().enumerate() {
+            option.update(i == self.current_selection)?;
+        }
+
+        Ok(Transition::None)
+    }
+
+    fn draw(&mut self, ctx: &mut Context, _: &Assets) -> GameResult<()> {
+        let coords = graphics::get_screen_coordinates(ctx);
+        let centered = |text: &graphics::Text, y: f32| {
+            Point2::new(coords.w / 2.0 - (text.width() / 2) as f32, y)
+        };
+
+        graphics::set_color(ctx, graphics::Color::new(0.0, 0.0, 0.0, 0.75))?;
+        graphics::rectangle(ctx, graphics::DrawMode::Fill, coords)?;
+        graphics::set_color(ctx, graphics::Color::new(1.0, 1.0, 1.0, 1.0))?;
+        graphics::draw(
+            ctx,
+            &self.title_text,
+            centered(&self.title_text, 100.0),
+            0.0,
+        )?;
+        graphics::draw(
+            ctx,
+            &self.status_text,
+            centered(&self.status_text, STATUS_Y),
+            0.0,
+        )?;
+        graphics::draw(ctx, &self.help_text, centered(&self.help_text, HELP_Y), 0.0)?;
+
+        for option in &self.options {
+            option.draw(ctx)?;
+        }
+
+        Ok(())
+    }
+
+    fn key_down_event(
+        &mut self,
+        _ctx: &mut Context,
+        keycode: Keycode,
+        _keymod: Mod,
+        repeat: bool,
+        assets: &Assets,
+    ) {
+        if repeat {
+            return;
+        }
+
+        match keycode {
+            Keycode::Up => self.handle_input(&OptionInputCommand::Up, assets),
+            Keycode::Down => self.handle_input(&OptionInputCommand::Down, assets),
+            Keycode::Return => self.handle_input(&OptionInputCommand::Select, assets),
+            Keycode::Left => self.change_setting(-1, assets),
+            Keycode::Right => self.change_setting(1, assets),
+            Keycode::Escape => self.request_back = true,
+            _ => (),
+        }
+    }
+
+    fn controller_button_down_event(
+        &mut self,
+        _ctx: &mut Context,
+        btn: Button,
+        _instance_id: i32,
+        assets: &Assets,
+    ) {
+        match btn {
+            Button::DPadUp => self.handle_input(&OptionInputCommand::Up, assets),
+            Button::DPadDown => self.handle_input(&OptionInputCommand::Down, assets),
+            Button::A => self.handle_input(&OptionInputCommand::Select, assets),
+            Button::DPadLeft => self.change_setting(-1, assets),
+            Button::DPadRight => self.change_setting(1, assets),
+            Button::B => self.request_back = true,
+            _ => (),
+        }
+    }
+}
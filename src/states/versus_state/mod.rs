@@ -0,0 +1,697 @@
+mod discovery;
+mod lobby;
+mod net;
+mod net_setup;
+mod protocol;
+mod rollback;
+mod setup;
+mod spectate;
+mod stream;
+#[cfg(test)]
+pub(super) mod test_support;
+
+pub use self::lobby::LobbyState;
+pub use self::setup::VersusSetupState;
+
+use self::protocol::MatchSettings;
+use self::rollback::ConfirmedEvent;
+use self::spectate::{SpectatorServer, SPECTATOR_PORT};
+use self::stream::LockStream;
+
+use crate::states::menu_state::MenuState;
+use crate::states::play_state::bot::{BotDifficulty, BotPlayer};
+use crate::states::play_state::game_core::{GameCore, GameEvent};
+use crate::states::play_state::game_mode::{GameMode, ModeSettings};
+use crate::states::play_state::game_view::{play_event_sounds, GameView};
+use crate::states::play_state::handling::Handling;
+use crate::states::play_state::input_state::{InputAction, InputState};
+use crate::states::play_state::replay::FRAME_DURATION;
+use crate::states::play_state::ruleset::Ruleset;
+use crate::states::play_state::{button_to_action, random_play_track};
+use crate::states::shared::option::{Option, OptionInputCommand};
+use crate::states::{Assets, State, Transition};
+use crate::util::{play_click_sfx, DurationExt};
+use ggez::event::{Button, Keycode, Mod};
+use ggez::graphics::{Point2, Rect};
+use ggez::{graphics, Context, GameResult};
+use std::time::Duration;
+
+const PLAYERS: usize = 2;
+// each board is shrunk to fit into its half of the screen. The middle of the
+// well and HUD is drawn a little below the middle of the screen, leaving room
+// for the player names above
+const BOARD_SCALE: f32 = 0.8;
+const BOARD_CENTRE_X: f32 = 605.0;
+const BOARD_CENTRE_Y: f32 = 360.0;
+const BOARD_SCREEN_Y: f32 = 380.0;
+const NAME_Y: f32 = 15.0;
+// the distance from the bottom of the screen to the spectators text
+const SPECTATORS_TEXT_MARGIN: f32 = 35.0;
+
+const FIRST_OPTION_Y: f32 = 350.0;
+const OPTION_SPACING: f32 = 65.0;
+
+/// What the players can do from the overlay shown while the match is paused
+/// or between rounds.
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum VersusOption {
+    Resume,
+    NextRound,
+    Rematch,
+    Menu,
+}
+
+/// One side of a versus match.
+struct Player {
+    input: InputState,
+    core: GameCore,
+    view: GameView,
+    // plays this side instead of a person, if set
+    bot: std::option::Option<BotPlayer>,
+    // rounds won so far in the match
+    wins: u32,
+}
+
+impl Player {
+    fn new(
+        ctx: &mut Context,
+        assets: &Assets,
+        ruleset: Ruleset,
+        handling: Handling,
+        seed: u64,
+        bot: std::option::Option<BotDifficulty>,
+        wins: u32,
+    ) -> Self {
+        let core = GameCore::new(
+            GameMode::Versus,
+            ModeSettings::default(),
+            ruleset,
+            handling,
+            seed,
+        );
+        let view = GameView::new(ctx, assets, &core);
+        Player {
+            input: InputState::default(),
+            core,
+            view,
+            bot: bot.map(|difficulty| BotPlayer::heuristic(difficulty, rand::random())),
+            wins,
+        }
+    }
+}
+
+/// A best-of-N match between two players on the same machine, with their
+/// boards side by side. Both boards are dealt the same pieces, and lines
+/// cleared on one board send garbage to the other. A round ends when a player
+/// tops out.
+///
+/// Player 1 plays with the left side of the keyboard and player 2 with the
+/// arrow keys. Gamepads are given to the players in the order they are first
+/// used. Either side can be played by a bot instead, which ignores the keys
+/// and gamepads for that side.
+///
+/// If the players chose to, the match can be watched from other machines
+/// while it is played.
+pub struct VersusState {
+    players: Vec<Player>,
+    // the bot playing each side, or `None` for a person
+    bots: [std::option::Option<BotDifficulty>; PLAYERS],
+    best_of: u32,
+    ruleset: Ruleset,
+    handling: Handling,
+    // instance ids of the gamepads in use, in the order of the sides played
+    // by people
+    gamepads: Vec<i32>,
+    // time that has passed but has not been played as a frame yet
+    frame_timer: f64,
+    // frames played so far in the round
+    frame: u32,
+    // sends the match to anyone watching, if spectators can be taken
+    spectators: std::option::Option<SpectatorServer>,
+    stream: LockStream,
+    current_track_name: String,
+
+    name_texts: Vec<graphics::Text>,
+    // says which port spectators can watch on, while they can
+    spectators_text: std::option::Option<graphics::Text>,
+    // shown, along with the options, while paused and between rounds
+    overlay_text: std::option::Option<graphics::Text>,
+    option_kinds: Vec<VersusOption>,
+    options: Vec<Option>,
+    current_selection: usize,
+
+    request_pause: bool,
+    request_option: bool,
+}
+
+impl VersusState {
+    /// Creates a match of `best_of` rounds, played with `ruleset`. Each side
+    /// is played by the bot given for it in `bots`, or by a person. If
+    /// `spectators` is true, the match can be watched from other machines.
+    pub fn new(
+        ctx: &mut Context,
+        assets: &Assets,
+        best_of: u32,
+        ruleset: Ruleset,
+        bots: [std::option::Option<BotDifficulty>; PLAYERS],
+        spectators: bool,
+    ) -> GameResult<Self> {
+        let handling = Handling::load(ctx);
+        let seed = rand::random();
+        let players = bots
+            .iter()
+            .map(|bot| Player::new(ctx, assets, ruleset, handling, seed, *bot, 0))
+            .collect();
+        let spectators = if spectators {
+            // another match on this machine may already be taking spectators
+            SpectatorServer::bind(SPECTATOR_PORT).ok()
+        } else {
+            None
+        };
+        let spectators_text = build_spectators_text(ctx, assets, &spectators)?;
+
+        let mut state = VersusState {
+            players,
+            bots,
+            best_of,
+            ruleset,
+            handling,
+            gamepads: Vec::new(),
+            frame_timer: 0.0,
+            frame: 0,
+            spectators,
+            stream: LockStream::default(),
+            current_track_name: random_play_track(assets),
+
+            name_texts: Vec::new(),
+            spectators_text,
+            overlay_text: None,
+            option_kinds: Vec::new(),
+            options: Vec::new(),
+            current_selection: 0,
+
+            request_pause: false,
+            request_option: false,
+        };
+        state.build_name_texts(ctx, assets)?;
+        state.stream_round(seed);
+
+        Ok(state)
+    }
+
+    /// Returns the number of rounds a player must win to win the match.
+    fn rounds_to_win(&self) -> u32 {
+        self.best_of / 2 + 1
+    }
+
+    /// Starts a new round. Both players get a fresh board, dealt the same
+    /// pieces as each other.
+    fn start_round(&mut self, ctx: &mut Context, assets: &Assets) -> GameResult<()> {
+        let seed = rand::random();
+        for (player, bot) in self.players.iter_mut().zip(self.bots.iter()) {
+            let wins = player.wins;
+            *player = Player::new(ctx, assets, self.ruleset, self.handling, seed, *bot, wins);
+        }
+        self.frame_timer = 0.0;
+        self.stream_round(seed);
+        self.hide_overlay();
+        self.build_name_texts(ctx, assets)
+    }
+
+    /// Tells spectators about the round just started, dealt from `seed`.
+    fn stream_round(&mut self, seed: u64) {
+        self.frame = 0;
+        self.stream = LockStream::default();
+        let settings = MatchSettings {
+            best_of: self.best_of,
+            ruleset: self.ruleset,
+        };
+        let players: Vec<(String, u32)> = (0..PLAYERS)
+            .map(|i| (self.player_name(i), self.players[i].wins))
+            .collect();
+        if let Some(spectators) = &mut self.spectators {
+            spectators.start_round(settings, &players, seed);
+        }
+    }
+
+    /// Gives the round to the player still standing, if there is one, and
+    /// shows who won the round or the match.
+    fn end_round(&mut self, ctx: &mut Context, assets: &Assets) -> GameResult<()> {
+        let standing: Vec<usize> = (0..PLAYERS)
+            .filter(|i| !self.players[*i].core.is_game_over())
+            .collect();
+
+        // both players topping out on the same frame is a draw
+        let winner = match standing.as_slice() {
+            [winner] => Some(*winner),
+            _ => None,
+        };
+        if let Some(winner) = winner {
+            self.players[winner].wins += 1;
+        }
+        self.build_name_texts(ctx, assets)?;
+        assets.get_sfx("gameover_win")?.play()?;
+
+        match winner {
+            Some(winner) if self.players[winner].wins >= self.rounds_to_win() => {
+                let title = format!("PLAYER {} WINS!", winner + 1);
+                self.show_overlay(
+                    ctx,
+                    assets,
+                    &title,
+                    &[VersusOption::Rematch, VersusOption::Menu],
+                )
+            }
+            Some(winner) => {
+                let title = format!("ROUND TO PLAYER {}", winner + 1);
+                self.show_overlay(
+                    ctx,
+                    assets,
+                    &title,
+                    &[VersusOption::NextRound, VersusOption::Menu],
+                )
+            }
+            None => self.show_overlay(
+                ctx,
+                assets,
+                "DRAW",
+                &[VersusOption::NextRound, VersusOption::Menu],
+            ),
+        }
+    }
+
+    /// Creates the name shown above each board, along with the rounds that
+    /// player has won.
+    fn build_name_texts(&mut self, ctx: &mut Context, assets: &Assets) -> GameResult<()> {
+        let rounds_to_win = self.rounds_to_win();
+        self.name_texts.clear();
+        for (i, player) in self.players.iter().enumerate() {
+            let name = format!(
+                "{}   Wins: {}/{}",
+                self.player_name(i),
+                player.wins,
+                rounds_to_win
+            );
+            self.name_texts
+                .push(graphics::Text::new(ctx, &name, assets.get_font("normal")?)?);
+        }
+        Ok(())
+    }
+
+    fn player_name(&self, player: usize) -> String {
+        match self.bots[player] {
+            Some(difficulty) => format!("Player {} ({} Bot)", player + 1, difficulty.name()),
+            None => format!("Player {}", player + 1),
+        }
+    }
+
+    fn show_overlay(
+        &mut self,
+        ctx: &mut Context,
+        assets: &Assets,
+        title: &str,
+        kinds: &[VersusOption],
+    ) -> GameResult<()> {
+        self.overlay_text = Some(graphics::Text::new(ctx, title, assets.get_font("title")?)?);
+        self.option_kinds = kinds.to_vec();
+        self.current_selection = 0;
+        self.options = overlay_options(ctx, assets, kinds);
+        Ok(())
+    }
+
+    fn hide_overlay(&mut self) {
+        self.overlay_text = None;
+        self.option_kinds.clear();
+        self.options.clear();
+    }
+
+    /// Release every held input. Called when the match is paused, so that
+    /// nothing is still held once it resumes.
+    fn release_all(&mut self) {
+        for player in &mut self.players {
+            for action in InputAction::ALL.iter() {
+                player.input.set_action(*action, false);
+            }
+        }
+    }
+
+    /// Returns the player using the gamepad `instance_id`, handing the
+    /// gamepad to the next person playing without one if it has not been used
+    /// yet.
+    fn gamepad_player(&mut self, instance_id: i32) -> std::option::Option<usize> {
+        let people: Vec<usize> = (0..PLAYERS).filter(|i| self.bots[*i].is_none()).collect();
+        let index = match self.gamepads.iter().position(|id| *id == instance_id) {
+            Some(index) => index,
+            None if self.gamepads.len() < people.len() => {
+                self.gamepads.push(instance_id);
+                self.gamepads.len() - 1
+            }
+            None => return None,
+        };
+        Some(people[index])
+    }
+
+    /// Press or release the input tied to `action` for `player`, unless a bot
+    /// is playing that side.
+    fn set_action(&mut self, player: usize, action: InputAction, pressed: bool) {
+        if self.bots[player].is_none() {
+            self.players[player].input.set_action(action, pressed);
+        }
+    }
+
+    fn handle_input(&mut self, command: &OptionInputCommand, assets: &Assets) {
+        match command {
+            OptionInputCommand::Up => {
+                play_click_sfx(assets).expect("Could not play click sfx in versus state -> up");
+                if self.current_selection == 0 {
+                    self.current_selection = self.options.len() - 1;
+                } else {
+                    self.current_selection -= 1;
+                }
+            }
+            OptionInputCommand::Down => {
+                play_click_sfx(assets).expect("Could not play click sfx in versus state -> down");
+                if self.current_selection >= self.options.len() - 1 {
+                    self.current_selection = 0;
+                } else {
+                    self.current_selection += 1;
+                }
+            }
+            OptionInputCommand::Select => self.request_option = true,
+        }
+    }
+}
+
+/// Creates the options shown on the overlay for each of `kinds`.
+fn overlay_options(ctx: &mut Context, assets: &Assets, kinds: &[VersusOption]) -> Vec<Option> {
+    let coords = graphics::get_screen_coordinates(ctx);
+    let mut options = Vec::new();
+    for (i, kind) in kinds.iter().enumerate() {
+        let name = match kind {
+            VersusOption::Resume => "Resume",
+            VersusOption::NextRound => "Next Round",
+            VersusOption::Rematch => "Rematch",
+            VersusOption::Menu => "Quit to Menu",
+        };
+        options.push(Option::new(
+            ctx,
+            assets,
+            name,
+            Point2::new(coords.w / 2.0, FIRST_OPTION_Y + OPTION_SPACING * i as f32),
+        ));
+    }
+    options
+}
+
+/// Returns the player a key belongs to on the shared keyboard, and the action
+/// tied to it. Player 1 uses the left side of the keyboard and player 2 the
+/// arrow keys.
+fn keycode_to_player_action(keycode: Keycode) -> std::option::Option<(usize, InputAction)> {
+    match keycode {
+        Keycode::A => Some((0, InputAction::Left)),
+        Keycode::D => Some((0, InputAction::Right)),
+        Keycode::W => Some((0, InputAction::HardDrop)),
+        Keycode::S => Some((0, InputAction::SoftDrop)),
+        Keycode::Q => Some((0, InputAction::RotateCounterclockwise)),
+        Keycode::E => Some((0, InputAction::RotateClockwise)),
+        Keycode::LShift => Some((0, InputAction::Hold)),
+        Keycode::Left => Some((1, InputAction::Left)),
+        Keycode::Right => Some((1, InputAction::Right)),
+        Keycode::Up => Some((1, InputAction::HardDrop)),
+        Keycode::Down => Some((1, InputAction::SoftDrop)),
+        Keycode::Period => Some((1, InputAction::RotateCounterclockwise)),
+        Keycode::Slash => Some((1, InputAction::RotateClockwise)),
+        Keycode::RShift => Some((1, InputAction::Hold)),
+        _ => None,
+    }
+}
+
+/// Returns the screen coordinates that draw a whole game shrunk into the half
+/// of `screen` belonging to player `index`.
+fn board_coordinates(screen: Rect, index: usize) -> Rect {
+    let half_width = screen.w / PLAYERS as f32;
+    let centre_x = screen.x + half_width * (index as f32 + 0.5);
+    Rect::new(
+        BOARD_CENTRE_X - centre_x / BOARD_SCALE,
+        BOARD_CENTRE_Y - (screen.y + BOARD_SCREEN_Y) / BOARD_SCALE,
+        screen.w / BOARD_SCALE,
+        screen.h / BOARD_SCALE,
+    )
+}
+
+/// Returns the text telling the players that the match is open to spectators
+/// and on which port, if `spectators` is taking them.
+fn build_spectators_text(
+    ctx: &mut Context,
+    assets: &Assets,
+    spectators: &std::option::Option<SpectatorServer>,
+) -> GameResult<std::option::Option<graphics::Text>> {
+    match spectators {
+        Some(_) => {
+            let text = format!("Open to spectators on port {}", SPECTATOR_PORT);
+            Ok(Some(graphics::Text::new(
+                ctx,
+                &text,
+                assets.get_font("ui")?,
+            )?))
+        }
+        None => Ok(None),
+    }
+}
+
+/// Draws each player's board, given as the view and core of each side, in
+/// their half of the screen with their name above it, and the overlay on top
+/// of everything if there is one.
+fn draw_match(
+    ctx: &mut Context,
+    assets: &Assets,
+    boards: Vec<(&mut GameView, &GameCore)>,
+    name_texts: &[graphics::Text],
+    spectators_text: std::option::Option<&graphics::Text>,
+    overlay_text: std::option::Option<&graphics::Text>,
+    options: &[Option],
+) -> GameResult<()> {
+    let coords = graphics::get_screen_coordinates(ctx);
+    graphics::draw(ctx, assets.get_image("game_bg")?, Point2::origin(), 0.0)?;
+
+    for (i, (view, core)) in boards.into_iter().enumerate() {
+        graphics::set_screen_coordinates(ctx, board_coordinates(coords, i))?;
+        view.draw_game(ctx, assets, core)?;
+    }
+    graphics::set_screen_coordinates(ctx, coords)?;
+
+    graphics::set_color(ctx, graphics::Color::new(1.0, 1.0, 1.0, 1.0))?;
+    let half_width = coords.w / PLAYERS as f32;
+    for (i, name_text) in name_texts.iter().enumerate() {
+        let centre_x = half_width * (i as f32 + 0.5);
+        let name_dest = Point2::new(centre_x - (name_text.width() / 2) as f32, NAME_Y);
+        graphics::draw(ctx, name_text, name_dest, 0.0)?;
+    }
+
+    if let Some(spectators_text) = spectators_text {
+        let spectators_dest = Point2::new(
+            coords.w / 2.0 - (spectators_text.width() / 2) as f32,
+            coords.h - SPECTATORS_TEXT_MARGIN,
+        );
+        graphics::draw(ctx, spectators_text, spectators_dest, 0.0)?;
+    }
+
+    if let Some(overlay_text) = overlay_text {
+        let overlay_dest = Point2::new(coords.w / 2.0 - (overlay_text.width() / 2) as f32, 200.0);
+
+        graphics::set_color(ctx, graphics::Color::new(0.0, 0.0, 0.0, 0.75))?;
+        graphics::rectangle(ctx, graphics::DrawMode::Fill, coords)?;
+        graphics::set_color(ctx, graphics::Color::new(1.0, 1.0, 1.0, 1.0))?;
+        graphics::draw(ctx, overlay_text, overlay_dest, 0.0)?;
+
+        for option in options {
+            option.draw(ctx)?;
+        }
+    }
+
+    Ok(())
+}
+
+impl State for VersusState {
+    fn update(
+        &mut self,
+        ctx: &mut Context,
+        assets: &Assets,
+        dt: Duration,
+    ) -> GameResult<Transition> {
+        // currently necessary to keep audio looping
+        let current_song = assets.get_music(&self.current_track_name)?;
+        if current_song.paused() {
+            current_song.resume();
+        } else {
+            current_song.play()?;
+        }
+
+        if self.request_pause {
+            self.request_pause = false;
+            self.release_all();
+            self.show_overlay(
+                ctx,
+                assets,
+                "PAUSED",
+                &[VersusOption::Resume, VersusOption::Menu],
+            )?;
+        } else if self.request_option {
+            self.request_option = false;
+            match self.option_kinds[self.current_selection] {
+                VersusOption::Resume => self.hide_overlay(),
+                VersusOption::NextRound => self.start_round(ctx, assets)?,
+                VersusOption::Rematch => {
+                    for player in &mut self.players {
+                        player.wins = 0;
+                    }
+                    self.start_round(ctx, assets)?;
+                }
+                VersusOption::Menu => {
+                    assets.get_music(&self.current_track_name)?.pause();
+                    return Ok(Transition::Swap(Box::new(MenuState::new(ctx, assets)?)));
+                }
+            }
+        }
+
+        if let Some(spectators) = &mut self.spectators {
+            spectators.update();
+        }
+
+        if self.overlay_text.is_none() {
+            // play every whole frame that has passed since the last update
+            self.frame_timer += dt.as_subsec_millis();
+            while self.frame_timer >= FRAME_DURATION.as_subsec_millis() {
+                self.frame_timer -= FRAME_DURATION.as_subsec_millis();
+
+                let mut sent = [0; PLAYERS];
+                let mut confirmed = Vec::new();
+                for (i, player) in self.players.iter_mut().enumerate() {
+                    if let Some(bot) = &mut player.bot {
+                        for (action, pressed) in bot.update(&player.core) {
+                            player.input.set_action(action, pressed);
+                        }
+                    }
+                    let events = player.core.step(&mut player.input, FRAME_DURATION);
+                    for event in &events {
+                        if let GameEvent::GarbageSent(rows) = event {
+                            sent[i] += rows;
+                        }
+                        // nothing is ever taken back in a local match
+                        confirmed.push(ConfirmedEvent {
+                            frame: self.frame,
+                            player: i,
+                            event: *event,
+                            lock: match event {
+                                GameEvent::PieceLocked => player.core.last_lock(),
+                                _ => None,
+                            },
+                        });
+                    }
+                    play_event_sounds(&events, assets)?;
+                }
+                for (i, rows) in sent.iter().enumerate() {
+                    self.players[(i + 1) % PLAYERS].core.receive_garbage(*rows);
+                }
+                let messages = self.stream.messages(&confirmed);
+                if let Some(spectators) = &mut self.spectators {
+                    spectators.send_boards(&messages);
+                }
+                self.frame += 1;
+
+                if self.players.iter().any(|player| player.core.is_game_over()) {
+                    self.end_round(ctx, assets)?;
+                    break;
+                }
+            }
+        }
+
+        for player in &mut self.players {
+            player.view.update(ctx, assets, &player.core);
+        }
+        for (i, option) in self.options.iter_mut().enumerate() {
+            option.update(i == self.current_selection)?;
+        }
+
+        Ok(Transition::None)
+    }
+
+    fn draw(&mut self, ctx: &mut Context, assets: &Assets) -> GameResult<()> {
+        let boards = self
+            .players
+            .iter_mut()
+            .map(|player| (&mut player.view, &player.core))
+            .collect();
+        draw_match(
+            ctx,
+            assets,
+            boards,
+            &self.name_texts,
+            self.spectators_text.as_ref(),
+            self.overlay_text.as_ref(),
+            &self.options,
+        )
+    }
+
+    fn key_down_event(
+        &mut self,
+        _ctx: &mut Context,
+        keycode: Keycode,
+        _keymod: Mod,
+        repeat: bool,
+        assets: &Assets,
+    ) {
+        if self.overlay_text.is_some() {
+            if repeat {
+                return;
+            }
+            match keycode {
+                Keycode::Up | Keycode::W => self.handle_input(&OptionInputCommand::Up, assets),
+                Keycode::Down | Keycode::S => self.handle_input(&OptionInputCommand::Down, assets),
+                Keycode::Return => self.handle_input(&OptionInputCommand::Select, assets),
+                _ => (),
+            }
+        } else if keycode == Keycode::Escape {
+            self.request_pause = true;
+        } else if let Some((player, action)) = keycode_to_player_action(keycode) {
+            self.set_action(player, action, true);
+        }
+    }
+
+    fn key_up_event(&mut self, _ctx: &mut Context, keycode: Keycode, _keymod: Mod, _repeat: bool) {
+        if let Some((player, action)) = keycode_to_player_action(keycode) {
+            self.set_action(player, action, false);
+        }
+    }
+
+    fn controller_button_down_event(
+        &mut self,
+        _ctx: &mut Context,
+        btn: Button,
+        instance_id: i32,
+        assets: &Assets,
+    ) {
+        if self.overlay_text.is_some() {
+            match btn {
+                Button::DPadUp => self.handle_input(&OptionInputCommand::Up, assets),
+                Button::DPadDown => self.handle_input(&OptionInputCommand::Down, assets),
+                Button::A => self.handle_input(&OptionInputCommand::Select, assets),
+                _ => (),
+            }
+        } else if btn == Button::Start {
+            self.request_pause = true;
+        } else if let Some(player) = self.gamepad_player(instance_id) {
+            if let Some(action) = button_to_action(btn) {
+                self.set_action(player, action, true);
+            }
+        }
+    }
+
+    fn controller_button_up_event(&mut self, _ctx: &mut Context, btn: Button, instance_id: i32) {
+        if let (Some(player), Some(action)) =
+            (self.gamepad_player(instance_id), button_to_action(btn))
+        {
+            self.set_action(player, action, false);
+        }
+    }
+}
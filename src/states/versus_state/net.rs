@@ -0,0 +1,589 @@
+use super::protocol::{Connection, MatchSettings, Message};
+use super::rollback::{InputFrame, RollbackSession, VersusGame};
+use super::spectate::{SpectatorServer, SPECTATOR_PORT};
+use super::stream::{LockStream, MirrorBoard};
+use super::{build_spectators_text, draw_match, overlay_options, VersusOption, PLAYERS};
+use crate::states::menu_state::MenuState;
+use crate::states::play_state::bot::{BotDifficulty, BotPlayer};
+use crate::states::play_state::game_view::{play_event_sounds, GameView};
+use crate::states::play_state::handling::Handling;
+use crate::states::play_state::input_state::InputAction;
+use crate::states::play_state::replay::FRAME_DURATION;
+use crate::states::play_state::{button_to_action, keycode_to_action, random_play_track};
+use crate::states::shared::option::{Option, OptionInputCommand};
+use crate::states::{Assets, State, Transition};
+use crate::util::{play_click_sfx, DurationExt};
+use ggez::event::{Button, Keycode, Mod};
+use ggez::{graphics, Context, GameResult};
+use std::time::Duration;
+
+// the board played on this machine is on the left, and the opponent's board
+// on the right
+const LOCAL: usize = 0;
+const REMOTE: usize = 1;
+
+/// The other machine in a network match.
+pub struct Peer {
+    pub connection: Connection,
+    /// The handling the opponent plays with.
+    pub handling: Handling,
+}
+
+/// A best-of-N match against a player on another machine. Both machines play
+/// both boards from the inputs of both players, which are sent over the
+/// `Connection` every frame. The opponent's inputs are guessed until they
+/// arrive, and the game is rolled back and played again if a guess was wrong,
+/// so neither player has to wait on the other. The host starts each round,
+/// and the other side follows.
+///
+/// Each side also sends the pieces that lock on its own board, which are
+/// mirrored onto a third board. If the mirror ever disagrees with the
+/// opponent's board as played on this machine, the match is abandoned.
+///
+/// The host also sends both boards to anyone watching the match, and shows
+/// the port they can watch on.
+///
+/// The match cannot be paused, as the opponent would keep playing. Escape
+/// brings up a menu for leaving the match while the game carries on.
+pub struct NetVersusState {
+    connection: Connection,
+    is_host: bool,
+    session: RollbackSession,
+    views: Vec<GameView>,
+    // the opponent's board as they have told it, which may be some pieces
+    // behind the board played on this machine
+    mirror: MirrorBoard,
+    stream: LockStream,
+    // sends the match to anyone watching, on the host only
+    spectators: std::option::Option<SpectatorServer>,
+    // plays this machine's board instead of a person, if set
+    bot: std::option::Option<BotDifficulty>,
+    bot_player: std::option::Option<BotPlayer>,
+    // the inputs held on this machine
+    held: InputFrame,
+    settings: MatchSettings,
+    // the handling each player plays with, in the order of the boards in the
+    // game
+    handling: [Handling; PLAYERS],
+    // rounds won so far in the match by each side
+    wins: [u32; PLAYERS],
+    // rows in each attack this machine's board sent this round
+    garbage_sent: Vec<u32>,
+    // set on the host from starting a round until the opponent has started it
+    // too. Messages sent before then belong to the round before
+    awaiting_round: bool,
+    round_over: bool,
+    opponent_left: bool,
+    // time that has passed but has not been played as a frame yet
+    frame_timer: f64,
+    current_track_name: String,
+
+    name_texts: Vec<graphics::Text>,
+    // says which port spectators can watch on, on the host only
+    spectators_text: std::option::Option<graphics::Text>,
+    // shown, along with the options, while the menu is open and between rounds
+    overlay_text: std::option::Option<graphics::Text>,
+    option_kinds: Vec<VersusOption>,
+    options: Vec<Option>,
+    current_selection: usize,
+
+    request_menu: bool,
+    request_option: bool,
+}
+
+impl NetVersusState {
+    /// Creates a match against `peer`, played with `settings`, and starts the
+    /// first round with `seed`. This machine's board is played by `bot`, or
+    /// by a person.
+    pub fn new(
+        ctx: &mut Context,
+        assets: &Assets,
+        peer: Peer,
+        is_host: bool,
+        settings: MatchSettings,
+        seed: u64,
+        bot: std::option::Option<BotDifficulty>,
+    ) -> GameResult<Self> {
+        // the host plays the first board of the game, on both machines
+        let local_handling = Handling::load(ctx);
+        let handling = if is_host {
+            [local_handling, peer.handling]
+        } else {
+            [peer.handling, local_handling]
+        };
+        let game = VersusGame::new(settings.ruleset, handling, seed);
+        let mirror = MirrorBoard::new(game.core(0).clone());
+        let spectators = if is_host {
+            SpectatorServer::bind(SPECTATOR_PORT).ok()
+        } else {
+            None
+        };
+        let spectators_text = build_spectators_text(ctx, assets, &spectators)?;
+        let mut state = NetVersusState {
+            connection: peer.connection,
+            is_host,
+            session: RollbackSession::new(game, 0),
+            views: Vec::new(),
+            mirror,
+            stream: LockStream::default(),
+            spectators,
+            bot,
+            bot_player: None,
+            held: InputFrame::default(),
+            settings,
+            handling,
+            wins: [0; PLAYERS],
+            garbage_sent: Vec::new(),
+            awaiting_round: false,
+            round_over: false,
+            opponent_left: false,
+            frame_timer: 0.0,
+            current_track_name: random_play_track(assets),
+
+            name_texts: Vec::new(),
+            spectators_text,
+            overlay_text: None,
+            option_kinds: Vec::new(),
+            options: Vec::new(),
+            current_selection: 0,
+
+            request_menu: false,
+            request_option: false,
+        };
+        state.start_round(ctx, assets, seed)?;
+
+        Ok(state)
+    }
+
+    /// Returns the index in the game of the board shown on `side`.
+    fn board(&self, side: usize) -> usize {
+        if self.is_host {
+            side
+        } else {
+            PLAYERS - 1 - side
+        }
+    }
+
+    /// Returns the number of rounds a player must win to win the match.
+    fn rounds_to_win(&self) -> u32 {
+        self.settings.best_of / 2 + 1
+    }
+
+    fn is_match_over(&self) -> bool {
+        self.wins.iter().any(|wins| *wins >= self.rounds_to_win())
+    }
+
+    /// Starts a new round with both boards dealt from `seed`, and tells the
+    /// opponent. For the host this starts the round on both machines, and for
+    /// the other side it answers the host.
+    fn start_round(&mut self, ctx: &mut Context, assets: &Assets, seed: u64) -> GameResult<()> {
+        let game = VersusGame::new(self.settings.ruleset, self.handling, seed);
+        self.session = RollbackSession::new(game, self.board(LOCAL));
+        self.mirror = MirrorBoard::new(self.session.game().core(self.board(REMOTE)).clone());
+        self.stream = LockStream::default();
+        self.views = (0..PLAYERS)
+            .map(|side| {
+                let core = self.session.game().core(self.board(side));
+                GameView::new(ctx, assets, core)
+            })
+            .collect();
+        self.bot_player = self
+            .bot
+            .map(|difficulty| BotPlayer::heuristic(difficulty, rand::random()));
+        self.held = InputFrame::default();
+
+        self.garbage_sent.clear();
+        self.awaiting_round = self.is_host;
+        self.round_over = false;
+        self.frame_timer = 0.0;
+        self.send(&Message::Round(seed));
+        if let Some(spectators) = &mut self.spectators {
+            // the host's board comes first
+            let players = [
+                ("Host".to_string(), self.wins[LOCAL]),
+                ("Guest".to_string(), self.wins[REMOTE]),
+            ];
+            spectators.start_round(self.settings, &players, seed);
+        }
+        self.hide_overlay();
+        self.build_name_texts(ctx, assets)
+    }
+
+    /// Gives the round to the player still standing, if there is one, and
+    /// shows who won the round or the match. Both machines see the same
+    /// outcome, as it is taken from the confirmed game.
+    fn end_round(&mut self, ctx: &mut Context, assets: &Assets) -> GameResult<()> {
+        self.round_over = true;
+        let game = self.session.confirmed_game();
+        let winner = match (
+            game.core(self.board(LOCAL)).is_game_over(),
+            game.core(self.board(REMOTE)).is_game_over(),
+        ) {
+            (true, false) => Some(REMOTE),
+            (false, true) => Some(LOCAL),
+            _ => None,
+        };
+        if let Some(winner) = winner {
+            self.wins[winner] += 1;
+        }
+        assets.get_sfx("gameover_win")?.play()?;
+        self.show_result(ctx, assets, winner)
+    }
+
+    /// Shows who won the round or the match. Only the host can start the next
+    /// round.
+    fn show_result(
+        &mut self,
+        ctx: &mut Context,
+        assets: &Assets,
+        winner: std::option::Option<usize>,
+    ) -> GameResult<()> {
+        self.build_name_texts(ctx, assets)?;
+        let match_over = self.is_match_over();
+        let title = match winner {
+            Some(LOCAL) if match_over => "YOU WIN!",
+            Some(_) if match_over => "OPPONENT WINS!",
+            Some(LOCAL) => "ROUND TO YOU",
+            Some(_) => "ROUND TO OPPONENT",
+            None => "DRAW",
+        };
+        let kinds = match (self.is_host, match_over) {
+            (true, true) => vec![VersusOption::Rematch, VersusOption::Menu],
+            (true, false) => vec![VersusOption::NextRound, VersusOption::Menu],
+            (false, _) => vec![VersusOption::Menu],
+        };
+        self.show_overlay(ctx, assets, title, &kinds)
+    }
+
+    /// Ends the match for good, showing `title` with the option to go back to
+    /// the menu.
+    fn abandon(&mut self, ctx: &mut Context, assets: &Assets, title: &str) -> GameResult<()> {
+        self.opponent_left = true;
+        self.round_over = true;
+        self.show_overlay(ctx, assets, title, &[VersusOption::Menu])
+    }
+
+    /// Creates the name shown above each board, along with the rounds that
+    /// player has won.
+    fn build_name_texts(&mut self, ctx: &mut Context, assets: &Assets) -> GameResult<()> {
+        let rounds_to_win = self.rounds_to_win();
+        self.name_texts.clear();
+        for (side, wins) in self.wins.iter().enumerate() {
+            let name = match (side, self.bot) {
+                (LOCAL, Some(difficulty)) => format!("You ({} Bot)", difficulty.name()),
+                (LOCAL, None) => "You".to_string(),
+                _ => "Opponent".to_string(),
+            };
+            let name = format!("{}   Wins: {}/{}", name, wins, rounds_to_win);
+            self.name_texts
+                .push(graphics::Text::new(ctx, &name, assets.get_font("normal")?)?);
+        }
+        Ok(())
+    }
+
+    fn show_overlay(
+        &mut self,
+        ctx: &mut Context,
+        assets: &Assets,
+        title: &str,
+        kinds: &[VersusOption],
+    ) -> GameResult<()> {
+        self.overlay_text = Some(graphics::Text::new(ctx, title, assets.get_font("title")?)?);
+        self.option_kinds = kinds.to_vec();
+        self.current_selection = 0;
+        self.options = overlay_options(ctx, assets, kinds);
+        Ok(())
+    }
+
+    fn hide_overlay(&mut self) {
+        self.overlay_text = None;
+        self.option_kinds.clear();
+        self.options.clear();
+    }
+
+    /// Sends `message` to the opponent. A connection that has failed is
+    /// noticed the next time messages are received.
+    fn send(&mut self, message: &Message) {
+        let _ = self.connection.send(message);
+    }
+
+    /// Handles every message the opponent has sent since the last update.
+    fn receive_messages(&mut self, ctx: &mut Context, assets: &Assets) -> GameResult<()> {
+        loop {
+            let message = match self.connection.receive() {
+                Ok(Some(message)) => message,
+                Ok(None) => break,
+                Err(_) => Message::Bye,
+            };
+            let in_round = !self.round_over && !self.awaiting_round;
+            match message {
+                // the opponent has started the round the host started last
+                Message::Round(seed) if self.is_host => {
+                    self.awaiting_round &= seed != self.session.game().core(0).seed();
+                }
+                Message::Round(seed) => {
+                    if self.is_match_over() {
+                        self.wins = [0; PLAYERS];
+                    }
+                    self.start_round(ctx, assets, seed)?;
+                }
+                Message::Inputs(packet) if in_round => {
+                    self.session.receive(&packet);
+                    self.handle_confirmed_events(ctx, assets)?;
+                }
+                Message::Lock {
+                    piece,
+                    garbage_taken,
+                } if in_round => {
+                    self.mirror.lock(&piece, garbage_taken, &self.garbage_sent);
+                    self.check_mirror(ctx, assets)?;
+                }
+                Message::GameOver if in_round => self.mirror.concede(),
+                Message::Bye => {
+                    self.abandon(ctx, assets, "OPPONENT LEFT")?;
+                    break;
+                }
+                // the garbage reaching this machine's board is played out
+                // from the opponent's inputs
+                _ => (),
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Tells the opponent about everything that happened on this machine's
+    /// board on the frames confirmed since the last call, and spectators
+    /// about both boards. Ends the round once the confirmed game is over.
+    fn handle_confirmed_events(&mut self, ctx: &mut Context, assets: &Assets) -> GameResult<()> {
+        let events = self.session.take_confirmed_events();
+        let messages = self.stream.messages(&events);
+        if let Some(spectators) = &mut self.spectators {
+            spectators.send_boards(&messages);
+        }
+        for (board, message) in messages {
+            if board == self.board(LOCAL) {
+                if let Message::Garbage(rows) = message {
+                    self.garbage_sent.push(rows);
+                }
+                self.send(&message);
+            }
+        }
+
+        self.check_mirror(ctx, assets)?;
+        if !self.round_over && self.session.confirmed_game().is_over() {
+            self.end_round(ctx, assets)?;
+        }
+        Ok(())
+    }
+
+    /// Abandons the match if the mirror has locked as many pieces as the
+    /// opponent's confirmed board, yet the two wells differ.
+    fn check_mirror(&mut self, ctx: &mut Context, assets: &Assets) -> GameResult<()> {
+        let remote = self.session.confirmed_game().core(self.board(REMOTE));
+        let mirror = self.mirror.core();
+        if !self.opponent_left
+            && mirror.pieces_locked() == remote.pieces_locked()
+            && mirror.well() != remote.well()
+        {
+            self.send(&Message::Bye);
+            self.abandon(ctx, assets, "OUT OF SYNC")?;
+        }
+        Ok(())
+    }
+
+    /// Press or release the input tied to `action`, unless a bot is playing
+    /// or the overlay is open.
+    fn set_action(&mut self, action: InputAction, pressed: bool) {
+        if self.bot.is_none() && (self.overlay_text.is_none() || !pressed) {
+            self.held.set(action, pressed);
+        }
+    }
+
+    fn handle_input(&mut self, command: &OptionInputCommand, assets: &Assets) {
+        match command {
+            OptionInputCommand::Up => {
+                play_click_sfx(assets).expect("Could not play click sfx in net versus -> up");
+                if self.current_selection == 0 {
+                    self.current_selection = self.options.len() - 1;
+                } else {
+                    self.current_selection -= 1;
+                }
+            }
+            OptionInputCommand::Down => {
+                play_click_sfx(assets).expect("Could not play click sfx in net versus -> down");
+                if self.current_selection >= self.options.len() - 1 {
+                    self.current_selection = 0;
+                } else {
+                    self.current_selection += 1;
+                }
+            }
+            OptionInputCommand::Select => self.request_option = true,
+        }
+    }
+}
+
+impl State for NetVersusState {
+    fn update(
+        &mut self,
+        ctx: &mut Context,
+        assets: &Assets,
+        dt: Duration,
+    ) -> GameResult<Transition> {
+        // currently necessary to keep audio looping
+        let current_song = assets.get_music(&self.current_track_name)?;
+        if current_song.paused() {
+            current_song.resume();
+        } else {
+            current_song.play()?;
+        }
+
+        if self.request_menu {
+            self.request_menu = false;
+            self.held = InputFrame::default();
+            self.show_overlay(
+                ctx,
+                assets,
+                "LEAVE MATCH?",
+                &[VersusOption::Resume, VersusOption::Menu],
+            )?;
+        } else if self.request_option {
+            self.request_option = false;
+            match self.option_kinds[self.current_selection] {
+                VersusOption::Resume => self.hide_overlay(),
+                VersusOption::NextRound => self.start_round(ctx, assets, rand::random())?,
+                VersusOption::Rematch => {
+                    self.wins = [0; PLAYERS];
+                    self.start_round(ctx, assets, rand::random())?;
+                }
+                VersusOption::Menu => {
+                    self.send(&Message::Bye);
+                    assets.get_music(&self.current_track_name)?.pause();
+                    return Ok(Transition::Swap(Box::new(MenuState::new(ctx, assets)?)));
+                }
+            }
+        }
+
+        if !self.opponent_left {
+            self.receive_messages(ctx, assets)?;
+        }
+        if let Some(spectators) = &mut self.spectators {
+            spectators.update();
+        }
+
+        if !self.round_over {
+            // play every whole frame that has passed since the last update,
+            // unless the opponent's inputs have fallen too far behind
+            self.frame_timer += dt.as_subsec_millis();
+            while self.frame_timer >= FRAME_DURATION.as_subsec_millis()
+                && self.session.can_advance()
+            {
+                self.frame_timer -= FRAME_DURATION.as_subsec_millis();
+
+                let local = self.board(LOCAL);
+                if let Some(bot) = &mut self.bot_player {
+                    for (action, pressed) in bot.update(self.session.game().core(local)) {
+                        self.held.set(action, pressed);
+                    }
+                }
+                for events in self.session.advance(self.held) {
+                    play_event_sounds(&events, assets)?;
+                }
+            }
+            // time spent waiting on the opponent is not made up for later
+            self.frame_timer = self.frame_timer.min(FRAME_DURATION.as_subsec_millis());
+
+            let packet = self.session.packet();
+            self.send(&Message::Inputs(packet));
+            self.handle_confirmed_events(ctx, assets)?;
+        }
+
+        for side in 0..PLAYERS {
+            let core = self.session.game().core(self.board(side));
+            self.views[side].update(ctx, assets, core);
+        }
+        for (i, option) in self.options.iter_mut().enumerate() {
+            option.update(i == self.current_selection)?;
+        }
+
+        Ok(Transition::None)
+    }
+
+    fn draw(&mut self, ctx: &mut Context, assets: &Assets) -> GameResult<()> {
+        let indices: Vec<usize> = (0..PLAYERS).map(|side| self.board(side)).collect();
+        let game = self.session.game();
+        let boards = self
+            .views
+            .iter_mut()
+            .zip(indices)
+            .map(|(view, board)| (view, game.core(board)))
+            .collect();
+        draw_match(
+            ctx,
+            assets,
+            boards,
+            &self.name_texts,
+            self.spectators_text.as_ref(),
+            self.overlay_text.as_ref(),
+            &self.options,
+        )
+    }
+
+    fn key_down_event(
+        &mut self,
+        _ctx: &mut Context,
+        keycode: Keycode,
+        _keymod: Mod,
+        repeat: bool,
+        assets: &Assets,
+    ) {
+        if self.overlay_text.is_some() {
+            if repeat {
+                return;
+            }
+            match keycode {
+                Keycode::Up => self.handle_input(&OptionInputCommand::Up, assets),
+                Keycode::Down => self.handle_input(&OptionInputCommand::Down, assets),
+                Keycode::Return => self.handle_input(&OptionInputCommand::Select, assets),
+                _ => (),
+            }
+        } else if keycode == Keycode::Escape {
+            self.request_menu = true;
+        } else if let Some(action) = keycode_to_action(keycode) {
+            self.set_action(action, true);
+        }
+    }
+
+    fn key_up_event(&mut self, _ctx: &mut Context, keycode: Keycode, _keymod: Mod, _repeat: bool) {
+        if let Some(action) = keycode_to_action(keycode) {
+            self.set_action(action, false);
+        }
+    }
+
+    fn controller_button_down_event(
+        &mut self,
+        _ctx: &mut Context,
+        btn: Button,
+        _instance_id: i32,
+        assets: &Assets,
+    ) {
+        if self.overlay_text.is_some() {
+            match btn {
+                Button::DPadUp => self.handle_input(&OptionInputCommand::Up, assets),
+                Button::DPadDown => self.handle_input(&OptionInputCommand::Down, assets),
+                Button::A => self.handle_input(&OptionInputCommand::Select, assets),
+                _ => (),
+            }
+        } else if btn == Button::Start {
+            self.request_menu = true;
+        } else if let Some(action) = button_to_action(btn) {
+            self.set_action(action, true);
+        }
+    }
+
+    fn controller_button_up_event(&mut self, _ctx: &mut Context, btn: Button, _instance_id: i32) {
+        if let Some(action) = button_to_action(btn) {
+            self.set_action(action, false);
+        }
+    }
+}
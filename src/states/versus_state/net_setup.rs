@@ -0,0 +1,363 @@
+use super::net::{NetVersusState, Peer};
+use super::protocol::{Connection, MatchSettings, Message, DEFAULT_PORT, PROTOCOL_VERSION};
+use super::spectate::{SpectateState, SPECTATOR_PORT};
+use crate::states::play_state::bot::BotDifficulty;
+use crate::states::play_state::handling::Handling;
+use crate::states::{Assets, State, Transition};
+use crate::util::play_click_sfx;
+use ggez::event::{Button, Keycode, Mod};
+use ggez::graphics::Point2;
+use ggez::{graphics, Context, GameResult};
+use std::io::ErrorKind;
+use std::net::TcpListener;
+use std::time::Duration;
+
+const DEFAULT_ADDRESS: &str = "127.0.0.1";
+const MAX_ADDRESS_LEN: usize = 64;
+
+/// How far along connecting to the other machine is.
+enum Phase {
+    /// Typing in the address of the host.
+    Address,
+    /// Waiting for someone to join.
+    Listening(TcpListener),
+    /// Connected, and saying hello.
+    Handshake(Connection),
+    /// Something went wrong, as described by the status text.
+    Failed,
+}
+
+/// What the handshake has led to so far.
+enum Handshake {
+    Waiting,
+    Failed(&'static str),
+    Ready(MatchSettings, Handling, u64),
+}
+
+/// Connects to another machine for a network match, either by waiting for
+/// someone to join or by joining a host at a typed in address. Once both sides
+/// have said hello and sent the handling their player plays with, the host
+/// sends the match settings and the seed for the first round, and the match
+/// begins. It can also connect to a match being played on another machine,
+/// to watch it. Drawn on top of the versus setup that pushed it, and pops
+/// itself when closed.
+pub struct NetSetupState {
+    phase: Phase,
+    // only known by the joining side once the host has sent them
+    settings: std::option::Option<MatchSettings>,
+    is_host: bool,
+    // connects to a match to watch it, rather than to play
+    spectate: bool,
+    hello_received: bool,
+    opponent_handling: std::option::Option<Handling>,
+    // plays this machine's board instead of a person, if set
+    bot: std::option::Option<BotDifficulty>,
+    address: String,
+
+    title_text: graphics::Text,
+    status_text: graphics::Text,
+    help_text: graphics::Text,
+
+    request_connect: bool,
+    request_back: bool,
+    request_refresh: bool,
+}
+
+impl NetSetupState {
+    /// Creates a screen that waits for someone to join a match played with
+    /// `settings`.
+    pub fn host(
+        ctx: &mut Context,
+        assets: &Assets,
+        settings: MatchSettings,
+        bot: std::option::Option<BotDifficulty>,
+    ) -> GameResult<Self> {
+        let (phase, status) = match TcpListener::bind(("0.0.0.0", DEFAULT_PORT))
+            .and_then(|listener| listener.set_nonblocking(true).map(|_| listener))
+        {
+            Ok(listener) => (
+                Phase::Listening(listener),
+                format!("Waiting for an opponent on port {}...", DEFAULT_PORT),
+            ),
+            Err(e) => (
+                Phase::Failed,
+                format!("Could not listen on port {}: {}", DEFAULT_PORT, e),
+            ),
+        };
+        NetSetupState::new(ctx, assets, "Host", phase, Some(settings), bot, &status)
+    }
+
+    /// Creates a screen for typing in the address of a host to join.
+    pub fn join(
+        ctx: &mut Context,
+        assets: &Assets,
+        bot: std::option::Option<BotDifficulty>,
+    ) -> GameResult<Self> {
+        let status = NetSetupState::address_text(DEFAULT_ADDRESS);
+        NetSetupState::new(ctx, assets, "Join", Phase::Address, None, bot, &status)
+    }
+
+    /// Creates a screen for typing in the address of a machine playing a
+    /// match, to watch it.
+    pub fn watch(ctx: &mut Context, assets: &Assets) -> GameResult<Self> {
+        let status = NetSetupState::address_text(DEFAULT_ADDRESS);
+        let mut state =
+            NetSetupState::new(ctx, assets, "Watch", Phase::Address, None, None, &status)?;
+        state.spectate = true;
+        Ok(state)
+    }
+
+    fn new(
+        ctx: &mut Context,
+        assets: &Assets,
+        title: &str,
+        phase: Phase,
+        settings: std::option::Option<MatchSettings>,
+        bot: std::option::Option<BotDifficulty>,
+        status: &str,
+    ) -> GameResult<Self> {
+        let help = match phase {
+            Phase::Address => "Type the address of the host, then press Enter",
+            _ => "Press Escape to go back",
+        };
+
+        Ok(NetSetupState {
+            is_host: settings.is_some(),
+            spectate: false,
+            phase,
+            settings,
+            hello_received: false,
+            opponent_handling: None,
+            bot,
+            address: DEFAULT_ADDRESS.to_string(),
+
+            title_text: graphics::Text::new(ctx, title, assets.get_font("title")?)?,
+            status_text: graphics::Text::new(ctx, status, assets.get_font("normal")?)?,
+            help_text: graphics::Text::new(ctx, help, assets.get_font("ui")?)?,
+
+            request_connect: false,
+            request_back: false,
+            request_refresh: false,
+        })
+    }
+
+    fn address_text(address: &str) -> String {
+        format!("Address: {}_", address)
+    }
+
+    /// Shows `status` in place of the current status text.
+    fn set_status(&mut self, ctx: &mut Context, assets: &Assets, status: &str) -> GameResult<()> {
+        self.status_text = graphics::Text::new(ctx, status, assets.get_font("normal")?)?;
+        Ok(())
+    }
+
+    /// Sends hello and this player's handling over a new connection. Returns
+    /// the phase that waits for the other side's.
+    fn say_hello(
+        &mut self,
+        ctx: &mut Context,
+        assets: &Assets,
+        mut connection: Connection,
+    ) -> GameResult<Phase> {
+        let _ = connection.send(&Message::Hello(PROTOCOL_VERSION));
+        let _ = connection.send(&Message::Handling(Handling::load(ctx)));
+        self.set_status(ctx, assets, "Connected, saying hello...")?;
+        Ok(Phase::Handshake(connection))
+    }
+
+    /// Shows `reason` as the status text. Returns the phase that gives up on
+    /// connecting.
+    fn fail(&mut self, ctx: &mut Context, assets: &Assets, reason: &str) -> GameResult<Phase> {
+        self.help_text =
+            graphics::Text::new(ctx, "Press Escape to go back", assets.get_font("ui")?)?;
+        self.set_status(ctx, assets, reason)?;
+        Ok(Phase::Failed)
+    }
+
+    /// Handles the messages sent by the other side while saying hello. Stops
+    /// at the first round, leaving any messages after it for the match.
+    fn handle_handshake(&mut self, connection: &mut Connection) -> Handshake {
+        loop {
+            let message = match connection.receive() {
+                Ok(Some(message)) => message,
+                Ok(None) => return Handshake::Waiting,
+                Err(_) => return Handshake::Failed("Lost the connection"),
+            };
+            match message {
+                Message::Hello(version) if version != PROTOCOL_VERSION => {
+                    return Handshake::Failed("The other side is running a different version");
+                }
+                Message::Hello(_) => self.hello_received = true,
+                Message::Handling(handling) if self.hello_received => {
+                    self.opponent_handling = Some(handling)
+                }
+                Message::Start(settings) if !self.is_host => self.settings = Some(settings),
+                Message::Round(seed) => {
+                    if let (Some(settings), Some(handling)) =
+                        (self.settings, self.opponent_handling)
+                    {
+                        return Handshake::Ready(settings, handling, seed);
+                    }
+                }
+                Message::Bye => return Handshake::Failed("The other side left"),
+                _ => (),
+            }
+            if let (true, Some(handling)) = (self.is_host, self.opponent_handling) {
+                let settings = self.settings.expect("The host always has match settings");
+                return Handshake::Ready(settings, handling, rand::random());
+            }
+        }
+    }
+}
+
+impl State for NetSetupState {
+    fn update(
+        &mut self,
+        ctx: &mut Context,
+        assets: &Assets,
+        _: Duration,
+    ) -> GameResult<Transition> {
+        if self.request_back {
+            if let Phase::Handshake(connection) = &mut self.phase {
+                let _ = connection.send(&Message::Bye);
+            }
+            return Ok(Transition::Pop);
+        }
+
+        // the phase is taken out while it is worked on, and put back after
+        let phase = std::mem::replace(&mut self.phase, Phase::Failed);
+        self.phase = match phase {
+            Phase::Address if self.request_connect && self.spectate => {
+                self.request_connect = false;
+                match Connection::connect(&self.address, SPECTATOR_PORT) {
+                    Ok(connection) => {
+                        assets.get_music("menu")?.pause();
+                        return Ok(Transition::Swap(Box::new(SpectateState::new(
+                            ctx, assets, connection,
+                        )?)));
+                    }
+                    Err(_) => self.fail(ctx, assets, "Could not connect to the match")?,
+                }
+            }
+            Phase::Address if self.request_connect => {
+                self.request_connect = false;
+                match Connection::connect(&self.address, DEFAULT_PORT) {
+                    Ok(connection) => self.say_hello(ctx, assets, connection)?,
+                    Err(_) => self.fail(ctx, assets, "Could not connect to the host")?,
+                }
+            }
+            Phase::Listening(listener) => match listener.accept() {
+                Ok((stream, _)) => match Connection::new(stream) {
+                    Ok(connection) => self.say_hello(ctx, assets, connection)?,
+                    Err(_) => self.fail(ctx, assets, "Could not connect to the opponent")?,
+                },
+                Err(ref e) if e.kind() == ErrorKind::WouldBlock => Phase::Listening(listener),
+                Err(_) => self.fail(ctx, assets, "Could not connect to the opponent")?,
+            },
+            Phase::Handshake(mut connection) => match self.handle_handshake(&mut connection) {
+                Handshake::Waiting => Phase::Handshake(connection),
+                Handshake::Failed(reason) => self.fail(ctx, assets, reason)?,
+                Handshake::Ready(settings, handling, seed) => {
+                    if self.is_host {
+                        let _ = connection.send(&Message::Start(settings));
+                    }
+                    assets.get_music("menu")?.pause();
+                    return Ok(Transition::Swap(Box::new(NetVersusState::new(
+                        ctx,
+                        assets,
+                        Peer {
+                            connection,
+                            handling,
+                        },
+                        self.is_host,
+                        settings,
+                        seed,
+                        self.bot,
+                    )?)));
+                }
+            },
+            phase => phase,
+        };
+
+        if self.request_refresh {
+            let text = NetSetupState::address_text(&self.address);
+            self.set_status(ctx, assets, &text)?;
+            self.request_refresh = false;
+        }
+
+        Ok(Transition::None)
+    }
+
+    fn draw(&mut self, ctx: &mut Context, _: &Assets) -> GameResult<()> {
+        let coords = graphics::get_screen_coordinates(ctx);
+        let centered = |text: &graphics::Text, y: f32| {
+            Point2::new(coords.w / 2.0 - (text.width() / 2) as f32, y)
+        };
+
+        graphics::set_color(ctx, graphics::Color::new(0.0, 0.0, 0.0, 0.75))?;
+        graphics::rectangle(ctx, graphics::DrawMode::Fill, coords)?;
+        graphics::set_color(ctx, graphics::Color::new(1.0, 1.0, 1.0, 1.0))?;
+        graphics::draw(
+            ctx,
+            &self.title_text,
+            centered(&self.title_text, 100.0),
+            0.0,
+        )?;
+        graphics::draw(
+            ctx,
+            &self.status_text,
+            centered(&self.status_text, 300.0),
+            0.0,
+        )?;
+        graphics::draw(ctx, &self.help_text, centered(&self.help_text, 450.0), 0.0)?;
+
+        Ok(())
+    }
+
+    fn text_input_event(&mut self, _ctx: &mut Context, text: String, assets: &Assets) {
+        if let Phase::Address = self.phase {
+            for c in text.chars() {
+                let allowed = c.is_ascii_alphanumeric() || c == '.' || c == ':' || c == '-';
+                if allowed && self.address.len() < MAX_ADDRESS_LEN {
+                    play_click_sfx(assets).expect("Could not play click sfx in net setup -> type");
+                    self.address.push(c);
+                    self.request_refresh = true;
+                }
+            }
+        }
+    }
+
+    fn key_down_event(
+        &mut self,
+        _ctx: &mut Context,
+        keycode: Keycode,
+        _keymod: Mod,
+        _repeat: bool,
+        _assets: &Assets,
+    ) {
+        match keycode {
+            Keycode::Escape => self.request_back = true,
+            Keycode::Return => self.request_connect = true,
+            Keycode::Backspace => {
+                if let Phase::Address = self.phase {
+                    self.request_refresh |= self.address.pop().is_some();
+                }
+            }
+            _ => (),
+        }
+    }
+
+    fn controller_button_down_event(
+        &mut self,
+        _ctx: &mut Context,
+        btn: Button,
+        _instance_id: i32,
+        _assets: &Assets,
+    ) {
+        match btn {
+            Button::A | Button::Start => self.request_connect = true,
+            Button::B => self.request_back = true,
+            _ => (),
+        }
+    }
+}
@@ -0,0 +1,442 @@
+use super::rollback::{InputFrame, InputPacket};
+use crate::states::play_state::game_core::LockedPiece;
+use crate::states::play_state::gravity::GravityCurve;
+use crate::states::play_state::handling::Handling;
+use crate::states::play_state::randomizer::RandomizerKind;
+use crate::states::play_state::rotation::RotationSystemKind;
+use crate::states::play_state::ruleset::{LockReset, Ruleset};
+use std::io::{self, ErrorKind, Read, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::time::Duration;
+
+/// Bumped whenever a message changes, so that different versions of the game
+/// refuse to play each other instead of falling out of step.
+pub const PROTOCOL_VERSION: u32 = 4;
+pub const DEFAULT_PORT: u16 = 7878;
+
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(3);
+const READ_BUFFER_SIZE: usize = 1024;
+
+/// The settings a network match is played with, chosen by the host.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct MatchSettings {
+    pub best_of: u32,
+    pub ruleset: Ruleset,
+}
+
+/// A message sent between the two machines in a network match. Each message is
+/// written as a single line of text, starting with its name.
+///
+/// Both machines play the whole match from both players' inputs, which are
+/// sent to the other side every frame. Every piece that locks on the sender's
+/// board is sent as well, along with the garbage it sends, so the receiver can
+/// check that its copy of that board has not fallen out of step.
+///
+/// Spectators are sent the match settings, a `Player` for each side and the
+/// seed of each round, followed by the locks, garbage and top outs of both
+/// boards, each wrapped in a `Board`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Message {
+    /// The first message sent by both sides, holding their protocol version.
+    Hello(u32),
+    /// Sent by both sides after hello, holding the handling their player
+    /// plays with.
+    Handling(Handling),
+    /// Sent by the host once both sides have said hello, to set up the match,
+    /// and again whenever the host changes the settings in the lobby.
+    Start(MatchSettings),
+    /// Sent from the lobby whenever the sender becomes ready to start, or
+    /// stops being ready.
+    Ready(bool),
+    /// Starts a round with boards dealt from the given seed. Sent by the host,
+    /// and sent back by the other side once its round has started.
+    Round(u64),
+    /// A piece locked. `garbage_taken` counts the `Garbage` messages received
+    /// before it locked, so the mirror can take in the same garbage first.
+    Lock {
+        piece: LockedPiece,
+        garbage_taken: u32,
+    },
+    /// Rows of garbage sent to the other side.
+    Garbage(u32),
+    /// The sender's inputs the other side has not acknowledged yet.
+    Inputs(InputPacket),
+    /// The sender topped out.
+    GameOver,
+    /// The sender left the match.
+    Bye,
+    /// The name of the player on a board, and the rounds they have won.
+    Player {
+        board: usize,
+        wins: u32,
+        name: String,
+    },
+    /// A `Lock`, `Garbage` or `GameOver` on one of the boards, sent to
+    /// spectators.
+    Board(usize, Box<Message>),
+}
+
+impl Message {
+    /// Returns the message as a line of text, without the line break.
+    pub fn encode(&self) -> String {
+        match self {
+            Message::Hello(version) => format!("hello {}", version),
+            Message::Handling(handling) => format!(
+                "handling {} {} {} {}",
+                handling.das, handling.arr, handling.soft_drop_factor, handling.das_cut
+            ),
+            Message::Start(MatchSettings { best_of, ruleset }) => format!(
+                "start {} {} {} {} {} {}",
+                best_of,
+                ruleset.rotation_system.name(),
+                ruleset.lock_delay,
+                ruleset.lock_reset.name(),
+                ruleset.randomizer.name(),
+                ruleset.gravity.name()
+            ),
+            Message::Ready(ready) => format!("ready {}", *ready as u8),
+            Message::Round(seed) => format!("round {}", seed),
+            Message::Lock {
+                piece,
+                garbage_taken,
+            } => {
+                let kick = match piece.kick {
+                    Some(kick) => kick.to_string(),
+                    None => "-".to_string(),
+                };
+                format!(
+                    "lock {} {} {} {} {} {}",
+                    piece.rotation, piece.x, piece.y, piece.held as u8, kick, garbage_taken
+                )
+            }
+            Message::Garbage(rows) => format!("garbage {}", rows),
+            Message::Inputs(packet) => {
+                let inputs: String = match packet.inputs.as_slice() {
+                    [] => "-".to_string(),
+                    inputs => inputs
+                        .iter()
+                        .map(|input| format!("{:02x}", input.bits()))
+                        .collect(),
+                };
+                format!("inputs {} {} {}", packet.ack, packet.first_frame, inputs)
+            }
+            Message::GameOver => "gameover".to_string(),
+            Message::Bye => "bye".to_string(),
+            Message::Player { board, wins, name } => format!("player {} {} {}", board, wins, name),
+            Message::Board(board, message) => format!("board {} {}", board, message.encode()),
+        }
+    }
+
+    /// Reads a message written by `Message::encode`. Returns `None` if the
+    /// line is not a valid message.
+    pub fn decode(line: &str) -> Option<Message> {
+        // names may hold spaces, and a board's message is a whole message
+        // of its own, so both take up the rest of the line
+        let mut words = line.splitn(4, ' ');
+        match (words.next(), words.next(), words.next(), words.next()) {
+            (Some("player"), Some(board), Some(wins), Some(name)) => {
+                return Some(Message::Player {
+                    board: board.parse().ok()?,
+                    wins: wins.parse().ok()?,
+                    name: name.to_string(),
+                });
+            }
+            (Some("board"), Some(board), Some(first), rest) => {
+                let message = match rest {
+                    Some(rest) => Message::decode(&format!("{} {}", first, rest))?,
+                    None => Message::decode(first)?,
+                };
+                match message {
+                    Message::Lock { .. } | Message::Garbage(_) | Message::GameOver => (),
+                    _ => return None,
+                }
+                return Some(Message::Board(board.parse().ok()?, Box::new(message)));
+            }
+            _ => (),
+        }
+
+        let parts: Vec<&str> = line.split_whitespace().collect();
+        let message = match parts.as_slice() {
+            ["hello", version] => Message::Hello(version.parse().ok()?),
+            ["handling", das, arr, soft_drop_factor, das_cut] => Message::Handling(Handling {
+                das: das.parse().ok()?,
+                arr: arr.parse().ok()?,
+                soft_drop_factor: soft_drop_factor.parse().ok()?,
+                das_cut: das_cut.parse().ok()?,
+            }),
+            ["start", best_of, rotation, lock_delay, lock_reset, randomizer, gravity] => {
+                Message::Start(MatchSettings {
+                    best_of: best_of.parse().ok()?,
+                    ruleset: Ruleset {
+                        rotation_system: RotationSystemKind::from_name(rotation)?,
+                        lock_delay: lock_delay.parse().ok()?,
+                        lock_reset: LockReset::from_name(lock_reset)?,
+                        randomizer: RandomizerKind::from_name(randomizer)?,
+                        gravity: GravityCurve::from_name(gravity)?,
+                    },
+                })
+            }
+            ["ready", "0"] => Message::Ready(false),
+            ["ready", "1"] => Message::Ready(true),
+            ["round", seed] => Message::Round(seed.parse().ok()?),
+            ["lock", rotation, x, y, held, kick, garbage_taken] => Message::Lock {
+                piece: LockedPiece {
+                    rotation: rotation.parse().ok().filter(|r| *r < 4)?,
+                    x: x.parse().ok()?,
+                    y: y.parse().ok()?,
+                    held: match *held {
+                        "0" => false,
+                        "1" => true,
+                        _ => return None,
+                    },
+                    kick: match *kick {
+                        "-" => None,
+                        kick => Some(kick.parse().ok()?),
+                    },
+                },
+                garbage_taken: garbage_taken.parse().ok()?,
+            },
+            ["garbage", rows] => Message::Garbage(rows.parse().ok()?),
+            ["inputs", ack, first_frame, inputs] => Message::Inputs(InputPacket {
+                ack: ack.parse().ok()?,
+                first_frame: first_frame.parse().ok()?,
+                inputs: match *inputs {
+                    "-" => Vec::new(),
+                    inputs if inputs.is_ascii() && inputs.len() % 2 == 0 => (0..inputs.len())
+                        .step_by(2)
+                        .map(|i| u8::from_str_radix(&inputs[i..i + 2], 16).ok())
+                        .map(|bits| bits.map(InputFrame::from_bits))
+                        .collect::<Option<_>>()?,
+                    _ => return None,
+                },
+            }),
+            ["gameover"] => Message::GameOver,
+            ["bye"] => Message::Bye,
+            _ => return None,
+        };
+        Some(message)
+    }
+}
+
+/// A TCP connection to the other machine in a network match. Never blocks
+/// once connected: messages that cannot be written yet are kept until the
+/// next send or receive, and receiving returns only the messages that have
+/// fully arrived.
+pub struct Connection {
+    stream: TcpStream,
+    // bytes read that do not make up a whole line yet
+    incoming: Vec<u8>,
+    // bytes not written yet
+    outgoing: Vec<u8>,
+}
+
+impl Connection {
+    /// Wraps a connected stream.
+    pub fn new(stream: TcpStream) -> io::Result<Self> {
+        stream.set_nonblocking(true)?;
+        stream.set_nodelay(true)?;
+        Ok(Connection {
+            stream,
+            incoming: Vec::new(),
+            outgoing: Vec::new(),
+        })
+    }
+
+    /// Connects to `address`, such as `192.168.0.2:7878`. The port can be
+    /// left out, in which case `default_port` is used.
+    pub fn connect(address: &str, default_port: u16) -> io::Result<Self> {
+        let address = if address.contains(':') {
+            address.to_string()
+        } else {
+            format!("{}:{}", address, default_port)
+        };
+        let socket_address = address
+            .to_socket_addrs()?
+            .next()
+            .ok_or_else(|| io::Error::new(ErrorKind::NotFound, "no such address"))?;
+        Connection::new(TcpStream::connect_timeout(
+            &socket_address,
+            CONNECT_TIMEOUT,
+        )?)
+    }
+
+    /// Sends `message`, or queues it to be sent as soon as possible.
+    pub fn send(&mut self, message: &Message) -> io::Result<()> {
+        self.outgoing.extend(message.encode().bytes());
+        self.outgoing.push(b'\n');
+        self.flush()
+    }
+
+    /// Returns the next message from the other side, or `None` if no whole
+    /// message has arrived yet. Fails if the other side closed the connection
+    /// and every message it sent has been received, or if it sent something
+    /// that is not a message.
+    pub fn receive(&mut self) -> io::Result<Option<Message>> {
+        self.flush()?;
+
+        if !self.incoming.contains(&b'\n') {
+            let mut buffer = [0; READ_BUFFER_SIZE];
+            loop {
+                match self.stream.read(&mut buffer) {
+                    // messages that arrived before the connection closed
+                    // are still handed out
+                    Ok(0) if self.incoming.contains(&b'\n') => break,
+                    Ok(0) => return Err(ErrorKind::UnexpectedEof.into()),
+                    Ok(read) => self.incoming.extend_from_slice(&buffer[..read]),
+                    Err(ref e) if e.kind() == ErrorKind::WouldBlock => break,
+                    Err(ref e) if e.kind() == ErrorKind::Interrupted => (),
+                    Err(e) => return Err(e),
+                }
+            }
+        }
+
+        match self.incoming.iter().position(|b| *b == b'\n') {
+            Some(end) => {
+                let line: Vec<u8> = self.incoming.drain(..=end).collect();
+                std::str::from_utf8(&line[..end])
+                    .ok()
+                    .and_then(Message::decode)
+                    .map(Some)
+                    .ok_or_else(|| io::Error::new(ErrorKind::InvalidData, "invalid message"))
+            }
+            None => Ok(None),
+        }
+    }
+
+    /// Writes as much of the queued outgoing bytes as the socket will take.
+    fn flush(&mut self) -> io::Result<()> {
+        while !self.outgoing.is_empty() {
+            match self.stream.write(&self.outgoing) {
+                Ok(0) => return Err(ErrorKind::WriteZero.into()),
+                Ok(written) => {
+                    self.outgoing.drain(..written);
+                }
+                Err(ref e) if e.kind() == ErrorKind::WouldBlock => break,
+                Err(ref e) if e.kind() == ErrorKind::Interrupted => (),
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::rollback::{InputFrame, InputPacket};
+    use super::{Connection, MatchSettings, Message, DEFAULT_PORT, PROTOCOL_VERSION};
+    use crate::states::play_state::game_core::LockedPiece;
+    use crate::states::play_state::handling::Handling;
+    use crate::states::play_state::ruleset::Ruleset;
+    use std::net::TcpListener;
+    use std::time::{Duration, Instant};
+
+    fn sample_messages() -> Vec<Message> {
+        vec![
+            Message::Hello(PROTOCOL_VERSION),
+            Message::Handling(Handling {
+                soft_drop_factor: f64::INFINITY,
+                ..Handling::default()
+            }),
+            Message::Start(MatchSettings {
+                best_of: 5,
+                ruleset: Ruleset::default(),
+            }),
+            Message::Ready(true),
+            Message::Ready(false),
+            Message::Round(12_345_678_901),
+            Message::Lock {
+                piece: LockedPiece {
+                    rotation: 3,
+                    x: -1,
+                    y: 18,
+                    held: true,
+                    kick: Some(2),
+                },
+                garbage_taken: 4,
+            },
+            Message::Lock {
+                piece: LockedPiece {
+                    rotation: 0,
+                    x: 4,
+                    y: 20,
+                    held: false,
+                    kick: None,
+                },
+                garbage_taken: 0,
+            },
+            Message::Garbage(3),
+            Message::Inputs(InputPacket {
+                ack: 120,
+                first_frame: 98,
+                inputs: vec![InputFrame::from_bits(0), InputFrame::from_bits(0x5a)],
+            }),
+            Message::Inputs(InputPacket {
+                ack: 7,
+                first_frame: 9,
+                inputs: Vec::new(),
+            }),
+            Message::GameOver,
+            Message::Bye,
+            Message::Player {
+                board: 1,
+                wins: 2,
+                name: "Player 2 (Hard Bot)".to_string(),
+            },
+            Message::Board(0, Box::new(Message::GameOver)),
+            Message::Board(
+                1,
+                Box::new(Message::Lock {
+                    piece: LockedPiece {
+                        rotation: 1,
+                        x: 7,
+                        y: 3,
+                        held: false,
+                        kick: None,
+                    },
+                    garbage_taken: 1,
+                }),
+            ),
+        ]
+    }
+
+    #[test]
+    fn messages_survive_encoding_and_decoding() {
+        for message in sample_messages() {
+            assert_eq!(Some(message.clone()), Message::decode(&message.encode()));
+        }
+        assert_eq!(None, Message::decode("lock 4 0 0 0 - 0"));
+        assert_eq!(None, Message::decode("inputs 0 0 abc"));
+        assert_eq!(None, Message::decode("board 0 player 1 0 name"));
+        assert_eq!(None, Message::decode("garbage"));
+    }
+
+    #[test]
+    fn messages_cross_a_localhost_connection() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        let mut client = Connection::connect(&address, DEFAULT_PORT).unwrap();
+        let mut server = Connection::new(listener.accept().unwrap().0).unwrap();
+
+        for message in sample_messages() {
+            client.send(&message).unwrap();
+        }
+
+        let mut received = Vec::new();
+        let started = Instant::now();
+        while received.len() < sample_messages().len() {
+            assert!(started.elapsed() < Duration::from_secs(5));
+            match server.receive().unwrap() {
+                Some(message) => received.push(message),
+                None => std::thread::sleep(Duration::from_millis(1)),
+            }
+        }
+        assert_eq!(sample_messages(), received);
+
+        drop(client);
+        let started = Instant::now();
+        while server.receive().is_ok() {
+            assert!(started.elapsed() < Duration::from_secs(5));
+            std::thread::sleep(Duration::from_millis(1));
+        }
+    }
+}
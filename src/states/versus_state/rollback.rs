@@ -0,0 +1,480 @@
+use super::PLAYERS;
+use crate::states::play_state::game_core::{GameCore, GameEvent, LockedPiece};
+use crate::states::play_state::game_mode::{GameMode, ModeSettings};
+use crate::states::play_state::handling::Handling;
+use crate::states::play_state::input_state::{InputAction, InputState};
+use crate::states::play_state::replay::FRAME_DURATION;
+use crate::states::play_state::ruleset::Ruleset;
+
+/// Frames between an input being pressed and it taking effect. Gives the
+/// opponent's inputs a head start, so fewer of them have to be guessed.
+pub const INPUT_DELAY: u32 = 2;
+/// The most frames the game may be played past the last frame the opponent's
+/// inputs are known for. Once this far ahead, the game waits for them.
+pub const MAX_ROLLBACK: u32 = 8;
+
+/// The inputs one player holds on one frame, one bit for each `InputAction`
+/// in the order of `InputAction::ALL`.
+#[derive(Debug, Copy, Clone, PartialEq, Default)]
+pub struct InputFrame(u8);
+
+impl InputFrame {
+    pub fn from_bits(bits: u8) -> Self {
+        InputFrame(bits)
+    }
+
+    pub fn bits(self) -> u8 {
+        self.0
+    }
+
+    /// Marks `action` as held or not.
+    pub fn set(&mut self, action: InputAction, pressed: bool) {
+        let bit = 1 << action_index(action);
+        if pressed {
+            self.0 |= bit;
+        } else {
+            self.0 &= !bit;
+        }
+    }
+
+    /// Presses and releases the inputs in `input` to match this frame.
+    fn apply(self, input: &mut InputState) {
+        for (i, action) in InputAction::ALL.iter().enumerate() {
+            input.set_action(*action, self.0 & (1 << i) != 0);
+        }
+    }
+}
+
+fn action_index(action: InputAction) -> usize {
+    InputAction::ALL
+        .iter()
+        .position(|a| *a == action)
+        .expect("Every action is in InputAction::ALL")
+}
+
+/// Both boards of a versus match, played together one frame at a time.
+/// Garbage sent by either board reaches the other on the same frame, so the
+/// whole match follows from the seed, the handling of each player and their
+/// inputs. Once either player tops out the match stands still, so every
+/// machine agrees on how it ended.
+#[derive(Clone)]
+pub struct VersusGame {
+    inputs: Vec<InputState>,
+    cores: Vec<GameCore>,
+}
+
+impl VersusGame {
+    /// Creates a match played with `ruleset`, with each board dealt from
+    /// `seed` and played with that player's `handling`.
+    pub fn new(ruleset: Ruleset, handling: [Handling; PLAYERS], seed: u64) -> Self {
+        VersusGame {
+            inputs: vec![InputState::default(); PLAYERS],
+            cores: handling
+                .iter()
+                .map(|handling| {
+                    GameCore::new(
+                        GameMode::Versus,
+                        ModeSettings::default(),
+                        ruleset,
+                        *handling,
+                        seed,
+                    )
+                })
+                .collect(),
+        }
+    }
+
+    /// Returns the board of `player`.
+    pub fn core(&self, player: usize) -> &GameCore {
+        &self.cores[player]
+    }
+
+    /// Returns true once either player has topped out.
+    pub fn is_over(&self) -> bool {
+        self.cores.iter().any(GameCore::is_game_over)
+    }
+
+    /// Plays one frame with the inputs each player holds. Returns what
+    /// happened on each board.
+    pub fn step(&mut self, frame: [InputFrame; PLAYERS]) -> Vec<Vec<GameEvent>> {
+        if self.is_over() {
+            return vec![Vec::new(); PLAYERS];
+        }
+
+        let mut events = Vec::new();
+        let mut sent = [0; PLAYERS];
+        for (i, (core, input)) in self.cores.iter_mut().zip(&mut self.inputs).enumerate() {
+            frame[i].apply(input);
+            let player_events = core.step(input, FRAME_DURATION);
+            for event in &player_events {
+                if let GameEvent::GarbageSent(rows) = event {
+                    sent[i] += rows;
+                }
+            }
+            events.push(player_events);
+        }
+        for (i, rows) in sent.iter().enumerate() {
+            self.cores[(i + 1) % PLAYERS].receive_garbage(*rows);
+        }
+        events
+    }
+}
+
+/// Something that happened on a frame both players' inputs are known for, so
+/// no rollback can undo it.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct ConfirmedEvent {
+    pub frame: u32,
+    pub player: usize,
+    pub event: GameEvent,
+    /// Where the piece locked, for a `GameEvent::PieceLocked`.
+    pub lock: Option<LockedPiece>,
+}
+
+/// The inputs one side sends the other. Every input the other side has not
+/// acknowledged yet is sent again, so a lost packet is made up for by the
+/// next one.
+#[derive(Debug, Clone, PartialEq)]
+pub struct InputPacket {
+    /// The number of the other side's frames the sender has inputs for.
+    pub ack: u32,
+    /// The frame of the first input in `inputs`.
+    pub first_frame: u32,
+    pub inputs: Vec<InputFrame>,
+}
+
+/// Plays a `VersusGame` against a player on another machine without waiting
+/// for their inputs. Inputs that have not arrived yet are guessed to be the
+/// same as their last known input. When the real inputs arrive and a guess
+/// was wrong, the game is rolled back to the last frame both players' inputs
+/// were known for and played forward again, so both machines always end up
+/// with the same game.
+pub struct RollbackSession {
+    local: usize,
+    // the game after every frame both players' inputs are known for, and the
+    // number of those frames
+    confirmed: VersusGame,
+    confirmed_frames: u32,
+    // the game after every frame played so far, and the number of those
+    // frames
+    current: VersusGame,
+    frames: u32,
+    // this player's inputs for each frame, known `INPUT_DELAY` frames ahead
+    local_inputs: Vec<InputFrame>,
+    // the opponent's inputs for each frame, as far as they have arrived
+    remote_inputs: Vec<InputFrame>,
+    // the opponent's input used for each frame played so far, guessed or not
+    used_inputs: Vec<InputFrame>,
+    // the number of this player's inputs the opponent has
+    acked: u32,
+    confirmed_events: Vec<ConfirmedEvent>,
+}
+
+impl RollbackSession {
+    /// Starts a session for `game`, in which this machine plays as `local`.
+    pub fn new(game: VersusGame, local: usize) -> Self {
+        RollbackSession {
+            local,
+            confirmed: game.clone(),
+            confirmed_frames: 0,
+            current: game,
+            frames: 0,
+            local_inputs: vec![InputFrame::default(); INPUT_DELAY as usize],
+            remote_inputs: Vec::new(),
+            used_inputs: Vec::new(),
+            acked: 0,
+            confirmed_events: Vec::new(),
+        }
+    }
+
+    /// Returns the game as it stands, including any guessed inputs.
+    pub fn game(&self) -> &VersusGame {
+        &self.current
+    }
+
+    /// Returns the game up to the last frame both players' inputs are known
+    /// for.
+    pub fn confirmed_game(&self) -> &VersusGame {
+        &self.confirmed
+    }
+
+    /// Returns false while the game is waiting for the opponent's inputs.
+    pub fn can_advance(&self) -> bool {
+        self.frames - self.confirmed_frames < MAX_ROLLBACK
+    }
+
+    /// Plays the next frame. `input` is what this player holds now, which
+    /// takes effect `INPUT_DELAY` frames later. Returns what happened on each
+    /// board, which a rollback may yet undo.
+    pub fn advance(&mut self, input: InputFrame) -> Vec<Vec<GameEvent>> {
+        self.local_inputs.push(input);
+        let remote = self.guess(self.frames);
+        self.used_inputs.push(remote);
+        let frame = self.frame_inputs(self.frames, remote);
+        let events = self.current.step(frame);
+        self.frames += 1;
+        self.confirm();
+        events
+    }
+
+    /// Returns the packet to send the opponent.
+    pub fn packet(&self) -> InputPacket {
+        InputPacket {
+            ack: self.remote_inputs.len() as u32,
+            first_frame: self.acked,
+            inputs: self.local_inputs[self.acked as usize..].to_vec(),
+        }
+    }
+
+    /// Takes in a packet from the opponent. Packets may arrive late, more
+    /// than once or not at all.
+    pub fn receive(&mut self, packet: &InputPacket) {
+        self.acked = self
+            .acked
+            .max(packet.ack)
+            .min(self.local_inputs.len() as u32);
+
+        let known = self.remote_inputs.len();
+        let first = packet.first_frame as usize;
+        if first <= known && known < first + packet.inputs.len() {
+            self.remote_inputs
+                .extend_from_slice(&packet.inputs[known - first..]);
+        }
+        self.confirm();
+    }
+
+    /// Returns everything that happened on the frames confirmed since the
+    /// last call.
+    pub fn take_confirmed_events(&mut self) -> Vec<ConfirmedEvent> {
+        std::mem::take(&mut self.confirmed_events)
+    }
+
+    /// Plays every frame both players' inputs are known for on the confirmed
+    /// game. If any of those frames were played with a wrong guess, the
+    /// current game is rolled back to the confirmed game and played forward
+    /// again with new guesses.
+    fn confirm(&mut self) {
+        let mut guessed_wrong = false;
+        while self.confirmed_frames < self.frames
+            && (self.confirmed_frames as usize) < self.remote_inputs.len()
+        {
+            let frame = self.confirmed_frames as usize;
+            let remote = self.remote_inputs[frame];
+            guessed_wrong |= self.used_inputs[frame] != remote;
+
+            let inputs = self.frame_inputs(self.confirmed_frames, remote);
+            let events = self.confirmed.step(inputs);
+            for (player, player_events) in events.into_iter().enumerate() {
+                for event in player_events {
+                    let lock = match event {
+                        GameEvent::PieceLocked => self.confirmed.core(player).last_lock(),
+                        _ => None,
+                    };
+                    self.confirmed_events.push(ConfirmedEvent {
+                        frame: self.confirmed_frames,
+                        player,
+                        event,
+                        lock,
+                    });
+                }
+            }
+            self.confirmed_frames += 1;
+        }
+
+        if guessed_wrong {
+            self.current = self.confirmed.clone();
+            for frame in self.confirmed_frames..self.frames {
+                let remote = self.guess(frame);
+                self.used_inputs[frame as usize] = remote;
+                let inputs = self.frame_inputs(frame, remote);
+                self.current.step(inputs);
+            }
+        }
+    }
+
+    /// Returns the opponent's input for `frame`, or a guess if it has not
+    /// arrived yet.
+    fn guess(&self, frame: u32) -> InputFrame {
+        match self.remote_inputs.get(frame as usize) {
+            Some(input) => *input,
+            None => self.remote_inputs.last().cloned().unwrap_or_default(),
+        }
+    }
+
+    /// Returns the inputs of both players for `frame`, given the opponent's.
+    fn frame_inputs(&self, frame: u32, remote: InputFrame) -> [InputFrame; PLAYERS] {
+        let mut inputs = [remote; PLAYERS];
+        inputs[self.local] = self.local_inputs[frame as usize];
+        inputs
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::protocol::Message;
+    use super::super::PLAYERS;
+    use super::{InputFrame, RollbackSession, VersusGame};
+    use crate::states::play_state::bot::{BotDifficulty, BotPlayer};
+    use crate::states::play_state::handling::Handling;
+    use crate::states::play_state::ruleset::Ruleset;
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+    use std::collections::VecDeque;
+    use std::net::{SocketAddr, UdpSocket};
+    use std::time::{Duration, Instant};
+
+    const FRAMES: u32 = 900;
+
+    /// A UDP socket on localhost that loses some of the packets sent through
+    /// it and holds the rest back for a few ticks, which can reorder them, as
+    /// a slow and unreliable link would.
+    struct LossyLink {
+        socket: UdpSocket,
+        peer: Option<SocketAddr>,
+        rng: StdRng,
+        loss: f64,
+        latency: u32,
+        jitter: u32,
+        tick: u32,
+        // packets waiting to be sent, with the tick to send each on
+        held: VecDeque<(u32, String)>,
+    }
+
+    impl LossyLink {
+        fn new(seed: u64, loss: f64, latency: u32, jitter: u32) -> Self {
+            let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+            socket.set_nonblocking(true).unwrap();
+            LossyLink {
+                socket,
+                peer: None,
+                rng: StdRng::seed_from_u64(seed),
+                loss,
+                latency,
+                jitter,
+                tick: 0,
+                held: VecDeque::new(),
+            }
+        }
+
+        fn send(&mut self, message: &Message) {
+            if !self.rng.gen_bool(self.loss) {
+                let delay = self.latency + self.rng.gen_range(0, self.jitter + 1);
+                self.held.push_back((self.tick + delay, message.encode()));
+            }
+        }
+
+        /// Sends every held packet that is due.
+        fn tick(&mut self) {
+            self.tick += 1;
+            let tick = self.tick;
+            let (due, held): (VecDeque<_>, VecDeque<_>) =
+                self.held.drain(..).partition(|(at, _)| *at <= tick);
+            self.held = held;
+            for (_, line) in due {
+                self.socket
+                    .send_to(line.as_bytes(), self.peer.unwrap())
+                    .unwrap();
+            }
+        }
+
+        fn receive(&mut self) -> Vec<Message> {
+            let mut messages = Vec::new();
+            let mut buffer = [0; 4096];
+            while let Ok(read) = self.socket.recv(&mut buffer) {
+                let line = std::str::from_utf8(&buffer[..read]).unwrap();
+                messages.push(Message::decode(line).unwrap());
+            }
+            messages
+        }
+    }
+
+    /// One machine in a match played by a bot.
+    struct Peer {
+        session: RollbackSession,
+        bot: BotPlayer,
+        held: InputFrame,
+        link: LossyLink,
+    }
+
+    impl Peer {
+        /// Takes in what has arrived, plays a frame if it can and there are
+        /// frames left to play, and sends its inputs.
+        fn tick(&mut self) {
+            for message in self.link.receive() {
+                if let Message::Inputs(packet) = message {
+                    self.session.receive(&packet);
+                }
+            }
+
+            let local = self.session.local;
+            if self.session.frames < FRAMES && self.session.can_advance() {
+                for (action, pressed) in self.bot.update(self.session.game().core(local)) {
+                    self.held.set(action, pressed);
+                }
+                self.session.advance(self.held);
+            }
+
+            self.link.send(&Message::Inputs(self.session.packet()));
+            self.link.tick();
+        }
+    }
+
+    #[test]
+    fn peers_converge_over_a_slow_lossy_link() {
+        let game = VersusGame::new(Ruleset::default(), [Handling::default(); PLAYERS], 11);
+        let mut peers: Vec<Peer> = (0..PLAYERS)
+            .map(|i| Peer {
+                session: RollbackSession::new(game.clone(), i),
+                bot: BotPlayer::heuristic(BotDifficulty::Hard, i as u64),
+                held: InputFrame::default(),
+                link: LossyLink::new(i as u64, 0.25, 3, 4),
+            })
+            .collect();
+        let addresses: Vec<SocketAddr> = peers
+            .iter()
+            .map(|peer| peer.link.socket.local_addr().unwrap())
+            .collect();
+        peers[0].link.peer = Some(addresses[1]);
+        peers[1].link.peer = Some(addresses[0]);
+
+        let started = Instant::now();
+        let mut confirmed_events = vec![Vec::new(); PLAYERS];
+        while peers
+            .iter()
+            .any(|peer| peer.session.confirmed_frames < FRAMES)
+        {
+            assert!(started.elapsed() < Duration::from_secs(30));
+            for (i, peer) in peers.iter_mut().enumerate() {
+                peer.tick();
+                confirmed_events[i].extend(peer.session.take_confirmed_events());
+            }
+            std::thread::sleep(Duration::from_micros(200));
+        }
+
+        // both bots played, and both peers played frames with wrong guesses
+        // that had to be rolled back
+        for (i, peer) in peers.iter().enumerate() {
+            let session = &peer.session;
+            assert!(session.confirmed_game().core(i).pieces_locked() > 10);
+            let frames = FRAMES as usize;
+            assert_ne!(
+                session.used_inputs[..frames],
+                session.remote_inputs[..frames]
+            );
+        }
+        assert_eq!(confirmed_events[0], confirmed_events[1]);
+        for player in 0..PLAYERS {
+            let wells: Vec<_> = peers
+                .iter()
+                .map(|peer| peer.session.confirmed_game().core(player).well())
+                .collect();
+            assert_eq!(wells[0], wells[1]);
+            for peer in &peers {
+                assert_eq!(
+                    peer.session.confirmed_game().core(player).well(),
+                    peer.session.game().core(player).well()
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,299 @@
+use super::net_setup::NetSetupState;
+use super::protocol::MatchSettings;
+use super::{VersusState, PLAYERS};
+use crate::states::play_state::bot::{next_player, player_name, BotDifficulty};
+use crate::states::play_state::ruleset::Ruleset;
+use crate::states::shared::option::{Option, OptionInputCommand};
+use crate::states::{Assets, State, Transition};
+use crate::util::{cycle, play_click_sfx};
+use ggez::event::{Button, Keycode, Mod};
+use ggez::graphics::Point2;
+use ggez::{graphics, Context, GameResult};
+use std::time::Duration;
+
+// the position of each option on the screen. The first options choose who
+// plays each side
+const BEST_OF_OPTION: usize = PLAYERS;
+const SPECTATORS_OPTION: usize = PLAYERS + 1;
+const START_OPTION: usize = PLAYERS + 2;
+const HOST_OPTION: usize = PLAYERS + 3;
+const JOIN_OPTION: usize = PLAYERS + 4;
+const WATCH_OPTION: usize = PLAYERS + 5;
+const BACK_OPTION: usize = PLAYERS + 6;
+
+const FIRST_OPTION_Y: f32 = 180.0;
+const OPTION_SPACING: f32 = 37.0;
+const CONTROLS_Y: f32 = 520.0;
+const CONTROLS_SPACING: f32 = 35.0;
+
+// the match lengths that can be chosen
+const BEST_OF: [u32; 4] = [1, 3, 5, 7];
+const DEFAULT_BEST_OF: u32 = 3;
+
+/// Lets the players choose who plays each side, a person or a bot, the length
+/// of a versus match and whether it can be watched from other machines before
+/// it starts. A match can also be hosted or joined over the network, in which
+/// case this machine's side is played as chosen for player 1, or a match
+/// played on another machine can be watched. Drawn on top of the menu that
+/// pushed it, and pops itself when closed.
+pub struct VersusSetupState {
+    // the bot playing each side, or `None` for a person
+    bots: [std::option::Option<BotDifficulty>; PLAYERS],
+    best_of: u32,
+    // whether the match started from here takes spectators
+    spectators: bool,
+    ruleset: Ruleset,
+    title_text: graphics::Text,
+    controls_texts: Vec<graphics::Text>,
+    options: Vec<Option>,
+    current_selection: usize,
+
+    request_start: bool,
+    request_host: bool,
+    request_join: bool,
+    request_watch: bool,
+    request_back: bool,
+    request_refresh: bool,
+}
+
+impl VersusSetupState {
+    /// Creates a setup screen for a match played with `ruleset`.
+    pub fn new(ctx: &mut Context, assets: &Assets, ruleset: Ruleset) -> GameResult<Self> {
+        let title_text = graphics::Text::new(ctx, "Versus", assets.get_font("title")?)?;
+        let mut controls_texts = Vec::new();
+        for line in &[
+            "Player 1: A D to move, W S to drop, Q E to rotate, Left Shift to hold",
+            "Player 2: Arrows to move and drop, . / to rotate, Right Shift to hold",
+            "Gamepads go to the people playing in the order they are first used",
+        ] {
+            controls_texts.push(graphics::Text::new(ctx, line, assets.get_font("ui")?)?);
+        }
+
+        let bots = [None; PLAYERS];
+        let best_of = DEFAULT_BEST_OF;
+        let mut names: Vec<String> = (0..PLAYERS)
+            .map(|i| VersusSetupState::player_option_text(i, bots[i]))
+            .collect();
+        names.push(VersusSetupState::best_of_option_text(best_of));
+        names.push(VersusSetupState::spectators_option_text(false));
+        names.push("Start".to_string());
+        names.push("Host Network Match".to_string());
+        names.push("Join Network Match".to_string());
+        names.push("Watch Match".to_string());
+        names.push("Back".to_string());
+
+        let coords = graphics::get_screen_coordinates(ctx);
+        let mut options: Vec<Option> = Vec::new();
+        for (i, name) in names.iter().enumerate() {
+            options.push(Option::new(
+                ctx,
+                assets,
+                name,
+                Point2::new(coords.w / 2.0, FIRST_OPTION_Y + OPTION_SPACING * i as f32),
+            ));
+        }
+
+        Ok(VersusSetupState {
+            bots,
+            best_of,
+            spectators: false,
+            ruleset,
+            title_text,
+            controls_texts,
+            options,
+            current_selection: 0,
+
+            request_start: false,
+            request_host: false,
+            request_join: false,
+            request_watch: false,
+            request_back: false,
+            request_refresh: false,
+        })
+    }
+
+    fn player_option_text(index: usize, bot: std::option::Option<BotDifficulty>) -> String {
+        format!("Player {}: {}", index + 1, player_name(bot))
+    }
+
+    fn best_of_option_text(best_of: u32) -> String {
+        format!("Best of: {}", best_of)
+    }
+
+    fn spectators_option_text(spectators: bool) -> String {
+        format!("Spectators: {}", if spectators { "On" } else { "Off" })
+    }
+
+    /// Change the selected setting to its next value, or the match length to
+    /// the previous one if `direction` is negative.
+    fn change_setting(&mut self, direction: i32, assets: &Assets) {
+        match self.current_selection {
+            BEST_OF_OPTION => self.best_of = cycle(&BEST_OF, self.best_of, direction),
+            SPECTATORS_OPTION => self.spectators = !self.spectators,
+            player if player < PLAYERS => self.bots[player] = next_player(self.bots[player]),
+            _ => return,
+        }
+
+        play_click_sfx(assets).expect("Could not play click sfx in versus setup -> change");
+        self.request_refresh = true;
+    }
+
+    fn handle_input(&mut self, command: &OptionInputCommand, assets: &Assets) {
+        match command {
+            OptionInputCommand::Up => {
+                play_click_sfx(assets).expect("Could not play click sfx in versus setup -> up");
+                if self.current_selection == 0 {
+                    self.current_selection = self.options.len() - 1;
+                } else {
+                    self.current_selection -= 1;
+                }
+            }
+            OptionInputCommand::Down => {
+                play_click_sfx(assets).expect("Could not play click sfx in versus setup -> down");
+                if self.current_selection >= self.options.len() - 1 {
+                    self.current_selection = 0;
+                } else {
+                    self.current_selection += 1;
+                }
+            }
+            OptionInputCommand::Select => match self.current_selection {
+                START_OPTION => self.request_start = true,
+                HOST_OPTION => self.request_host = true,
+                JOIN_OPTION => self.request_join = true,
+                WATCH_OPTION => self.request_watch = true,
+                BACK_OPTION => self.request_back = true,
+                _ => self.change_setting(1, assets),
+            },
+        }
+    }
+}
+
+impl State for VersusSetupState {
+    fn update(
+        &mut self,
+        ctx: &mut Context,
+        assets: &Assets,
+        _: Duration,
+    ) -> GameResult<Transition> {
+        if self.request_back {
+            return Ok(Transition::Pop);
+        } else if self.request_start {
+            assets.get_music("menu")?.pause();
+            return Ok(Transition::Swap(Box::new(VersusState::new(
+                ctx,
+                assets,
+                self.best_of,
+                self.ruleset,
+                self.bots,
+                self.spectators,
+            )?)));
+        } else if self.request_host {
+            self.request_host = false;
+            let settings = MatchSettings {
+                best_of: self.best_of,
+                ruleset: self.ruleset,
+            };
+            return Ok(Transition::Push(Box::new(NetSetupState::host(
+                ctx,
+                assets,
+                settings,
+                self.bots[0],
+            )?)));
+        } else if self.request_join {
+            self.request_join = false;
+            return Ok(Transition::Push(Box::new(NetSetupState::join(
+                ctx,
+                assets,
+                self.bots[0],
+            )?)));
+        } else if self.request_watch {
+            self.request_watch = false;
+            return Ok(Transition::Push(Box::new(NetSetupState::watch(
+                ctx, assets,
+            )?)));
+        }
+
+        if self.request_refresh {
+            for (i, bot) in self.bots.iter().enumerate() {
+                let text = VersusSetupState::player_option_text(i, *bot);
+                self.options[i].set_text(ctx, assets, &text)?;
+            }
+            let text = VersusSetupState::best_of_option_text(self.best_of);
+            self.options[BEST_OF_OPTION].set_text(ctx, assets, &text)?;
+            let text = VersusSetupState::spectators_option_text(self.spectators);
+            self.options[SPECTATORS_OPTION].set_text(ctx, assets, &text)?;
+            self.request_refresh = false;
+        }
+
+        for (i, option) in self.options.iter_mut().enumerate() {
+            option.update(i == self.current_selection)?;
+        }
+
+        Ok(Transition::None)
+    }
+
+    fn draw(&mut self, ctx: &mut Context, _: &Assets) -> GameResult<()> {
+        let coords = graphics::get_screen_coordinates(ctx);
+        let title_dest = Point2::new(coords.w / 2.0 - (self.title_text.width() / 2) as f32, 100.0);
+
+        graphics::set_color(ctx, graphics::Color::new(0.0, 0.0, 0.0, 0.75))?;
+        graphics::rectangle(ctx, graphics::DrawMode::Fill, coords)?;
+        graphics::set_color(ctx, graphics::Color::new(1.0, 1.0, 1.0, 1.0))?;
+        graphics::draw(ctx, &self.title_text, title_dest, 0.0)?;
+
+        for (i, controls_text) in self.controls_texts.iter().enumerate() {
+            let controls_dest = Point2::new(
+                coords.w / 2.0 - (controls_text.width() / 2) as f32,
+                CONTROLS_Y + CONTROLS_SPACING * i as f32,
+            );
+            graphics::draw(ctx, controls_text, controls_dest, 0.0)?;
+        }
+
+        for option in &self.options {
+            option.draw(ctx)?;
+        }
+
+        Ok(())
+    }
+
+    fn key_down_event(
+        &mut self,
+        _ctx: &mut Context,
+        keycode: Keycode,
+        _keymod: Mod,
+        repeat: bool,
+        assets: &Assets,
+    ) {
+        if repeat {
+            return;
+        }
+
+        match keycode {
+            Keycode::Up => self.handle_input(&OptionInputCommand::Up, assets),
+            Keycode::Down => self.handle_input(&OptionInputCommand::Down, assets),
+            Keycode::Return => self.handle_input(&OptionInputCommand::Select, assets),
+            Keycode::Left => self.change_setting(-1, assets),
+            Keycode::Right => self.change_setting(1, assets),
+            Keycode::Escape => self.request_back = true,
+            _ => (),
+        }
+    }
+
+    fn controller_button_down_event(
+        &mut self,
+        _ctx: &mut Context,
+        btn: Button,
+        _instance_id: i32,
+        assets: &Assets,
+    ) {
+        match btn {
+            Button::DPadUp => self.handle_input(&OptionInputCommand::Up, assets),
+            Button::DPadDown => self.handle_input(&OptionInputCommand::Down, assets),
+            Button::A => self.handle_input(&OptionInputCommand::Select, assets),
+            Button::DPadLeft => self.change_setting(-1, assets),
+            Button::DPadRight => self.change_setting(1, assets),
+            Button::B => self.request_back = true,
+            _ => (),
+        }
+    }
+}
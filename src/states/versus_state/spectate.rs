@@ -0,0 +1,453 @@
+use super::protocol::{Connection, MatchSettings, Message, PROTOCOL_VERSION};
+use super::stream::MirrorBoard;
+use super::{board_coordinates, NAME_Y, PLAYERS};
+use crate::states::menu_state::MenuState;
+use crate::states::play_state::game_core::GameCore;
+use crate::states::play_state::game_mode::{GameMode, ModeSettings};
+use crate::states::play_state::handling::Handling;
+use crate::states::play_state::random_play_track;
+use crate::states::{Assets, State, Transition};
+use ggez::event::{Button, Keycode, Mod};
+use ggez::graphics::Point2;
+use ggez::{graphics, Context, GameResult};
+use std::io::{self, ErrorKind};
+use std::net::{Ipv4Addr, TcpListener};
+use std::time::Duration;
+
+/// The TCP port spectators connect to.
+pub const SPECTATOR_PORT: u16 = 7880;
+
+const STATUS_Y: f32 = 660.0;
+
+/// Lets spectators watch a match played on this machine. Each spectator is
+/// sent the settings, players and seed of the round, and then every lock,
+/// attack and top out on both boards. Spectators who join mid-round are
+/// caught up with everything sent since the round started. Spectators cannot
+/// send anything that changes the match.
+pub struct SpectatorServer {
+    listener: TcpListener,
+    spectators: Vec<Connection>,
+    // every message sent since the round started
+    round: Vec<Message>,
+}
+
+impl SpectatorServer {
+    /// Takes spectators on `port` of every network interface.
+    pub fn bind(port: u16) -> io::Result<Self> {
+        let listener = TcpListener::bind((Ipv4Addr::UNSPECIFIED, port))?;
+        listener.set_nonblocking(true)?;
+        Ok(SpectatorServer {
+            listener,
+            spectators: Vec::new(),
+            round: Vec::new(),
+        })
+    }
+
+    /// Starts a new round with both boards dealt from `seed`. `players` holds
+    /// the name of the player on each board and the rounds they have won.
+    pub fn start_round(&mut self, settings: MatchSettings, players: &[(String, u32)], seed: u64) {
+        self.round.clear();
+        self.broadcast(Message::Start(settings));
+        for (board, (name, wins)) in players.iter().enumerate() {
+            self.broadcast(Message::Player {
+                board,
+                wins: *wins,
+                name: name.clone(),
+            });
+        }
+        self.broadcast(Message::Round(seed));
+    }
+
+    /// Sends spectators each message about a board, as made by a
+    /// `LockStream`.
+    pub fn send_boards(&mut self, messages: &[(usize, Message)]) {
+        for (board, message) in messages {
+            self.broadcast(Message::Board(*board, Box::new(message.clone())));
+        }
+    }
+
+    /// Takes in new spectators and catches them up on the round, and lets go
+    /// of spectators who have left.
+    pub fn update(&mut self) {
+        while let Ok((stream, _)) = self.listener.accept() {
+            if let Ok(mut spectator) = Connection::new(stream) {
+                let _ = spectator.send(&Message::Hello(PROTOCOL_VERSION));
+                for message in &self.round {
+                    let _ = spectator.send(message);
+                }
+                self.spectators.push(spectator);
+            }
+        }
+
+        self.spectators = self
+            .spectators
+            .drain(..)
+            .filter_map(|mut spectator| {
+                if SpectatorServer::is_open(&mut spectator) {
+                    Some(spectator)
+                } else {
+                    None
+                }
+            })
+            .collect();
+    }
+
+    /// Receives and throws away everything `spectator` has sent, which also
+    /// sends on anything still waiting to go out. Returns false once the
+    /// spectator has gone.
+    fn is_open(spectator: &mut Connection) -> bool {
+        loop {
+            match spectator.receive() {
+                Ok(Some(_)) => (),
+                Ok(None) => return true,
+                Err(ref e) if e.kind() == ErrorKind::InvalidData => (),
+                Err(_) => return false,
+            }
+        }
+    }
+
+    fn broadcast(&mut self, message: Message) {
+        for spectator in &mut self.spectators {
+            let _ = spectator.send(&message);
+        }
+        self.round.push(message);
+    }
+}
+
+impl Drop for SpectatorServer {
+    fn drop(&mut self) {
+        for spectator in &mut self.spectators {
+            let _ = spectator.send(&Message::Bye);
+        }
+    }
+}
+
+/// A match as followed by a spectator, from the messages sent by a
+/// `SpectatorServer`.
+#[derive(Default)]
+pub struct Spectator {
+    settings: Option<MatchSettings>,
+    // the name of the player on each board, and the rounds they have won
+    players: Vec<(String, u32)>,
+    boards: Vec<MirrorBoard>,
+    // rows in each attack sent by each board this round
+    attacks: Vec<Vec<u32>>,
+}
+
+impl Spectator {
+    /// Follows `message`. Returns true if a player's name or wins changed.
+    pub fn handle(&mut self, message: Message) -> bool {
+        match message {
+            Message::Start(settings) => self.settings = Some(settings),
+            Message::Player { board, wins, name } if board < PLAYERS => {
+                self.players.resize(PLAYERS, (String::new(), 0));
+                self.players[board] = (name, wins);
+                return true;
+            }
+            Message::Round(seed) => {
+                if let Some(settings) = self.settings {
+                    self.boards = (0..PLAYERS)
+                        .map(|_| {
+                            MirrorBoard::new(GameCore::new(
+                                GameMode::Versus,
+                                ModeSettings::default(),
+                                settings.ruleset,
+                                Handling::default(),
+                                seed,
+                            ))
+                        })
+                        .collect();
+                    self.attacks = vec![Vec::new(); PLAYERS];
+                }
+            }
+            Message::Board(board, message) if board < self.boards.len() => match *message {
+                Message::Lock {
+                    piece,
+                    garbage_taken,
+                } => {
+                    let opponent = &self.attacks[(board + 1) % PLAYERS];
+                    self.boards[board].lock(&piece, garbage_taken, opponent);
+                }
+                Message::Garbage(rows) => self.attacks[board].push(rows),
+                Message::GameOver => self.boards[board].concede(),
+                _ => (),
+            },
+            _ => (),
+        }
+        false
+    }
+
+    /// Returns the board of `player`, once a round has started.
+    pub fn board(&self, player: usize) -> std::option::Option<&GameCore> {
+        self.boards.get(player).map(MirrorBoard::core)
+    }
+
+    /// Returns the text shown above the board of `player`.
+    pub fn header(&self, player: usize) -> String {
+        match (self.players.get(player), self.settings) {
+            (Some((name, wins)), Some(settings)) => {
+                format!("{}   Wins: {}/{}", name, wins, settings.best_of / 2 + 1)
+            }
+            (Some((name, _)), None) => name.clone(),
+            _ => format!("Player {}", player + 1),
+        }
+    }
+
+    /// Returns true once either player has topped out.
+    pub fn is_round_over(&self) -> bool {
+        self.boards.iter().any(|board| board.core().is_game_over())
+    }
+}
+
+/// Watches a match played on another machine, as sent by its
+/// `SpectatorServer`. Each board is drawn as its well and the piece about to
+/// fall, under the name of its player. Nothing can be done but leave.
+pub struct SpectateState {
+    connection: Connection,
+    spectator: Spectator,
+    hello_received: bool,
+    // set once the match can no longer be followed
+    ended: bool,
+    current_track_name: String,
+
+    name_texts: Vec<graphics::Text>,
+    status_text: graphics::Text,
+
+    request_menu: bool,
+}
+
+impl SpectateState {
+    /// Creates a state that watches the match sent over `connection`.
+    pub fn new(ctx: &mut Context, assets: &Assets, connection: Connection) -> GameResult<Self> {
+        let mut state = SpectateState {
+            connection,
+            spectator: Spectator::default(),
+            hello_received: false,
+            ended: false,
+            current_track_name: random_play_track(assets),
+
+            name_texts: Vec::new(),
+            status_text: graphics::Text::new(ctx, " ", assets.get_font("normal")?)?,
+
+            request_menu: false,
+        };
+        state.build_name_texts(ctx, assets)?;
+        state.set_status(ctx, assets, "Waiting for the match...")?;
+        Ok(state)
+    }
+
+    fn build_name_texts(&mut self, ctx: &mut Context, assets: &Assets) -> GameResult<()> {
+        self.name_texts.clear();
+        for player in 0..PLAYERS {
+            let header = self.spectator.header(player);
+            self.name_texts.push(graphics::Text::new(
+                ctx,
+                &header,
+                assets.get_font("normal")?,
+            )?);
+        }
+        Ok(())
+    }
+
+    fn set_status(&mut self, ctx: &mut Context, assets: &Assets, status: &str) -> GameResult<()> {
+        self.status_text = graphics::Text::new(ctx, status, assets.get_font("normal")?)?;
+        Ok(())
+    }
+
+    /// Stops following the match, showing `reason`.
+    fn end(&mut self, ctx: &mut Context, assets: &Assets, reason: &str) -> GameResult<()> {
+        self.ended = true;
+        let status = format!("{}. Press Escape to go back", reason);
+        self.set_status(ctx, assets, &status)
+    }
+
+    /// Follows every message sent since the last update.
+    fn receive_messages(&mut self, ctx: &mut Context, assets: &Assets) -> GameResult<()> {
+        let was_round_over = self.spectator.is_round_over();
+        let mut players_changed = false;
+        loop {
+            let message = match self.connection.receive() {
+                Ok(Some(message)) => message,
+                Ok(None) => break,
+                Err(_) => Message::Bye,
+            };
+            match message {
+                Message::Hello(version) if version != PROTOCOL_VERSION => {
+                    return self.end(ctx, assets, "The match is on a different version");
+                }
+                Message::Hello(_) => self.hello_received = true,
+                Message::Bye => return self.end(ctx, assets, "The match has ended"),
+                Message::Round(_) => {
+                    players_changed |= self.spectator.handle(message);
+                    self.set_status(ctx, assets, "Spectating")?;
+                }
+                message if self.hello_received => {
+                    players_changed |= self.spectator.handle(message);
+                }
+                _ => (),
+            }
+        }
+
+        if players_changed {
+            self.build_name_texts(ctx, assets)?;
+        }
+        if !was_round_over && self.spectator.is_round_over() {
+            self.set_status(ctx, assets, "Round over, waiting for the next round...")?;
+        }
+        Ok(())
+    }
+}
+
+impl State for SpectateState {
+    fn update(
+        &mut self,
+        ctx: &mut Context,
+        assets: &Assets,
+        _: Duration,
+    ) -> GameResult<Transition> {
+        // currently necessary to keep audio looping
+        let current_song = assets.get_music(&self.current_track_name)?;
+        if current_song.paused() {
+            current_song.resume();
+        } else {
+            current_song.play()?;
+        }
+
+        if self.request_menu {
+            assets.get_music(&self.current_track_name)?.pause();
+            return Ok(Transition::Swap(Box::new(MenuState::new(ctx, assets)?)));
+        }
+
+        if !self.ended {
+            self.receive_messages(ctx, assets)?;
+        }
+
+        Ok(Transition::None)
+    }
+
+    fn draw(&mut self, ctx: &mut Context, assets: &Assets) -> GameResult<()> {
+        let coords = graphics::get_screen_coordinates(ctx);
+        graphics::draw(ctx, assets.get_image("game_bg")?, Point2::origin(), 0.0)?;
+
+        for player in 0..PLAYERS {
+            if let Some(core) = self.spectator.board(player) {
+                graphics::set_screen_coordinates(ctx, board_coordinates(coords, player))?;
+                core.well().draw(ctx, assets.get_image("block")?)?;
+                if !core.is_game_over() {
+                    core.current_piece().draw(ctx, assets.get_image("block")?)?;
+                }
+            }
+        }
+        graphics::set_screen_coordinates(ctx, coords)?;
+
+        graphics::set_color(ctx, graphics::Color::new(1.0, 1.0, 1.0, 1.0))?;
+        let half_width = coords.w / PLAYERS as f32;
+        for (i, name_text) in self.name_texts.iter().enumerate() {
+            let centre_x = half_width * (i as f32 + 0.5);
+            let name_dest = Point2::new(centre_x - (name_text.width() / 2) as f32, NAME_Y);
+            graphics::draw(ctx, name_text, name_dest, 0.0)?;
+        }
+        let status_dest = Point2::new(
+            coords.w / 2.0 - (self.status_text.width() / 2) as f32,
+            STATUS_Y,
+        );
+        graphics::draw(ctx, &self.status_text, status_dest, 0.0)?;
+
+        Ok(())
+    }
+
+    fn key_down_event(
+        &mut self,
+        _ctx: &mut Context,
+        keycode: Keycode,
+        _keymod: Mod,
+        _repeat: bool,
+        _assets: &Assets,
+    ) {
+        if keycode == Keycode::Escape {
+            self.request_menu = true;
+        }
+    }
+
+    fn controller_button_down_event(
+        &mut self,
+        _ctx: &mut Context,
+        btn: Button,
+        _instance_id: i32,
+        _assets: &Assets,
+    ) {
+        if btn == Button::B || btn == Button::Start {
+            self.request_menu = true;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::protocol::{Connection, MatchSettings, Message};
+    use super::super::stream::LockStream;
+    use super::super::test_support::BotMatch;
+    use super::super::PLAYERS;
+    use super::{Spectator, SpectatorServer};
+    use crate::states::play_state::ruleset::Ruleset;
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn spectator_joining_mid_round_follows_both_boards() {
+        let mut server = SpectatorServer::bind(0).unwrap();
+        let port = server.listener.local_addr().unwrap().port();
+        let settings = MatchSettings {
+            best_of: 3,
+            ruleset: Ruleset::default(),
+        };
+        let players = [("Host".to_string(), 1), ("Guest".to_string(), 0)];
+        server.start_round(settings, &players, 11);
+
+        let mut bot_match = BotMatch::new(settings.ruleset, 11);
+        let mut stream = LockStream::default();
+        let mut client = None;
+        let mut spectator = Spectator::default();
+
+        for (frame, events) in bot_match.by_ref().take(2000).enumerate() {
+            // the spectator turns up part way through the round
+            if frame == 1000 {
+                client = Some(Connection::connect("127.0.0.1", port).unwrap());
+            }
+            server.update();
+
+            server.send_boards(&stream.messages(&events));
+
+            if let Some(client) = &mut client {
+                while let Some(message) = client.receive().unwrap() {
+                    spectator.handle(message);
+                }
+            }
+        }
+
+        // the spectator hears everything up to the server going away
+        drop(server);
+        let mut client = client.unwrap();
+        let started = Instant::now();
+        loop {
+            assert!(started.elapsed() < Duration::from_secs(5));
+            match client.receive() {
+                Ok(Some(Message::Bye)) => break,
+                Ok(Some(message)) => {
+                    spectator.handle(message);
+                }
+                Ok(None) => std::thread::sleep(Duration::from_millis(1)),
+                Err(e) => panic!("{}", e),
+            }
+        }
+
+        assert_eq!("Host   Wins: 1/2", spectator.header(0));
+        assert_eq!("Guest   Wins: 0/2", spectator.header(1));
+        let game = bot_match.game();
+        for i in 0..PLAYERS {
+            let board = spectator.board(i).unwrap();
+            assert!(board.pieces_locked() > 20);
+            assert_eq!(game.core(i).well(), board.well());
+            assert_eq!(game.core(i).is_game_over(), board.is_game_over());
+        }
+    }
+}
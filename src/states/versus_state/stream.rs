@@ -0,0 +1,136 @@
+use super::protocol::Message;
+use super::rollback::ConfirmedEvent;
+use super::PLAYERS;
+use crate::states::play_state::game_core::{GameCore, GameEvent, LockedPiece};
+
+/// Turns what happens on the boards of a versus match into the stream of
+/// messages that lets another machine follow each board: every piece that
+/// locks, every attack sent and every top out.
+#[derive(Default)]
+pub struct LockStream {
+    // the attacks that have reached each board
+    attacks_received: [u32; PLAYERS],
+    // the attacks sent on the frame being streamed, which only reach the
+    // other board on the next frame
+    arriving: [u32; PLAYERS],
+    frame: Option<u32>,
+}
+
+impl LockStream {
+    /// Returns the message for each of `events`, in order, along with the
+    /// board it is about. Events must be given in the order they happened,
+    /// and every event of a frame must be given in the same call.
+    pub fn messages(&mut self, events: &[ConfirmedEvent]) -> Vec<(usize, Message)> {
+        let mut messages = Vec::new();
+        for confirmed in events {
+            if self.frame != Some(confirmed.frame) {
+                for (received, arriving) in self.attacks_received.iter_mut().zip(&mut self.arriving)
+                {
+                    *received += *arriving;
+                    *arriving = 0;
+                }
+                self.frame = Some(confirmed.frame);
+            }
+
+            let board = confirmed.player;
+            let message = match (confirmed.event, confirmed.lock) {
+                (GameEvent::PieceLocked, Some(piece)) => Message::Lock {
+                    piece,
+                    garbage_taken: self.attacks_received[board],
+                },
+                (GameEvent::GarbageSent(rows), _) => {
+                    self.arriving[(board + 1) % PLAYERS] += 1;
+                    Message::Garbage(rows)
+                }
+                (GameEvent::GameOver, _) => Message::GameOver,
+                _ => continue,
+            };
+            messages.push((board, message));
+        }
+        messages
+    }
+}
+
+/// A board played from the stream of messages about it, dealt the same pieces
+/// as the board it follows.
+pub struct MirrorBoard {
+    core: GameCore,
+    // how many of the opponent's attacks the board has taken in
+    attacks_taken: usize,
+}
+
+impl MirrorBoard {
+    /// Creates a board that follows a board starting out as `core`.
+    pub fn new(core: GameCore) -> Self {
+        MirrorBoard {
+            core,
+            attacks_taken: 0,
+        }
+    }
+
+    pub fn core(&self) -> &GameCore {
+        &self.core
+    }
+
+    /// Locks `piece`, first taking in the attacks the board had taken in when
+    /// it locked. `attacks` holds the rows in each attack sent by the
+    /// opponent so far.
+    pub fn lock(&mut self, piece: &LockedPiece, garbage_taken: u32, attacks: &[u32]) {
+        let taken = (garbage_taken as usize).min(attacks.len());
+        for rows in &attacks[self.attacks_taken.min(taken)..taken] {
+            self.core.receive_garbage(*rows);
+        }
+        self.attacks_taken = self.attacks_taken.max(taken);
+        self.core.place_piece(piece);
+    }
+
+    /// Ends the game on the board, as the board it follows topped out.
+    pub fn concede(&mut self) {
+        self.core.concede();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::protocol::Message;
+    use super::super::test_support::BotMatch;
+    use super::super::PLAYERS;
+    use super::{LockStream, MirrorBoard};
+    use crate::states::play_state::ruleset::Ruleset;
+
+    #[test]
+    fn mirrors_follow_both_boards_of_a_bot_match() {
+        let mut bot_match = BotMatch::new(Ruleset::default(), 3);
+        let mut stream = LockStream::default();
+        let mut mirrors: Vec<MirrorBoard> = (0..PLAYERS)
+            .map(|i| MirrorBoard::new(bot_match.game().core(i).clone()))
+            .collect();
+        let mut attacks = vec![Vec::new(); PLAYERS];
+
+        for events in bot_match.by_ref().take(3000) {
+            // every message crosses the wire as text
+            for (board, message) in stream.messages(&events) {
+                match Message::decode(&message.encode()).unwrap() {
+                    Message::Lock {
+                        piece,
+                        garbage_taken,
+                    } => {
+                        let opponent = &attacks[(board + 1) % PLAYERS];
+                        mirrors[board].lock(&piece, garbage_taken, opponent);
+                    }
+                    Message::Garbage(rows) => attacks[board].push(rows),
+                    Message::GameOver => mirrors[board].concede(),
+                    _ => unreachable!(),
+                }
+            }
+        }
+
+        assert!(attacks.iter().all(|sent| !sent.is_empty()));
+        let game = bot_match.game();
+        for (i, mirror) in mirrors.iter().enumerate() {
+            assert!(mirror.core().pieces_locked() > 20);
+            assert_eq!(game.core(i).well(), mirror.core().well());
+            assert_eq!(game.core(i).is_game_over(), mirror.core().is_game_over());
+        }
+    }
+}
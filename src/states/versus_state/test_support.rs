@@ -0,0 +1,65 @@
+use super::rollback::{ConfirmedEvent, InputFrame, VersusGame};
+use super::PLAYERS;
+use crate::states::play_state::bot::{BotDifficulty, BotPlayer};
+use crate::states::play_state::game_core::GameEvent;
+use crate::states::play_state::handling::Handling;
+use crate::states::play_state::ruleset::Ruleset;
+
+/// A match between two bots, played one frame for each call to `next`, which
+/// returns everything that happened on that frame. Every frame is confirmed,
+/// as no inputs have to cross a network.
+pub struct BotMatch {
+    game: VersusGame,
+    bots: Vec<BotPlayer>,
+    held: [InputFrame; PLAYERS],
+    frame: u32,
+}
+
+impl BotMatch {
+    /// Starts a match between two hard bots, played with `ruleset` and dealt
+    /// from `seed`.
+    pub fn new(ruleset: Ruleset, seed: u64) -> Self {
+        BotMatch {
+            game: VersusGame::new(ruleset, [Handling::default(); PLAYERS], seed),
+            bots: (0..PLAYERS)
+                .map(|i| BotPlayer::heuristic(BotDifficulty::Hard, i as u64))
+                .collect(),
+            held: [InputFrame::default(); PLAYERS],
+            frame: 0,
+        }
+    }
+
+    /// Returns the game the bots are playing.
+    pub fn game(&self) -> &VersusGame {
+        &self.game
+    }
+}
+
+impl Iterator for BotMatch {
+    type Item = Vec<ConfirmedEvent>;
+
+    fn next(&mut self) -> Option<Vec<ConfirmedEvent>> {
+        for (i, bot) in self.bots.iter_mut().enumerate() {
+            for (action, pressed) in bot.update(self.game.core(i)) {
+                self.held[i].set(action, pressed);
+            }
+        }
+        let mut events = Vec::new();
+        for (player, player_events) in self.game.step(self.held).into_iter().enumerate() {
+            for event in player_events {
+                let lock = match event {
+                    GameEvent::PieceLocked => self.game.core(player).last_lock(),
+                    _ => None,
+                };
+                events.push(ConfirmedEvent {
+                    frame: self.frame,
+                    player,
+                    event,
+                    lock,
+                });
+            }
+        }
+        self.frame += 1;
+        Some(events)
+    }
+}
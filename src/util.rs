@@ -31,6 +31,29 @@ mod tests {
 use crate::states::Assets;
 use ggez::GameResult;
 
+/// Formats a time in seconds as minutes, seconds and milliseconds, e.g.
+/// `1:05.320`.
+pub fn format_time(seconds: f64) -> String {
+    let millis = (seconds * 1000.0).round() as u64;
+    format!(
+        "{}:{:02}.{:03}",
+        millis / 60_000,
+        millis / 1000 % 60,
+        millis % 1000
+    )
+}
+
+/// Returns the value after `current` in `values`, or the one before it if
+/// `direction` is negative, wrapping around at either end.
+pub fn cycle<T: PartialOrd + Copy>(values: &[T], current: T, direction: i32) -> T {
+    let index = values
+        .iter()
+        .position(|value| *value >= current)
+        .unwrap_or(values.len() - 1) as i32;
+    let next = (index + direction).rem_euclid(values.len() as i32);
+    values[next as usize]
+}
+
 /// Play the 'click' sound effect. This is a general utility method as it is
 /// used in a couple of different game states.
 pub fn play_click_sfx(assets: &Assets) -> GameResult<()> {